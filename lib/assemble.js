@@ -398,7 +398,7 @@ const sub_operators = {
         "not", "and", "or", "xor", "add", "sub", "mul", "div", "lsl", "lsr",
         "asr", "rol", "ror"
     ],
-    cmp: ["eq", "ge", "gt", "lt", "le", "ne"],
+    cmp: ["eq", "ge", "gt", "lt", "le", "ne", "equal"],
     dict: ["has", "get", "add", "set", "del"],
     deque: ["new", "empty", "push", "pop", "put", "pull", "len"],
     actor: ["send", "post", "create", "become", "self"],
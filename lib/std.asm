@@ -38,6 +38,8 @@ E_STOP:                     ; actor stopped
     ref -15
 E_ABORT:                    ; actor transaction aborted
     ref -16
+E_TIME_LIM:                 ; Sponsor time limit reached
+    ref -17
 
 ; Common Tail-Sequences
 
@@ -137,6 +139,7 @@ return_one:                 ; k
     E_MSG_LIM
     E_ASSERT
     E_STOP
+    E_TIME_LIM
     cust_send
     send_msg
     sink_beh
@@ -82,9 +82,9 @@ const TIMER_DEV_OFS = 4;
 const IO_DEV_OFS = 5;
 const BLOB_DEV_OFS = 6;
 const RANDOM_DEV_OFS = 7;
-const HOST_DEV_OFS = 13;
-const QUOTA_OFS = 14;
-const SPONSOR_OFS = 15;
+const HOST_DEV_OFS = 8;
+const QUOTA_OFS = 9;
+const SPONSOR_OFS = 10;
 
 // Error codes (from rs/src/lib.rs)
 
@@ -105,6 +105,7 @@ const E_MSG_LIM = -13;
 const E_ASSERT = -14;
 const E_STOP = -15;
 const E_ABORT = -16;
+const E_TIME_LIM = -17;
 
 // Log levels
 
@@ -151,7 +152,8 @@ const error_messages = [
     "sponsor event limit reached",      // E_MSG_LIM = -13
     "assertion failed",                 // E_ASSERT = -14
     "actor transaction stopped",        // E_STOP = -15
-    "actor transaction aborted"         // E_ABORT = -16
+    "actor transaction aborted",        // E_ABORT = -16
+    "sponsor time limit reached"        // E_TIME_LIM = -17
 ];
 const gc_labels = [
     "free",
@@ -222,7 +224,8 @@ imm_labels[VM_CMP] = Object.freeze([
     "gt",
     "lt",
     "le",
-    "ne"
+    "ne",
+    "equal"
 ]);
 imm_labels[VM_ACTOR] = Object.freeze([
     "send",
@@ -660,6 +663,7 @@ export default Object.freeze({
     E_ASSERT,
     E_STOP,
     E_ABORT,
+    E_TIME_LIM,
     LOG_NONE,
     LOG_INFO,
     LOG_WARN,
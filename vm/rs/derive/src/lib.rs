@@ -0,0 +1,197 @@
+// Derive macros for `ufork::convert::{ToValue, FromValue}`.
+//
+// A struct is encoded like a tuple of its fields, as a list with a dotted tail
+// (`a,b,c`). A struct with a single field is encoded as that field's value,
+// and a struct without fields is encoded as `#nil`.
+//
+// An enum variant is encoded as its fixnum index, followed by its fields
+// (`index,a,b,c`). A variant without fields is encoded as just the index.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident,
+};
+
+#[proc_macro_derive(ToValue)]
+pub fn derive_to_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::ufork::convert::ToValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, encode) = encode_fields(&data.fields);
+            quote! {
+                let #name #pattern = self;
+                Ok(#encode)
+            }
+        },
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+                let ident = &variant.ident;
+                let index = index as isize;
+                let (pattern, encode) = encode_fields(&variant.fields);
+                if variant.fields.is_empty() {
+                    quote! {
+                        #name::#ident #pattern => Ok(::ufork::any::Any::fix(#index)),
+                    }
+                } else {
+                    quote! {
+                        #name::#ident #pattern => {
+                            let fields = #encode;
+                            heap.cons(::ufork::any::Any::fix(#index), fields)
+                        },
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        },
+        Data::Union(_) => {
+            return error(name, "unions can not be converted to uFork values");
+        },
+    };
+    quote! {
+        impl #impl_generics ::ufork::convert::ToValue for #name #ty_generics #where_clause {
+            fn to_value(&self, heap: &mut ::ufork::convert::Heap) -> ::core::result::Result<::ufork::any::Any, ::ufork::Error> {
+                #body
+            }
+        }
+    }.into()
+}
+
+#[proc_macro_derive(FromValue)]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::ufork::convert::FromValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => decode_fields(quote!(#name), &data.fields),
+        Data::Enum(data) => {
+            let units = data.variants.iter().enumerate()
+                .filter(|(_, variant)| variant.fields.is_empty())
+                .map(|(index, variant)| {
+                    let ident = &variant.ident;
+                    let index = index as isize;
+                    quote! { #index => Ok(#name::#ident), }
+                });
+            let others = data.variants.iter().enumerate()
+                .filter(|(_, variant)| !variant.fields.is_empty())
+                .map(|(index, variant)| {
+                    let ident = &variant.ident;
+                    let index = index as isize;
+                    let decode = decode_fields(quote!(#name::#ident), &variant.fields);
+                    quote! { #index => { #decode }, }
+                });
+            quote! {
+                if let ::core::option::Option::Some(index) = value.fix_num() {
+                    return match index {
+                        #(#units)*
+                        _ => Err(::ufork::E_BOUNDS),
+                    };
+                }
+                let (index, value) = heap.uncons(value)?;
+                match index.get_fix()? {
+                    #(#others)*
+                    _ => Err(::ufork::E_BOUNDS),
+                }
+            }
+        },
+        Data::Union(_) => {
+            return error(name, "unions can not be converted from uFork values");
+        },
+    };
+    quote! {
+        impl #impl_generics ::ufork::convert::FromValue for #name #ty_generics #where_clause {
+            fn from_value(heap: &::ufork::convert::Heap, value: ::ufork::any::Any) -> ::core::result::Result<Self, ::ufork::Error> {
+                #body
+            }
+        }
+    }.into()
+}
+
+fn add_bounds(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn error(name: &Ident, message: &str) -> TokenStream {
+    syn::Error::new(name.span(), message).to_compile_error().into()
+}
+
+fn field_names(fields: &Fields) -> Vec<Ident> {
+    (0..fields.len())
+        .map(|n| format_ident!("field_{}", n, span = Span::call_site()))
+        .collect()
+}
+
+// a pattern binding each field by reference, and a block encoding them
+fn encode_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let names = field_names(fields);
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote! { { #(#idents: #names),* } }
+        },
+        Fields::Unnamed(_) => quote! { ( #(#names),* ) },
+        Fields::Unit => quote! {},
+    };
+    let encode = match names.split_last() {
+        None => quote! { ::ufork::any::NIL },
+        Some((last, [])) => quote! {
+            ::ufork::convert::ToValue::to_value(#last, heap)?
+        },
+        Some((last, init)) => {
+            let init = init.iter().rev();
+            quote! {{
+                let mut list = ::ufork::convert::ToValue::to_value(#last, heap)?;
+                #(
+                    let item = ::ufork::convert::ToValue::to_value(#init, heap)?;
+                    list = heap.cons(item, list)?;
+                )*
+                list
+            }}
+        },
+    };
+    (pattern, encode)
+}
+
+// an expression decoding `value` into the fields of `path`
+fn decode_fields(path: TokenStream2, fields: &Fields) -> TokenStream2 {
+    let names = field_names(fields);
+    let decode = match names.split_last() {
+        None => quote! {
+            if value != ::ufork::any::NIL {
+                return Err(::ufork::E_NO_TYPE);
+            }
+        },
+        Some((last, init)) => quote! {
+            let rest = value;
+            #(
+                let (item, rest) = heap.uncons(rest)?;
+                let #init = ::ufork::convert::FromValue::from_value(heap, item)?;
+            )*
+            let #last = ::ufork::convert::FromValue::from_value(heap, rest)?;
+        },
+    };
+    let construct = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote! { #path { #(#idents: #names),* } }
+        },
+        Fields::Unnamed(_) => quote! { #path ( #(#names),* ) },
+        Fields::Unit => quote! { #path },
+    };
+    quote! {
+        #decode
+        Ok(#construct)
+    }
+}
@@ -114,6 +114,7 @@ pub const CMP_GT: Any       = PLUS_2;
 pub const CMP_LT: Any       = PLUS_3;
 pub const CMP_LE: Any       = PLUS_4;
 pub const CMP_NE: Any       = PLUS_5;
+pub const CMP_EQUAL: Any    = PLUS_6;  // structural equality
 
 // VM_ACTOR actor operations
 pub const ACTOR_SEND: Any   = ZERO;
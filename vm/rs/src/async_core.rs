@@ -0,0 +1,256 @@
+// An AsyncCore drives a Core from an async executor, running in slices
+// until idle, then parking until a `CoreHandle` posts more work.
+//
+// While waiting on devices, the Core also parks, once each pending device
+// has registered the waker (see `Device::register_waker`). Devices may
+// instead deliver results through a `CoreHandle`, which wakes the Core too.
+// A pending device that can't register a waker keeps the Core runnable,
+// so it yields to the executor between polls.
+
+use ::core::future::{poll_fn, Future};
+use ::core::task::{Context, Poll};
+
+use crate::*;
+use crate::core_handle::CoreHandle;
+
+// run-loop steps taken before yielding to other tasks
+pub const SLICE_STEPS: i32 = 256;
+
+enum Drive {
+    Pending,  // more work is expected, and a wake-up is arranged
+    Idle,  // no more work, until a handle posts some
+    Interrupted,
+}
+
+pub struct AsyncCore {
+    core: Core,
+}
+
+impl AsyncCore {
+    pub fn new(core: Core) -> AsyncCore {
+        AsyncCore { core }
+    }
+    pub fn core(&self) -> &Core {
+        &self.core
+    }
+    pub fn core_mut(&mut self) -> &mut Core {
+        &mut self.core
+    }
+    pub fn into_inner(self) -> Core {
+        self.core
+    }
+    pub fn handle(&mut self) -> CoreHandle {
+        // posting work through this handle wakes the Core
+        self.core.handle()
+    }
+    pub fn call(&mut self, target: Any, message: Any, sponsor: Any) -> impl Future<Output = Result<Any, Error>> + '_ {
+        // like `Core::call`, resolving to the reply.
+        // fails with the root sponsor's error signal, if one is raised,
+        // or with `E_STOP` if the run-loop is interrupted.
+        let reply = self.core.call(target, message, sponsor);
+        poll_fn(move |cx| {
+            let reply = reply.as_ref().map_err(|&error| error)?;
+            if let Some(msg) = self.core.poll_reply(reply)? {
+                return Poll::Ready(Ok(msg));
+            }
+            let drive = self.drive(cx)?;
+            if let Some(msg) = self.core.poll_reply(reply)? {
+                return Poll::Ready(Ok(msg));
+            }
+            match drive {
+                Drive::Pending | Drive::Idle => Poll::Pending,
+                Drive::Interrupted => Poll::Ready(Err(E_STOP)),
+            }
+        })
+    }
+    pub fn serve(&mut self) -> impl Future<Output = Result<(), Error>> + '_ {
+        // run indefinitely, parking whenever idle.
+        // resolves when the run-loop is interrupted,
+        // or fails with the root sponsor's error signal.
+        poll_fn(move |cx| match self.drive(cx)? {
+            Drive::Pending | Drive::Idle => Poll::Pending,
+            Drive::Interrupted => Poll::Ready(Ok(())),
+        })
+    }
+    pub fn run_until_idle(&mut self) -> impl Future<Output = Result<(), Error>> + '_ {
+        // run until there is no more work (without parking)
+        poll_fn(move |cx| match self.drive(cx)? {
+            Drive::Pending => Poll::Pending,
+            Drive::Idle => Poll::Ready(Ok(())),
+            Drive::Interrupted => Poll::Ready(Err(E_STOP)),
+        })
+    }
+    fn drive(&mut self, cx: &mut Context<'_>) -> Result<Drive, Error> {
+        // run one slice, arranging to be woken if work remains
+        self.core.poll_devices()?;
+        let report = self.core.run(SLICE_STEPS);
+        match report.outcome {
+            RunOutcome::Idle => {
+                if self.core.register_waker(cx.waker()) {
+                    cx.waker().wake_by_ref();  // posted since the last step
+                    return Ok(Drive::Pending);
+                }
+                Ok(Drive::Idle)
+            },
+            RunOutcome::StepLimit => {
+                cx.waker().wake_by_ref();  // yield, then continue
+                Ok(Drive::Pending)
+            },
+            RunOutcome::WaitingOnDevices => {
+                let posted = self.core.register_waker(cx.waker());
+                if posted || !self.core.register_device_wakers(cx.waker()) {
+                    cx.waker().wake_by_ref();  // can't park, so poll again
+                }
+                Ok(Drive::Pending)
+            },
+            RunOutcome::Interrupted => Ok(Drive::Interrupted),
+            RunOutcome::RootQuotaExhausted { kind } => Err(match kind {
+                QuotaKind::Memory => E_MEM_LIM,
+                QuotaKind::Events => E_MSG_LIM,
+                QuotaKind::Cycles => E_CPU_LIM,
+                QuotaKind::Time => E_TIME_LIM,
+            }),
+            RunOutcome::Fatal { error } => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use alloc::sync::Arc;
+
+    use ::core::cell::Cell;
+    use ::core::pin::pin;
+    use ::core::task::Waker;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+    use std::time::Duration;
+
+    use super::*;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    // replies `n+1` to `cust,n`, later, from a worker thread
+    struct Worker {
+        handle: CoreHandle,
+    }
+
+    impl Device for Worker {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let myself = core.event_target(ep);
+            let msg = core.event_message(ep);
+            let cust = core.car(msg);
+            let n = core.cdr(msg).get_fix()?;
+            let stub = core.reserve_stub(myself, cust)?;  // keep `cust` while we work
+            let handle = self.handle.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                handle.post_event(SPONSOR, cust, Any::fix(n + 1));
+                handle.post_release(stub);
+            });
+            Ok(UNDEF)
+        }
+    }
+
+    // replies `n+1` to `cust,n`, once polled after its timer fires
+    struct Timer {
+        waiting: Option<(Any, Any)>,  // stub holding `cust`, reply
+        fired: Arc<AtomicBool>,
+        polls: Rc<Cell<usize>>,
+    }
+
+    impl Device for Timer {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let myself = core.event_target(ep);
+            let msg = core.event_message(ep);
+            let n = core.cdr(msg).get_fix()?;
+            let stub = core.reserve_stub(myself, core.car(msg))?;
+            self.waiting = Some((stub, Any::fix(n + 1)));
+            Ok(UNDEF)
+        }
+        fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+            self.polls.set(self.polls.get() + 1);
+            if self.fired.load(Ordering::Acquire) {
+                if let Some((stub, reply)) = self.waiting.take() {
+                    let cust = core.ram(stub).y();
+                    core.release_stub(stub);
+                    let evt = core.reserve_event(SPONSOR, cust, reply)?;
+                    core.event_enqueue(evt);
+                }
+            }
+            Ok(())
+        }
+        fn pending(&self) -> bool {
+            self.waiting.is_some()
+        }
+        fn register_waker(&mut self, waker: &Waker) -> bool {
+            let fired = self.fired.clone();
+            let waker = waker.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                fired.store(true, Ordering::Release);
+                waker.wake();
+            });
+            true
+        }
+    }
+
+    #[test]
+    fn park_while_waiting_on_devices() {
+        let polls = Rc::new(Cell::new(0));
+        let mut core = Core::default();
+        core.init();
+        let timer = Timer { waiting: None, fired: Arc::default(), polls: polls.clone() };
+        let timer = core.register_device(Box::new(timer)).unwrap();
+        let mut core = AsyncCore::new(core);
+        assert_eq!(Ok(Any::fix(42)), block_on(core.call(timer, Any::fix(41), SPONSOR)));
+        assert!(polls.get() <= 3);  // parked, rather than polling for 50ms
+    }
+
+    #[test]
+    fn park_until_posted() {
+        let mut core = Core::default();
+        core.init();
+        let mut core = AsyncCore::new(core);
+        let handle = core.handle();
+        let worker = core.core_mut().register_device(Box::new(Worker { handle })).unwrap();
+        assert_eq!(Ok(Any::fix(42)), block_on(core.call(worker, Any::fix(41), SPONSOR)));
+        assert_eq!(Ok(Any::fix(7)), block_on(core.call(worker, Any::fix(6), SPONSOR)));
+        assert_eq!(Err(E_NOT_CAP), block_on(core.call(UNDEF, Any::fix(6), SPONSOR)));
+        assert_eq!(Ok(()), block_on(core.run_until_idle()));
+
+        // serve until a posted task interrupts the run-loop
+        let handle = core.handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            handle.post_task(|core: &mut Core| core.interrupt_handle().interrupt());
+        });
+        assert_eq!(Ok(()), block_on(core.serve()));
+
+        // the root sponsor's error signal is reported
+        core.core_mut().set_sponsor_events(SPONSOR, ZERO);
+        assert_eq!(Err(E_MSG_LIM), block_on(core.call(worker, Any::fix(1), SPONSOR)));
+    }
+
+}
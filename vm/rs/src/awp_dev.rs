@@ -0,0 +1,904 @@
+// The AwpDevice implements the Actor Wire Protocol (AWP),
+// allowing actors to exchange messages with actors on other cores.
+// The interface is described in `awp_dev.md`, and the protocol in `awp.md`.
+//
+// Frames are carried by a pluggable `Transport`. Transports never call back
+// into the device, instead `poll` collects their activity between `run_loop` slices.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ::core::task::Waker;
+
+use crate::*;
+use crate::oed::{self, Value};
+use crate::slots::Slots;
+
+// AWP request failure codes (`#f,error`)
+pub const E_CONNECTION_LOST: isize = -1;
+pub const E_ALREADY_LISTENING: isize = -2;
+pub const E_LISTEN_FAIL: isize = -3;
+pub const E_NO_ACQUAINTANCE: isize = -4;
+pub const E_NO_STORE: isize = -5;
+
+// device request tags
+pub const AWP_INTRO: Any = ZERO;  // #intro,to_cancel,callback,store,petname,hello
+pub const AWP_LISTEN: Any = PLUS_1;  // #listen,to_cancel,callback,store,greeter
+
+// proxy tags are fixnums, `nr << 2 | kind`
+const TAG_REMOTE: isize = 0;  // remote actor
+const TAG_STOP: isize = 1;  // `_ -> stop listening`
+const TAG_REPLY: isize = 2;  // `result -> introduction result`
+const TAG_CANCEL: isize = 3;  // `reason -> cancel introduction`
+
+fn proxy_tag(kind: isize, nr: usize) -> Any {
+    Any::fix(((nr as isize) << 2) | kind)
+}
+fn split_tag(tag: Any) -> Option<(isize, usize)> {
+    let tag = tag.fix_num()?;
+    if tag < 0 {
+        return None;
+    }
+    Some((tag & 3, (tag >> 2) as usize))
+}
+
+pub type ConnId = usize;  // transport-assigned connection identifier
+pub type ListenId = usize;  // transport-assigned listener identifier
+pub type RandomFn = Box<dyn FnMut(&mut [u8])>;  // fills a buffer with secure random octets
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportEvent {
+    Opened(ListenId, ConnId),  // a listener accepted a connection
+    Connected(ConnId),  // an outgoing connection was established
+    Received(ConnId, Vec<u8>),  // a frame arrived
+    Closed(ConnId),  // a connection failed, or was closed by the remote party
+}
+
+// abstract transport interface
+pub trait Transport {
+    fn listen(&mut self, identity: &Value, bind_info: &Value) -> Result<ListenId, Error>;
+    fn stop(&mut self, listener: ListenId);  // stop accepting connections
+    fn connect(&mut self, identity: &Value, name: &Value, address: &Value) -> Result<ConnId, Error>;
+    fn name(&self, conn: ConnId) -> Option<Value>;  // authenticated name of the remote party
+    fn send(&mut self, conn: ConnId, frame: &[u8]);
+    fn close(&mut self, conn: ConnId);  // no `Closed` event is reported
+    fn poll(&mut self) -> Option<TransportEvent>;
+    fn register_waker(&mut self, _waker: &Waker) -> bool { false }  // wake when `poll` has events, default: can't
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Acquaintance {
+    pub name: Value,
+    pub address: Option<Value>,
+}
+
+impl Acquaintance {
+    fn to_value(&self) -> Value {
+        let mut props = vec![(Value::String(String::from("name")), self.name.clone())];
+        if let Some(address) = &self.address {
+            props.push((Value::String(String::from("address")), address.clone()));
+        }
+        Value::Object(props)
+    }
+    fn from_value(meta: &Value) -> Result<Acquaintance, Error> {
+        match meta.get("name") {
+            Some(name) => Ok(Acquaintance {
+                name: name.clone(),
+                address: meta.get("address").cloned(),
+            }),
+            None => Err(E_FAIL),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Store {
+    pub identity: Value,
+    pub bind_info: Option<Value>,
+    pub acquaintances: Vec<Acquaintance>,  // indexed by petname, self is petname 0
+}
+
+type Swiss = [u8; 16];  // 128-bit Swiss number
+type Convo = (usize, usize);  // (store_nr, petname) of a conversation
+
+struct Remote {
+    cap: Any,  // proxy capability
+    convo: Convo,
+    swiss: Swiss,
+}
+
+struct Listening {
+    store_nr: usize,
+    listener: ListenId,
+    greeter: Any,  // stub holding `greeter` capability
+}
+
+struct Intro {
+    convo: Convo,
+    callback: Any,  // stub holding `sponsor,callback`
+    reply_to: Any,  // proxy exported to the greeter
+}
+
+pub struct AwpDevice {
+    transport: Box<dyn Transport>,
+    stores: Vec<Store>,
+    random: RandomFn,  // source of Swiss numbers
+    dev_cap: Any,
+    convos: BTreeMap<ConnId, Convo>,  // open (or opening) connections
+    connections: BTreeMap<Convo, ConnId>,
+    opening: BTreeMap<Convo, ConnId>,
+    outbox: BTreeMap<Convo, Vec<Vec<u8>>>,  // frames awaiting a connection
+    remotes: Slots<Remote>,  // remote nr -> proxy
+    remote_tags: BTreeMap<(Convo, Swiss), usize>,
+    stubs: BTreeMap<Swiss, Any>,  // Swiss number -> stub holding exported capability
+    exports: BTreeMap<(Convo, Raw), Swiss>,  // exported capability -> Swiss number
+    listeners: Slots<Listening>,  // stop nr -> listener
+    intros: Slots<Intro>,  // reply (and cancel) nr -> pending introduction
+}
+
+impl AwpDevice {
+    pub fn new(transport: Box<dyn Transport>, stores: Vec<Store>, random: RandomFn) -> AwpDevice {
+        AwpDevice {
+            transport,
+            stores,
+            random,
+            dev_cap: UNDEF,
+            convos: BTreeMap::new(),
+            connections: BTreeMap::new(),
+            opening: BTreeMap::new(),
+            outbox: BTreeMap::new(),
+            remotes: Slots::default(),
+            remote_tags: BTreeMap::new(),
+            stubs: BTreeMap::new(),
+            exports: BTreeMap::new(),
+            listeners: Slots::default(),
+            intros: Slots::default(),
+        }
+    }
+
+    pub fn stores(&self) -> &[Store] {
+        &self.stores
+    }
+
+    fn petname(&mut self, store_nr: usize, acquaintance: Acquaintance) -> usize {
+        // find (or add) an acquaintance, updating their address if one is provided
+        let store = &mut self.stores[store_nr];
+        match store.acquaintances.iter().position(|a| a.name == acquaintance.name) {
+            Some(petname) => {
+                if acquaintance.address.is_some() {
+                    store.acquaintances[petname].address = acquaintance.address;
+                }
+                petname
+            },
+            None => {
+                store.acquaintances.push(acquaintance);
+                store.acquaintances.len() - 1
+            },
+        }
+    }
+
+    fn marshal(&mut self, core: &mut Core, convo: Convo, value: Any) -> Result<Value, Error> {
+        // capabilities are encoded as `ext(acquaintance, swiss)`
+        oed::from_heap(core, value, &mut |core, cap| {
+            if !cap.is_cap() {
+                return Err(E_NO_TYPE);
+            }
+            self.export(core, convo, cap)
+        })
+    }
+
+    fn export(&mut self, core: &mut Core, convo: Convo, cap: Any) -> Result<Value, Error> {
+        let quad = core.ram(core.cap_to_ptr(cap));
+        if (quad.t() == PROXY_T) && (quad.x() == self.dev_cap) {
+            if let Some(remote) = split_tag(quad.y()).and_then(|(kind, nr)| (kind == TAG_REMOTE).then_some(nr)).and_then(|nr| self.remotes.get(nr)) {
+                // pass on the details of a remote actor directly
+                let (store_nr, petname) = remote.convo;
+                let meta = self.stores[store_nr].acquaintances[petname].to_value();
+                return Ok(Value::Extension(Box::new(meta), remote.swiss.to_vec()));
+            }
+        }
+        let swiss = match self.exports.get(&(convo, cap.raw())) {
+            Some(swiss) => *swiss,
+            None => {
+                // retain the capability until the conversation ends
+                let mut swiss: Swiss = [0; 16];
+                (self.random)(&mut swiss);
+                let stub = core.reserve_stub(self.dev_cap, cap)?;
+                self.stubs.insert(swiss, stub);
+                self.exports.insert((convo, cap.raw()), swiss);
+                swiss
+            },
+        };
+        let meta = self.stores[convo.0].acquaintances[0].to_value();  // self
+        Ok(Value::Extension(Box::new(meta), swiss.to_vec()))
+    }
+
+    fn unexport(&mut self, core: &mut Core, convo: Convo, cap: Any) {
+        if let Some(swiss) = self.exports.remove(&(convo, cap.raw())) {
+            if let Some(stub) = self.stubs.remove(&swiss) {
+                core.release_stub(stub);
+            }
+        }
+    }
+
+    fn unmarshal(&mut self, core: &mut Core, store_nr: usize, value: &Value) -> Result<Any, Error> {
+        oed::to_heap(core, value, &mut |core, ext| match ext {
+            Value::Extension(meta, data) => {
+                let swiss: Swiss = data.as_slice().try_into().map_err(|_| E_FAIL)?;
+                let petname = self.petname(store_nr, Acquaintance::from_value(meta)?);
+                if petname == 0 {
+                    if let Some(stub) = self.stubs.get(&swiss) {
+                        return Ok(core.ram(*stub).y());  // local actor
+                    }
+                }
+                self.remote(core, (store_nr, petname), swiss)
+            },
+            _ => Err(E_NO_TYPE),
+        })
+    }
+
+    fn remote(&mut self, core: &mut Core, convo: Convo, swiss: Swiss) -> Result<Any, Error> {
+        // get the proxy for a remote actor, creating it if necessary
+        if let Some(nr) = self.remote_tags.get(&(convo, swiss)) {
+            if let Some(remote) = self.remotes.get(*nr) {
+                return Ok(remote.cap);
+            }
+        }
+        let nr = self.remotes.vacant();
+        let cap = core.reserve_proxy(self.dev_cap, proxy_tag(TAG_REMOTE, nr))?;
+        self.remotes.insert(Remote { cap, convo, swiss }, 0);  // removed when the proxy is dropped
+        self.remote_tags.insert((convo, swiss), nr);
+        Ok(cap)
+    }
+
+    fn enqueue(&mut self, core: &mut Core, convo: Convo, target: Option<Swiss>, message: Value) -> Result<(), Error> {
+        let mut props = Vec::new();
+        if let Some(swiss) = target {
+            props.push((Value::String(String::from("target")), Value::Blob(swiss.to_vec())));
+        }
+        props.push((Value::String(String::from("message")), Value::Blob(message.encode())));
+        let frame = Value::Object(props).encode();
+        self.outbox.entry(convo).or_default().push(frame);
+        self.flush(core, convo)
+    }
+
+    fn flush(&mut self, core: &mut Core, convo: Convo) -> Result<(), Error> {
+        // attempt to send pending frames, connecting if necessary
+        if !self.outbox.contains_key(&convo) {
+            return Ok(());
+        }
+        if let Some(conn) = self.connections.get(&convo) {
+            for frame in self.outbox.remove(&convo).unwrap_or_default() {
+                self.transport.send(*conn, &frame);
+            }
+            return Ok(());
+        }
+        if self.opening.contains_key(&convo) {
+            return Ok(());  // wait for connection
+        }
+        let (store_nr, petname) = convo;
+        let store = &self.stores[store_nr];
+        let acquaintance = &store.acquaintances[petname];
+        let conn = match &acquaintance.address {
+            Some(address) => self.transport.connect(&store.identity, &acquaintance.name, address),
+            None => Err(E_FAIL),  // reachable only while they are connected to us
+        };
+        match conn {
+            Ok(conn) => {
+                self.opening.insert(convo, conn);
+                self.convos.insert(conn, convo);
+                Ok(())
+            },
+            Err(_) => self.lose(core, convo),
+        }
+    }
+
+    fn register(&mut self, core: &mut Core, convo: Convo, conn: ConnId) -> Result<(), Error> {
+        // adopt an open connection, there is only one connection per conversation
+        if let Some(other) = self.opening.remove(&convo) {
+            if other != conn {
+                self.transport.close(other);
+                self.convos.remove(&other);
+            }
+        }
+        if let Some(other) = self.connections.insert(convo, conn) {
+            if other != conn {
+                // the old connection may only appear open to us, so keep the new one
+                self.transport.close(other);
+                self.convos.remove(&other);
+            }
+        }
+        self.flush(core, convo)
+    }
+
+    fn lose(&mut self, core: &mut Core, convo: Convo) -> Result<(), Error> {
+        // fail introductions awaiting a reply over a lost connection
+        self.outbox.remove(&convo);
+        let failed: Vec<usize> = self.intros.iter()
+            .filter(|(_, intro)| intro.convo == convo)
+            .map(|(intro_nr, _)| intro_nr)
+            .collect();
+        for intro_nr in failed {
+            let intro = self.intros.take(intro_nr).unwrap();
+            let result = fail_result(core, E_CONNECTION_LOST)?;
+            reply(core, intro.callback, result)?;
+        }
+        // capabilities exported to the remote party are no longer reachable
+        let lost: Vec<(Convo, Raw)> = self.exports.keys()
+            .filter(|(exported_to, _)| *exported_to == convo)
+            .copied()
+            .collect();
+        for (_, raw) in lost {
+            self.unexport(core, convo, Any::new(raw));
+        }
+        Ok(())
+    }
+
+    fn stop_listening(&mut self, core: &mut Core, stop_nr: usize) {
+        if let Some(listening) = self.listeners.take(stop_nr) {
+            self.transport.stop(listening.listener);
+            core.release_stub(listening.greeter);
+        }
+    }
+
+    fn receive(&mut self, core: &mut Core, convo: Convo, frame: &[u8]) -> Result<(), Error> {
+        // malformed frames are ignored, since the transport need not be reliable
+        let frame = match Value::decode(frame) {
+            Ok(frame) => frame,
+            Err(_) => return Ok(()),
+        };
+        let message = match frame.get("message") {
+            Some(Value::Blob(octets)) => match Value::decode(octets) {
+                Ok(message) => message,
+                Err(_) => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let (store_nr, petname) = convo;
+        let target = match frame.get("target") {
+            None => {  // introduction request
+                let greeter = self.listeners.iter()
+                    .map(|(_, listening)| listening)
+                    .find(|listening| listening.store_nr == store_nr)
+                    .map(|listening| listening.greeter);
+                match greeter {
+                    Some(stub) => core.ram(stub).y(),
+                    None => return Ok(()),  // no greeter
+                }
+            },
+            Some(Value::Blob(swiss)) => match self.stubs.get(swiss.as_slice()) {
+                Some(stub) => core.ram(*stub).y(),
+                None => return Ok(()),  // missing stub
+            },
+            _ => return Ok(()),
+        };
+        let msg = match self.unmarshal(core, store_nr, &message) {
+            Ok(msg) => msg,
+            Err(_) => return Ok(()),
+        };
+        if frame.get("target").is_none() {
+            // to_cancel,callback,petname,hello -> greeter
+            let callback = core.car(msg);
+            let hello = core.cdr(msg);
+            let req = core.reserve(&Quad::pair_t(Any::fix(petname as isize), hello))?;
+            let req = core.reserve(&Quad::pair_t(callback, req))?;
+            let req = core.reserve(&Quad::pair_t(UNDEF, req))?;
+            return send(core, SPONSOR, target, req);
+        }
+        send(core, SPONSOR, target, msg)
+    }
+
+    fn intro(&mut self, core: &mut Core, sponsor: Any, request: Any) -> Result<Any, Error> {
+        let to_cancel = core.nth(request, PLUS_1);
+        let callback = core.nth(request, PLUS_2);
+        if !callback.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let store_nr = core.nth(request, PLUS_3).get_fix()?;
+        let petname = core.nth(request, PLUS_4).get_fix()?;
+        let hello = core.nth(request, MINUS_4);
+        let store = match self.stores.get(store_nr as usize) {
+            Some(store) if store_nr >= 0 => store,
+            _ => {
+                let result = fail_result(core, E_NO_STORE)?;
+                return core.reserve_event(sponsor, callback, result);
+            },
+        };
+        if (petname < 0) || (petname as usize >= store.acquaintances.len()) {
+            let result = fail_result(core, E_NO_ACQUAINTANCE)?;
+            return core.reserve_event(sponsor, callback, result);
+        }
+        let convo = (store_nr as usize, petname as usize);
+        // the greeter replies to a proxy that forwards (only once) to the callback
+        let intro_nr = self.intros.vacant();
+        let reply_to = core.reserve_proxy(self.dev_cap, proxy_tag(TAG_REPLY, intro_nr))?;
+        let message = core.reserve(&Quad::pair_t(reply_to, hello))?;
+        let message = self.marshal(core, convo, message)?;
+        let callback = hold(core, self.dev_cap, sponsor, callback)?;
+        let cancel = match to_cancel.is_cap() {
+            true => Some(core.reserve_proxy(self.dev_cap, proxy_tag(TAG_CANCEL, intro_nr))?),
+            false => None,
+        };
+        let proxies = if cancel.is_some() { 2 } else { 1 };
+        self.intros.insert(Intro { convo, callback, reply_to }, proxies);
+        if let Some(cancel) = cancel {
+            send(core, sponsor, to_cancel, cancel)?;
+        }
+        self.enqueue(core, convo, None, message)?;
+        Ok(UNDEF)
+    }
+
+    fn listen(&mut self, core: &mut Core, sponsor: Any, request: Any) -> Result<Any, Error> {
+        let callback = core.nth(request, PLUS_2);
+        if !callback.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let store_nr = core.nth(request, PLUS_3).get_fix()?;
+        let greeter = core.nth(request, MINUS_3);
+        if !greeter.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let result = match self.stores.get(store_nr as usize) {
+            _ if store_nr < 0 => fail_result(core, E_NO_STORE)?,
+            None => fail_result(core, E_NO_STORE)?,
+            Some(_) if self.listeners.iter().any(|(_, l)| l.store_nr == store_nr as usize) => {
+                fail_result(core, E_ALREADY_LISTENING)?
+            },
+            Some(store) => {
+                let listener = match &store.bind_info {
+                    Some(bind_info) => self.transport.listen(&store.identity, bind_info),
+                    None => Err(E_FAIL),
+                };
+                match listener {
+                    Ok(listener) => {
+                        let greeter = core.reserve_stub(self.dev_cap, greeter)?;
+                        let stop_nr = self.listeners.vacant();
+                        let stop = core.reserve_proxy(self.dev_cap, proxy_tag(TAG_STOP, stop_nr))?;
+                        self.listeners.insert(Listening { store_nr: store_nr as usize, listener, greeter }, 1);
+                        core.reserve(&Quad::pair_t(TRUE, stop))?
+                    },
+                    Err(_) => fail_result(core, E_LISTEN_FAIL)?,
+                }
+            },
+        };
+        core.reserve_event(sponsor, callback, result)
+    }
+}
+
+fn fail_result(core: &mut Core, error: isize) -> Result<Any, Error> {
+    core.reserve(&Quad::pair_t(FALSE, Any::fix(error)))
+}
+fn hold(core: &mut Core, dev_cap: Any, sponsor: Any, customer: Any) -> Result<Any, Error> {
+    // protect `sponsor,customer` until a reply is sent
+    let pair = core.reserve(&Quad::pair_t(sponsor, customer))?;
+    core.reserve_stub(dev_cap, pair)
+}
+fn send(core: &mut Core, sponsor: Any, target: Any, msg: Any) -> Result<(), Error> {
+    let evt = core.reserve_event(sponsor, target, msg)?;
+    core.event_enqueue(evt);
+    Ok(())
+}
+fn reply(core: &mut Core, callback: Any, result: Any) -> Result<(), Error> {
+    // deliver `result` to the callback held by a stub, releasing the stub
+    let held = core.ram(callback).y();  // sponsor,callback
+    core.release_stub(callback);
+    send(core, core.car(held), core.cdr(held), result)
+}
+
+impl Device for AwpDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let event = core.mem(ep);
+        let sponsor = event.t();
+        let target = event.x();
+        let msg = event.y();
+        let myself = core.ram(core.cap_to_ptr(target));
+        if myself.t() == PROXY_T {
+            self.dev_cap = myself.x();
+            match split_tag(myself.y()) {
+                Some((TAG_REMOTE, nr)) => {  // send to remote actor
+                    if let Some(remote) = self.remotes.get(nr) {
+                        let (convo, swiss) = (remote.convo, remote.swiss);
+                        let message = self.marshal(core, convo, msg)?;
+                        self.enqueue(core, convo, Some(swiss), message)?;
+                    }
+                },
+                Some((TAG_STOP, nr)) => self.stop_listening(core, nr),
+                Some((kind, nr)) => {
+                    if let Some(intro) = self.intros.take(nr) {
+                        self.unexport(core, intro.convo, intro.reply_to);
+                        if kind == TAG_REPLY {
+                            reply(core, intro.callback, msg)?;  // introduction result
+                        } else {
+                            core.release_stub(intro.callback);  // cancelled, no reply is expected
+                        }
+                    }
+                },
+                None => {},
+            }
+            return Ok(UNDEF);
+        }
+        self.dev_cap = target;
+        let tag = core.nth(msg, PLUS_1);
+        let request = core.nth(msg, MINUS_1);
+        match tag {
+            AWP_INTRO => self.intro(core, sponsor, request),
+            AWP_LISTEN => self.listen(core, sponsor, request),
+            _ if tag.is_fix() => Err(E_BOUNDS),
+            _ => Err(E_NOT_FIX),
+        }
+    }
+    fn drop_proxy(&mut self, core: &mut Core, proxy: Any) {
+        // the tag is held by the proxy itself, so it survives the sweep of other cells
+        match split_tag(core.ram(core.cap_to_ptr(proxy)).y()) {
+            Some((TAG_REMOTE, nr)) => {
+                if let Some(remote) = self.remotes.take(nr) {
+                    self.remote_tags.remove(&(remote.convo, remote.swiss));
+                }
+            },
+            Some((TAG_STOP, nr)) => {
+                self.stop_listening(core, nr);  // nobody is left to stop the listener
+                self.listeners.drop_proxy(nr);
+            },
+            Some((_, nr)) => self.intros.drop_proxy(nr),
+            None => {},
+        }
+    }
+    fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+        while let Some(event) = self.transport.poll() {
+            match event {
+                TransportEvent::Opened(listener, conn) => {
+                    let store_nr = self.listeners.iter()
+                        .find(|(_, listening)| listening.listener == listener)
+                        .map(|(_, listening)| listening.store_nr);
+                    let (store_nr, name) = match (store_nr, self.transport.name(conn)) {
+                        (Some(store_nr), Some(name)) => (store_nr, name),
+                        _ => {
+                            self.transport.close(conn);
+                            continue;
+                        },
+                    };
+                    // unknown parties become acquaintances automatically
+                    let petname = self.petname(store_nr, Acquaintance { name, address: None });
+                    self.convos.insert(conn, (store_nr, petname));
+                    self.register(core, (store_nr, petname), conn)?;
+                },
+                TransportEvent::Connected(conn) => {
+                    if let Some(convo) = self.convos.get(&conn) {
+                        self.register(core, *convo, conn)?;
+                    }
+                },
+                TransportEvent::Received(conn, frame) => {
+                    if let Some(convo) = self.convos.get(&conn) {
+                        self.receive(core, *convo, &frame)?;
+                    }
+                },
+                TransportEvent::Closed(conn) => {
+                    if let Some(convo) = self.convos.remove(&conn) {
+                        if self.connections.get(&convo) == Some(&conn) {
+                            self.connections.remove(&convo);
+                            self.lose(core, convo)?;
+                        }
+                        if self.opening.get(&convo) == Some(&conn) {
+                            self.opening.remove(&convo);
+                            self.lose(core, convo)?;
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+    fn pending(&self) -> bool {
+        !self.convos.is_empty()
+            || !self.listeners.is_empty()
+            || !self.intros.is_empty()
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        self.transport.register_waker(waker)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::rc::Rc;
+
+    use ::core::cell::RefCell;
+
+    use super::*;
+    use crate::memory_transport::{MemoryNetwork, MemoryTransport};
+
+    type Log = Rc<RefCell<Vec<(Any, Any)>>>;
+
+    // remembers each `#t,output` or `#f,error` result (or bare value)
+    struct Recorder {
+        log: Log,
+    }
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);
+            let entry = if core.typeq(PAIR_T, msg) { (core.car(msg), core.cdr(msg)) } else { (UNDEF, msg) };
+            if entry.1.is_cap() {
+                core.reserve_stub(DEBUG_DEV, entry.1)?;  // keep capability alive
+            }
+            self.log.borrow_mut().push(entry);
+            Ok(UNDEF)
+        }
+    }
+
+    // greets every party with `#t,increment`, remembering `petname,hello`
+    struct Greeter {
+        log: Log,
+        increment: Any,
+    }
+    impl Device for Greeter {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);  // to_cancel,callback,petname,hello
+            let callback = core.nth(msg, PLUS_2);
+            self.log.borrow_mut().push((core.nth(msg, PLUS_3), core.nth(msg, MINUS_3)));
+            let greeting = core.reserve(&Quad::pair_t(TRUE, self.increment))?;
+            core.reserve_event(SPONSOR, callback, greeting)
+        }
+    }
+
+    // replies to `customer,n` with `n+1`
+    struct Increment;
+    impl Device for Increment {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);
+            let n = core.cdr(msg).get_fix()?;
+            core.reserve_event(SPONSOR, core.car(msg), Any::fix(n + 1))
+        }
+    }
+
+    fn test_random() -> RandomFn {
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        Box::new(move |buf: &mut [u8]| {
+            for octet in buf.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *octet = state as u8;
+            }
+        })
+    }
+
+    fn request(core: &mut Core, awp_dev: Any, tag: Any, input: &[Any]) {
+        cancellable_request(core, awp_dev, tag, UNDEF, input);
+    }
+
+    fn cancellable_request(core: &mut Core, awp_dev: Any, tag: Any, to_cancel: Any, input: &[Any]) {
+        let (last, init) = input.split_last().unwrap();
+        let mut msg = *last;
+        for item in init.iter().rev() {
+            msg = core.reserve(&Quad::pair_t(*item, msg)).unwrap();
+        }
+        let msg = core.reserve(&Quad::pair_t(to_cancel, msg)).unwrap();
+        let msg = core.reserve(&Quad::pair_t(tag, msg)).unwrap();
+        let evt = core.reserve_event(SPONSOR, awp_dev, msg).unwrap();
+        core.event_enqueue(evt);
+    }
+
+    fn new_core(transport: Box<dyn Transport>, store: Store) -> (Box<Core>, Any) {
+        let mut core = Box::new(Core::default());
+        core.init();
+        let awp_dev = core.register_device(Box::new(AwpDevice::new(transport, vec![store], test_random()))).unwrap();
+        (core, awp_dev)
+    }
+
+    fn run_until(cores: &mut [&mut Core], done: &dyn Fn() -> bool) {
+        for _ in 0..5000 {
+            for core in cores.iter_mut() {
+                assert_eq!(ZERO, core.run_loop(0));
+                core.poll_devices().unwrap();
+                assert_eq!(ZERO, core.run_loop(0));
+            }
+            if done() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("timed out");
+    }
+
+    // Alice is introduced to Bob, who greets her with an actor that she uses.
+    pub(crate) fn exchange_messages(alice: Box<dyn Transport>, bob: Box<dyn Transport>, bob_address: Value) {
+        let alice_name = Value::String(String::from("alice"));
+        let bob_name = Value::String(String::from("bob"));
+        let (mut alice_core, alice_awp) = new_core(alice, Store {
+            identity: alice_name.clone(),
+            bind_info: None,
+            acquaintances: vec![
+                Acquaintance { name: alice_name.clone(), address: None },
+                Acquaintance { name: bob_name.clone(), address: Some(bob_address.clone()) },
+            ],
+        });
+        let (mut bob_core, bob_awp) = new_core(bob, Store {
+            identity: bob_name.clone(),
+            bind_info: Some(bob_address.clone()),
+            acquaintances: vec![
+                Acquaintance { name: bob_name.clone(), address: Some(bob_address) },
+            ],
+        });
+        let alice_log = Log::default();
+        let bob_log = Log::default();
+        let greeter_log = Log::default();
+        let alice_recorder = alice_core.register_device(Box::new(Recorder { log: alice_log.clone() })).unwrap();
+        let bob_recorder = bob_core.register_device(Box::new(Recorder { log: bob_log.clone() })).unwrap();
+        let increment = bob_core.register_device(Box::new(Increment)).unwrap();
+        let greeter = bob_core.register_device(Box::new(Greeter { log: greeter_log.clone(), increment })).unwrap();
+
+        // Bob listens, with a greeter
+        request(&mut bob_core, bob_awp, AWP_LISTEN, &[bob_recorder, ZERO, greeter]);
+        run_until(&mut [&mut bob_core], &|| !bob_log.borrow().is_empty());
+        let (ok, stop) = bob_log.borrow()[0];
+        assert_eq!(TRUE, ok);
+        assert!(stop.is_cap());
+
+        // Alice asks to be introduced to Bob (petname 1)
+        request(&mut alice_core, alice_awp, AWP_INTRO, &[alice_recorder, ZERO, PLUS_1, Any::fix(42)]);
+        run_until(&mut [&mut alice_core, &mut bob_core], &|| !alice_log.borrow().is_empty());
+        assert_eq!(vec![(PLUS_1, Any::fix(42))], *greeter_log.borrow());  // Alice is Bob's petname 1
+        let (ok, increment) = alice_log.borrow()[0];
+        assert_eq!(TRUE, ok);
+        assert!(increment.is_cap());
+        assert_eq!(PROXY_T, alice_core.ram(alice_core.cap_to_ptr(increment)).t());
+
+        // Alice sends her recorder to Bob's actor, which replies with a number
+        let msg = alice_core.reserve(&Quad::pair_t(alice_recorder, Any::fix(7))).unwrap();
+        let evt = alice_core.reserve_event(SPONSOR, increment, msg).unwrap();
+        alice_core.event_enqueue(evt);
+        run_until(&mut [&mut alice_core, &mut bob_core], &|| alice_log.borrow().len() >= 2);
+        assert_eq!((UNDEF, Any::fix(8)), alice_log.borrow()[1]);
+
+        // Bob stops listening
+        let evt = bob_core.reserve_event(SPONSOR, stop, UNDEF).unwrap();
+        bob_core.event_enqueue(evt);
+        assert_eq!(ZERO, bob_core.run_loop(0));
+    }
+
+    #[test]
+    fn awp_dev_memory_transport() {
+        let network = Rc::new(RefCell::new(MemoryNetwork::default()));
+        exchange_messages(
+            Box::new(MemoryTransport::new(&network)),
+            Box::new(MemoryTransport::new(&network)),
+            Value::String(String::from("@bob")),
+        );
+    }
+
+    #[test]
+    fn awp_dev_intro_failures() {
+        let network = Rc::new(RefCell::new(MemoryNetwork::default()));
+        let alice_name = Value::String(String::from("alice"));
+        let (mut core, awp_dev) = new_core(Box::new(MemoryTransport::new(&network)), Store {
+            identity: alice_name.clone(),
+            bind_info: None,
+            acquaintances: vec![
+                Acquaintance { name: alice_name, address: None },
+                Acquaintance { name: Value::String(String::from("bob")), address: Some(Value::Null) },
+            ],
+        });
+        let log = Log::default();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        request(&mut core, awp_dev, AWP_INTRO, &[recorder, PLUS_1, ZERO, UNDEF]);  // no such store
+        request(&mut core, awp_dev, AWP_INTRO, &[recorder, ZERO, PLUS_2, UNDEF]);  // no such petname
+        request(&mut core, awp_dev, AWP_INTRO, &[recorder, ZERO, PLUS_1, UNDEF]);  // nobody listening
+        request(&mut core, awp_dev, AWP_LISTEN, &[recorder, ZERO, recorder]);  // no bind_info
+        run_until(&mut [&mut core], &|| log.borrow().len() >= 4);
+        let mut results: Vec<(Any, Any)> = log.borrow().clone();
+        results.sort_by_key(|(_, error)| error.fix_num());
+        assert_eq!(vec![
+            (FALSE, Any::fix(E_NO_STORE)),
+            (FALSE, Any::fix(E_NO_ACQUAINTANCE)),
+            (FALSE, Any::fix(E_LISTEN_FAIL)),
+            (FALSE, Any::fix(E_CONNECTION_LOST)),
+        ], results);
+    }
+
+    #[test]
+    fn awp_dev_reclaims_exports() {
+        let network = Rc::new(RefCell::new(MemoryNetwork::default()));
+        let alice_name = Value::String(String::from("alice"));
+        let bob_name = Value::String(String::from("bob"));
+        let bob_address = Value::String(String::from("@bob"));
+        let alice_awp = Rc::new(RefCell::new(AwpDevice::new(
+            Box::new(MemoryTransport::new(&network)),
+            vec![Store {
+                identity: alice_name.clone(),
+                bind_info: None,
+                acquaintances: vec![
+                    Acquaintance { name: alice_name, address: None },
+                    Acquaintance { name: bob_name.clone(), address: Some(bob_address.clone()) },
+                ],
+            }],
+            test_random(),
+        )));
+        let mut alice_core = Box::new(Core::default());
+        alice_core.init();
+        let alice_dev = alice_core.register_device(Box::new(alice_awp.clone())).unwrap();
+        let alice_log = Log::default();
+        let alice_recorder = alice_core.register_device(Box::new(Recorder { log: alice_log.clone() })).unwrap();
+
+        // nobody is listening, so the connection is lost
+        request(&mut alice_core, alice_dev, AWP_INTRO, &[alice_recorder, ZERO, PLUS_1, UNDEF]);
+        run_until(&mut [&mut alice_core], &|| !alice_log.borrow().is_empty());
+        assert_eq!((FALSE, Any::fix(E_CONNECTION_LOST)), alice_log.borrow()[0]);
+        alice_core.gc_collect_all();
+        assert!(alice_awp.borrow().stubs.is_empty());
+        assert!(alice_awp.borrow().exports.is_empty());
+        assert!(!alice_awp.borrow().pending());
+
+        // Bob's greeter never replies, so Alice cancels her introduction
+        let (mut bob_core, bob_dev) = new_core(Box::new(MemoryTransport::new(&network)), Store {
+            identity: bob_name.clone(),
+            bind_info: Some(bob_address.clone()),
+            acquaintances: vec![
+                Acquaintance { name: bob_name, address: Some(bob_address) },
+            ],
+        });
+        let bob_log = Log::default();
+        let bob_recorder = bob_core.register_device(Box::new(Recorder { log: bob_log.clone() })).unwrap();
+        request(&mut bob_core, bob_dev, AWP_LISTEN, &[bob_recorder, ZERO, bob_recorder]);
+        run_until(&mut [&mut bob_core], &|| !bob_log.borrow().is_empty());
+        cancellable_request(&mut alice_core, alice_dev, AWP_INTRO, alice_recorder, &[alice_recorder, ZERO, PLUS_1, UNDEF]);
+        run_until(&mut [&mut alice_core, &mut bob_core], &|| bob_log.borrow().len() >= 2);
+        let (_, cancel) = alice_log.borrow()[1];
+        assert!(cancel.is_cap());
+        assert_eq!(1, alice_awp.borrow().stubs.len());  // reply_to
+        let evt = alice_core.reserve_event(SPONSOR, cancel, UNDEF).unwrap();
+        alice_core.event_enqueue(evt);
+        assert_eq!(ZERO, alice_core.run_loop(0));
+        alice_core.gc_collect_all();
+        assert!(alice_awp.borrow().stubs.is_empty());
+        assert!(alice_awp.borrow().intros.is_empty());
+        assert_eq!(2, alice_log.borrow().len());  // no reply after cancel
+    }
+
+    #[test]
+    fn awp_dev_drops_listener() {
+        // remembers each result, without keeping its capabilities
+        struct Forgetful {
+            log: Log,
+        }
+        impl Device for Forgetful {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                let msg = core.event_message(ep);
+                self.log.borrow_mut().push((core.car(msg), UNDEF));
+                Ok(UNDEF)
+            }
+        }
+        let network = Rc::new(RefCell::new(MemoryNetwork::default()));
+        let bob_name = Value::String(String::from("bob"));
+        let bob_address = Value::String(String::from("@bob"));
+        let bob_awp = Rc::new(RefCell::new(AwpDevice::new(
+            Box::new(MemoryTransport::new(&network)),
+            vec![Store {
+                identity: bob_name.clone(),
+                bind_info: Some(bob_address.clone()),
+                acquaintances: vec![
+                    Acquaintance { name: bob_name, address: Some(bob_address) },
+                ],
+            }],
+            test_random(),
+        )));
+        let mut core = Box::new(Core::default());
+        core.init();
+        let awp_dev = core.register_device(Box::new(bob_awp.clone())).unwrap();
+        let log = Log::default();
+        let forgetful = core.register_device(Box::new(Forgetful { log: log.clone() })).unwrap();
+        for n in 1..=2 {
+            request(&mut core, awp_dev, AWP_LISTEN, &[forgetful, ZERO, forgetful]);
+            run_until(&mut [&mut core], &|| log.borrow().len() >= n);
+            assert_eq!(TRUE, log.borrow()[n - 1].0);  // listening (again)
+
+            // nobody can stop the listener, so it is stopped when its proxy is dropped
+            core.gc_collect_all();
+            assert!(!core.devices_pending());
+            assert_eq!(0, bob_awp.borrow().listeners.capacity());  // the slot is released
+        }
+    }
+
+}
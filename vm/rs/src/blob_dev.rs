@@ -2,6 +2,7 @@
 // The interface is described in `blob_dev.md`.
 
 use crate::*;
+use crate::oed::{OED_POS_INT, OED_ARRAY, OED_BLOB, OED_EXTENSION, OED_NULL};
 
 //const BLOB_RAM_MAX: usize = 64;     // 64 octets of Blob RAM (for testing)
 //const BLOB_RAM_MAX: usize = 1<<8;   // 256 octets of Blob RAM (for testing)
@@ -10,13 +11,6 @@ use crate::*;
 //const BLOB_RAM_MAX: usize = 1<<14;  // 16K octets of Blob RAM
 const BLOB_RAM_MAX: usize = 1<<16;  // 64K octets of Blob RAM (maximum value)
 
-// OED-encoded types
-const OED_POS_INT: u8 =     0x82;   // Positive Integer
-const OED_ARRAY: u8 =       0x88;   // Array
-const OED_BLOB: u8 =        0x8A;   // Octet Blob
-const OED_EXTENSION: u8 =   0x8B;   // Extension Blob
-const OED_NULL: u8 =        0x8F;   // `null` octet
-
 fn u16_lsb(nat: usize) -> u8 {
     (nat & 0xFF) as u8
 }
@@ -91,7 +85,7 @@ impl BlobDevice {
             let free = self.get_u16(ofs + 5);  // `size` field is the number of free octets in this Blob
             if pos == (ofs + 9 + free) {
                 // allocation immediately follows this free block
-                let len = self.get_u16(pos + 1);  // `size` field is the number of data octets in this Blob
+                let len = ::core::cmp::max(4, self.get_u16(pos + 1));  // `size` field is the number of data octets in this Blob (at least 4)
                 let free_len = free + len + 5;
                 if next == (pos + len + 5) {
                     // coalesce the following free block
@@ -107,7 +101,7 @@ impl BlobDevice {
                 return Ok(());
             } else if (next == 0) || (pos < next) {
                 // allocation preceeds next free block
-                let len = self.get_u16(pos + 1);  // `size` field is the number of data octets in this Blob
+                let len = ::core::cmp::max(4, self.get_u16(pos + 1));  // `size` field is the number of data octets in this Blob (at least 4)
                 self.blob_ram[pos] = OED_EXTENSION;  // Blob -> Extension Blob
                 if next == (pos + len + 5) {
                     // coalesce the following free block
@@ -205,6 +199,36 @@ impl BlobDevice {
         self.blob_ram[ofs + 3] = u16_msb(data);
     }
 
+    pub fn alloc_blob(&mut self, core: &mut Core, data: &[u8]) -> Result<Any, Error> {
+        // host-side allocation, returns a blob capability holding a copy of `data`
+        // (sized exactly, even below the minimum allocation)
+        let handle = self.blob_reserve(Any::fix(data.len() as isize))?;
+        let (base, _len) = self.blob_dims(handle)?;
+        self.blob_ram[base..base + data.len()].copy_from_slice(data);
+        if data.len() < 4 {
+            self.set_u16(base - 4, data.len());  // exact size, within the minimum allocation
+        }
+        match core.reserve_proxy(BLOB_DEV, handle) {
+            Ok(blob) => Ok(blob),
+            Err(error) => {
+                self.blob_release(handle)?;
+                Err(error)
+            }
+        }
+    }
+    pub fn blob_data(&self, core: &Core, blob: Any) -> Result<&[u8], Error> {
+        // host-side access to the contents of a blob capability
+        if !blob.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let proxy = core.ram(core.cap_to_ptr(blob));
+        if (proxy.t() != PROXY_T) || (proxy.x() != BLOB_DEV) {
+            return Err(E_BOUNDS);  // not an allocated blob
+        }
+        let (base, len) = self.blob_dims(proxy.y())?;
+        Ok(&self.blob_ram[base..base + len])
+    }
+
     pub fn blob_top(&self) -> Any {
         Any::fix(BLOB_RAM_MAX as isize)
     }
@@ -307,6 +331,12 @@ impl Device for BlobDevice {
         }
         Err(E_BOUNDS)
     }
+    fn compare_proxies(&self, a: Any, b: Any) -> Option<::core::cmp::Ordering> {
+        // blobs compare by their contents
+        let (a_base, a_len) = self.blob_dims(a).ok()?;
+        let (b_base, b_len) = self.blob_dims(b).ok()?;
+        Some(self.blob_ram[a_base..a_base + a_len].cmp(&self.blob_ram[b_base..b_base + b_len]))
+    }
     fn drop_proxy(&mut self, core: &mut Core, proxy: Any) {
         if proxy.is_cap() {
             let ptr = core.cap_to_ptr(proxy);
@@ -332,4 +362,25 @@ mod tests {
         assert_ne!(0, ::core::mem::size_of::<BlobDevice>());
     }
 
+    #[test]
+    fn short_blobs_keep_their_size() {
+        let mut dev = ::alloc::boxed::Box::new(BlobDevice::new());
+        dev.init();
+        let mut core = ::alloc::boxed::Box::new(Core::default());
+        core.init();
+        let free = dev.get_u16(14);  // octets in the initial free block
+        let padded = dev.blob_reserve(PLUS_2).unwrap();
+        assert_eq!(PLUS_4, dev.blob_size(padded).unwrap());  // minimum allocation
+        let blob = dev.alloc_blob(&mut core, b"hi").unwrap();
+        assert_eq!(b"hi", dev.blob_data(&core, blob).unwrap());
+        let short = core.ram(core.cap_to_ptr(blob)).y();
+        assert_eq!(PLUS_2, dev.blob_size(short).unwrap());
+        let long = dev.blob_reserve(Any::fix(6)).unwrap();
+        assert_eq!(Any::fix(6), dev.blob_size(long).unwrap());
+        dev.blob_release(padded).unwrap();
+        dev.blob_release(short).unwrap();
+        dev.blob_release(long).unwrap();
+        assert_eq!(free, dev.get_u16(14));  // coalesced, with the padding
+    }
+
 }
@@ -0,0 +1,416 @@
+// A Bridge connects Cores in the same process (each on its own thread,
+// if desired), so that actors may send messages to actors on other Cores.
+//
+// Each Core joins the Bridge, which registers a BridgeDevice on that Core.
+// Capabilities in cross-core messages are exported as stubs on their home
+// Core, and imported as proxies of the receiving Core's BridgeDevice.
+// Other values are copied, as by `oed::from_heap` and `oed::to_heap`.
+// Messages are delivered through each Core's `CoreHandle`, preserving the
+// order of messages from each sender.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use std::sync::{Mutex, MutexGuard};
+
+use crate::*;
+use crate::core_handle::CoreHandle;
+use crate::oed::{self, Value};
+
+// a capability exported from one Core, which may be imported by another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RemoteCap {
+    home: usize,  // member exporting the capability
+    id: usize,  // export number
+}
+
+struct Export {
+    stub: Any,  // stub holding the exported capability
+    refs: usize,  // outstanding references (proxies, or messages in flight)
+}
+
+struct Member {
+    handle: CoreHandle,
+    device: Any,  // BridgeDevice capability
+    exports: Vec<Option<Export>>,  // id -> export
+    export_ids: BTreeMap<Raw, usize>,  // exported capability -> id
+    imports: Vec<Option<RemoteCap>>,  // proxy tag -> imported capability
+    import_tags: BTreeMap<(usize, usize), (usize, Any)>,  // (home, id) -> (tag, proxy)
+}
+
+#[derive(Clone, Default)]
+pub struct Bridge {
+    members: Arc<Mutex<Vec<Member>>>,
+}
+
+impl Bridge {
+    pub fn new() -> Bridge {
+        Bridge::default()
+    }
+    pub fn join(&self, core: &mut Core) -> Result<BridgePort, Error> {
+        // connect `core` to the bridge, registering its BridgeDevice
+        let handle = core.handle();
+        let member = {
+            let mut members = self.lock();
+            members.push(Member {
+                handle,
+                device: UNDEF,
+                exports: Vec::new(),
+                export_ids: BTreeMap::new(),
+                imports: Vec::new(),
+                import_tags: BTreeMap::new(),
+            });
+            members.len() - 1
+        };
+        let device = core.register_device(Box::new(BridgeDevice { bridge: self.clone(), member }))?;
+        self.lock()[member].device = device;
+        Ok(BridgePort { bridge: self.clone(), member })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Member>> {
+        self.members.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+    fn export(&self, core: &mut Core, member: usize, cap: Any) -> Result<RemoteCap, Error> {
+        // add a reference to `cap`, exporting it if necessary
+        let mut members = self.lock();
+        let local = &mut members[member];
+        if let Some(&id) = local.export_ids.get(&cap.raw()) {
+            if let Some(export) = &mut local.exports[id] {
+                export.refs += 1;
+                return Ok(RemoteCap { home: member, id });
+            }
+        }
+        let stub = core.reserve_stub(local.device, cap)?;
+        let id = local.exports.len();
+        local.exports.push(Some(Export { stub, refs: 1 }));
+        local.export_ids.insert(cap.raw(), id);
+        Ok(RemoteCap { home: member, id })
+    }
+    fn add_ref(&self, remote: RemoteCap) {
+        if let Some(Some(export)) = self.lock()[remote.home].exports.get_mut(remote.id) {
+            export.refs += 1;
+        }
+    }
+    fn release_ref(&self, remote: RemoteCap) {
+        // release the export's stub (on its home Core) with the last reference
+        let mut members = self.lock();
+        let home = &mut members[remote.home];
+        let slot = match home.exports.get_mut(remote.id) {
+            Some(slot) => slot,
+            None => return,
+        };
+        if let Some(export) = slot {
+            export.refs -= 1;
+            if export.refs == 0 {
+                let stub = export.stub;
+                *slot = None;
+                home.export_ids.retain(|_, id| *id != remote.id);
+                home.handle.post_release(stub);
+            }
+        }
+    }
+    fn import(&self, core: &mut Core, member: usize, remote: RemoteCap) -> Result<Any, Error> {
+        // take a reference to `remote`, as a capability local to `member`
+        let mut members = self.lock();
+        let local = &mut members[member];
+        if remote.home == member {
+            let stub = match local.exports.get(remote.id) {
+                Some(Some(export)) => export.stub,
+                _ => return Err(E_BOUNDS),  // released
+            };
+            drop(members);
+            let cap = core.ram(stub).y();
+            self.release_ref(remote);  // no proxy holds this reference
+            return Ok(cap);
+        }
+        if let Some(&(_, proxy)) = local.import_tags.get(&(remote.home, remote.id)) {
+            drop(members);
+            self.release_ref(remote);  // the existing proxy holds a reference
+            return Ok(proxy);
+        }
+        let tag = local.imports.len();
+        let proxy = core.reserve_proxy(local.device, Any::fix(tag as isize))?;
+        local.imports.push(Some(remote));
+        local.import_tags.insert((remote.home, remote.id), (tag, proxy));
+        Ok(proxy)
+    }
+    fn remote(&self, member: usize, tag: Any) -> Option<RemoteCap> {
+        let members = self.lock();
+        let tag = tag.fix_num()? as usize;
+        members[member].imports.get(tag).copied().flatten()
+    }
+    fn drop_import(&self, member: usize, tag: Any) {
+        let remote = {
+            let mut members = self.lock();
+            let local = &mut members[member];
+            let remote = tag.fix_num()
+                .and_then(|tag| local.imports.get_mut(tag as usize))
+                .and_then(Option::take);
+            match remote {
+                Some(remote) => {
+                    local.import_tags.remove(&(remote.home, remote.id));
+                    remote
+                },
+                None => return,
+            }
+        };
+        self.release_ref(remote);
+    }
+    fn reference(&self, core: &mut Core, member: usize, cap: Any) -> Result<RemoteCap, Error> {
+        // add a reference to a capability held by `member`
+        let device = self.lock()[member].device;
+        let quad = core.ram(core.cap_to_ptr(cap));
+        if (quad.t() == PROXY_T) && (quad.x() == device) {
+            if let Some(remote) = self.remote(member, quad.y()) {
+                self.add_ref(remote);  // pass on an imported capability
+                return Ok(remote);
+            }
+        }
+        self.export(core, member, cap)
+    }
+    fn marshal(&self, core: &mut Core, member: usize, value: Any) -> Result<Value, Error> {
+        // capabilities are encoded as `ext([home, id])`, each holding a reference
+        oed::from_heap(core, value, &mut |core, cap| {
+            if !cap.is_cap() {
+                return Err(E_NO_TYPE);
+            }
+            let remote = self.reference(core, member, cap)?;
+            let meta = Value::Array(vec![
+                Value::Integer(remote.home as i64),
+                Value::Integer(remote.id as i64),
+            ]);
+            Ok(Value::Extension(Box::new(meta), Vec::new()))
+        })
+    }
+    fn unmarshal(&self, core: &mut Core, member: usize, value: &Value) -> Result<Any, Error> {
+        oed::to_heap(core, value, &mut |core, ext| match decode(ext) {
+            Some(remote) => self.import(core, member, remote),
+            None => Err(E_NO_TYPE),
+        })
+    }
+    fn release_all(&self, value: &Value) {
+        // release the references held by an undelivered message
+        match value {
+            Value::Array(elements) => elements.iter().for_each(|value| self.release_all(value)),
+            Value::Object(props) => props.iter().for_each(|(key, value)| {
+                self.release_all(key);
+                self.release_all(value);
+            }),
+            _ => if let Some(remote) = decode(value) {
+                self.release_ref(remote);
+            },
+        }
+    }
+    fn deliver(&self, core: &mut Core, target: RemoteCap, message: &Value) {
+        // runs on the target's home Core, releasing the message's reference to `target`
+        let stub = match self.lock()[target.home].exports.get(target.id) {
+            Some(Some(export)) => export.stub,
+            _ => return self.release_all(message),  // target released
+        };
+        let cap = core.ram(stub).y();
+        let result = self.unmarshal(core, target.home, message)
+            .and_then(|msg| core.reserve_event(SPONSOR, cap, msg));
+        match result {
+            Ok(evt) => core.event_enqueue(evt),
+            Err(error) => core.call_audit_fn(error, cap),
+        }
+        self.release_ref(target);
+    }
+}
+
+// a Core's connection to a Bridge
+pub struct BridgePort {
+    bridge: Bridge,
+    member: usize,
+}
+
+impl BridgePort {
+    pub fn member(&self) -> usize {
+        self.member
+    }
+    pub fn device(&self) -> Any {
+        self.bridge.lock()[self.member].device
+    }
+    pub fn export(&self, core: &mut Core, cap: Any) -> Result<RemoteCap, Error> {
+        // share `cap` with another Core, which should import it (exactly once)
+        if !cap.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        self.bridge.reference(core, self.member, cap)
+    }
+    pub fn import(&self, core: &mut Core, remote: RemoteCap) -> Result<Any, Error> {
+        // a capability (usually a proxy) for `remote`, on this port's Core
+        self.bridge.import(core, self.member, remote)
+    }
+}
+
+pub struct BridgeDevice {
+    bridge: Bridge,
+    member: usize,
+}
+
+impl Device for BridgeDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let target = core.event_target(ep);
+        let proxy = core.ram(core.cap_to_ptr(target));
+        if proxy.t() != PROXY_T {
+            return Ok(UNDEF);  // ignore messages sent to the device itself
+        }
+        let remote = match self.bridge.remote(self.member, proxy.y()) {
+            Some(remote) => remote,
+            None => return Ok(UNDEF),  // dropped
+        };
+        let message = self.bridge.marshal(core, self.member, core.event_message(ep))?;
+        self.bridge.add_ref(remote);  // until delivered
+        let bridge = self.bridge.clone();
+        let handle = bridge.lock()[remote.home].handle.clone();
+        handle.post_task(move |core: &mut Core| bridge.deliver(core, remote, &message));
+        Ok(UNDEF)
+    }
+    fn drop_proxy(&mut self, core: &mut Core, proxy: Any) {
+        let tag = core.ram(core.cap_to_ptr(proxy)).y();
+        self.bridge.drop_import(self.member, tag);
+    }
+}
+
+fn decode(value: &Value) -> Option<RemoteCap> {
+    // the capability encoded by `ext([home, id])`
+    match value {
+        Value::Extension(meta, _) => match meta.as_ref() {
+            Value::Array(ids) => match ids.as_slice() {
+                &[Value::Integer(home), Value::Integer(id)] => Some(RemoteCap { home: home as usize, id: id as usize }),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+
+    use ::core::cell::RefCell;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    // remembers each message
+    struct Recorder {
+        log: Rc<RefCell<Vec<Any>>>,
+    }
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            self.log.borrow_mut().push(core.event_message(ep));
+            Ok(UNDEF)
+        }
+    }
+
+    // replies to `customer,n` with `n+1`
+    struct Increment;
+    impl Device for Increment {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);
+            let n = core.cdr(msg).get_fix()?;
+            core.reserve_event(SPONSOR, core.car(msg), Any::fix(n + 1))
+        }
+    }
+
+    fn live_exports(bridge: &Bridge) -> Vec<usize> {
+        bridge.lock().iter()
+            .map(|member| member.exports.iter().flatten().count())
+            .collect()
+    }
+
+    fn count_quads(core: &Core, t: Any) -> usize {
+        (RAM_BASE_OFS..core.ram_top().ofs())
+            .filter(|&ofs| core.ram(Any::ram(ofs)).t() == t)
+            .count()
+    }
+
+    #[test]
+    fn bridge_between_cores() {
+        let bridge = Bridge::new();
+        let mut alice = Box::new(Core::default());
+        alice.init();
+        let mut bob = Box::new(Core::default());
+        bob.init();
+        let alice_port = bridge.join(&mut alice).unwrap();
+        let bob_port = bridge.join(&mut bob).unwrap();
+        let increment = alice.register_device(Box::new(Increment)).unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recorder = bob.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+
+        // Bob sends `recorder,n` to Alice's increment, which replies to Bob's recorder
+        let remote = alice_port.export(&mut alice, increment).unwrap();
+        let proxy = bob_port.import(&mut bob, remote).unwrap();
+        bob.reserve_stub(recorder, proxy).unwrap();  // keep `proxy` between messages
+        assert_eq!(PROXY_T, bob.ram(bob.cap_to_ptr(proxy)).t());
+        for n in [41, 6] {
+            let msg = bob.reserve(&Quad::pair_t(recorder, Any::fix(n))).unwrap();
+            let evt = bob.reserve_event(SPONSOR, proxy, msg).unwrap();
+            bob.event_enqueue(evt);
+        }
+        for _ in 0..2 {
+            assert_eq!(ZERO, bob.run_loop(0));
+            assert_eq!(ZERO, alice.run_loop(0));
+        }
+        assert_eq!(vec![Any::fix(42), Any::fix(7)], *log.borrow());
+
+        // Alice's proxy for the recorder was collected, releasing Bob's stub
+        assert_eq!(0, count_quads(&alice, PROXY_T));
+        assert_eq!(vec![1, 0], live_exports(&bridge));
+        assert_eq!(3, count_quads(&bob, STUB_T));  // bridge, recorder, and `proxy`
+
+        // a capability sent home arrives as the original capability
+        let remote = bob_port.export(&mut bob, proxy).unwrap();
+        assert_eq!(increment, alice_port.import(&mut alice, remote).unwrap());
+        assert_eq!(vec![1, 0], live_exports(&bridge));
+    }
+
+    #[test]
+    fn bridge_between_threads() {
+        let bridge = Bridge::new();
+        let done = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let alice = {
+            let bridge = bridge.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut core = Box::new(Core::default());
+                core.init();
+                let port = bridge.join(&mut core).unwrap();
+                let increment = core.register_device(Box::new(Increment)).unwrap();
+                tx.send(port.export(&mut core, increment).unwrap()).unwrap();
+                while !done.load(Ordering::Acquire) {
+                    assert_eq!(ZERO, core.run_loop(0));
+                    thread::yield_now();
+                }
+            })
+        };
+        let mut core = Box::new(Core::default());
+        core.init();
+        let port = bridge.join(&mut core).unwrap();
+        let increment = port.import(&mut core, rx.recv().unwrap()).unwrap();
+        core.reserve_stub(port.device(), increment).unwrap();
+        for n in 0..10 {
+            let reply = core.call(increment, Any::fix(n), SPONSOR).unwrap();
+            let msg = loop {
+                if let Some(msg) = core.await_reply(&reply, 100).unwrap() {
+                    break msg;
+                }
+                thread::yield_now();
+            };
+            assert_eq!(Any::fix(n + 1), msg);
+        }
+        done.store(true, Ordering::Release);
+        alice.join().unwrap();
+    }
+
+}
@@ -0,0 +1,72 @@
+// Wall-clock budgets for sponsors (see `Core::set_sponsor_time_budget`).
+//
+// A budget is only enforced while the embedder provides a clock
+// (see `Core::set_clock_fn`). The start time of each event is recorded
+// when its transaction begins, if its sponsor has a per-event limit.
+
+use alloc::collections::BTreeMap;
+
+use crate::*;
+
+// wall-clock limits for a sponsor, in ticks of the embedder's clock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeBudget {
+    pub deadline: Option<u64>,  // no work is done at (or after) this time
+    pub per_event: Option<u64>,  // maximum time to process each event
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct TimeBudgets {
+    budgets: BTreeMap<usize, TimeBudget>,  // sponsor offset -> budget
+    event_start: BTreeMap<usize, u64>,  // event offset -> start time (for `per_event` budgets)
+}
+
+impl TimeBudgets {
+    pub(crate) const fn new() -> Self {
+        TimeBudgets {
+            budgets: BTreeMap::new(),
+            event_start: BTreeMap::new(),
+        }
+    }
+    pub(crate) fn has(&self, sponsor: usize) -> bool {
+        self.budgets.contains_key(&sponsor)
+    }
+    pub(crate) fn get(&self, sponsor: usize) -> TimeBudget {
+        self.budgets.get(&sponsor).copied().unwrap_or_default()
+    }
+    pub(crate) fn set(&mut self, sponsor: usize, budget: TimeBudget) {
+        if budget == TimeBudget::default() {
+            self.budgets.remove(&sponsor);
+        } else {
+            self.budgets.insert(sponsor, budget);
+        }
+    }
+    pub(crate) fn times_events(&self, sponsor: usize) -> bool {
+        self.budgets.get(&sponsor).is_some_and(|budget| budget.per_event.is_some())
+    }
+    pub(crate) fn start_event(&mut self, ep: usize, now: u64) {
+        self.event_start.insert(ep, now);
+    }
+    pub(crate) fn check(&self, sponsor: usize, ep: usize, now: u64) -> Result<(), Error> {
+        let Some(budget) = self.budgets.get(&sponsor) else {
+            return Ok(());
+        };
+        if budget.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(E_TIME_LIM);  // Sponsor deadline passed
+        }
+        if let (Some(limit), Some(&start)) = (budget.per_event, self.event_start.get(&ep)) {
+            if now.saturating_sub(start) > limit {
+                return Err(E_TIME_LIM);  // Sponsor per-event time limit reached
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn forget(&mut self, ofs: usize, sponsor: bool) {
+        if sponsor {
+            self.budgets.remove(&ofs);
+        }
+        if !self.event_start.is_empty() {
+            self.event_start.remove(&ofs);
+        }
+    }
+}
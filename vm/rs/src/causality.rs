@@ -0,0 +1,89 @@
+// Causality tracking for events (see `Core::set_causality`).
+//
+// Each event is given an id, and the id of the event that caused it.
+// The causes of recent events are retained, so a chain of causes may be
+// followed back after the events themselves have been collected.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+// where an event came from, when causality tracking is on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    pub id: u64,  // events are numbered as they are created (or first enqueued)
+    pub cause: Option<u64>,  // the event being processed when this one was sent
+}
+
+const CAUSE_HISTORY: usize = 4096;  // causes retained for `causal_chain`
+
+#[derive(Clone, Default)]
+pub(crate) struct Causality {
+    enabled: bool,
+    provenance: BTreeMap<usize, Provenance>,  // event offset -> provenance
+    cause_log: BTreeMap<u64, Option<u64>>,  // event id -> cause (recent events only)
+    next: u64,  // id of the next event
+}
+
+impl Causality {
+    pub(crate) const fn new() -> Self {
+        Causality {
+            enabled: false,
+            provenance: BTreeMap::new(),
+            cause_log: BTreeMap::new(),
+            next: 0,
+        }
+    }
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.provenance.clear();
+            self.cause_log.clear();
+        }
+    }
+    pub(crate) fn provenance(&self, ofs: usize) -> Option<Provenance> {
+        self.provenance.get(&ofs).copied()
+    }
+    pub(crate) fn note(&mut self, ofs: usize, cause: Option<u64>) {
+        let id = match self.provenance.get(&ofs) {
+            Some(known) => known.id,
+            None => {
+                self.next += 1;
+                self.next - 1
+            },
+        };
+        self.provenance.insert(ofs, Provenance { id, cause });
+        self.cause_log.insert(id, cause);
+        if self.cause_log.len() > CAUSE_HISTORY {
+            self.cause_log.pop_first();
+        }
+    }
+    pub(crate) fn inherit(&mut self, cause: usize, ofs: usize) {
+        if self.enabled {
+            let cause = self.provenance(cause).map(|cause| cause.id);
+            self.note(ofs, cause);
+        }
+    }
+    pub(crate) fn enqueued(&mut self, ofs: usize) {
+        if self.enabled && !self.provenance.contains_key(&ofs) {
+            self.note(ofs, None);  // from the host
+        }
+    }
+    pub(crate) fn chain(&self, id: u64) -> Vec<u64> {
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            chain.push(id);
+            next = self.cause_log.get(&id).copied().flatten();
+        }
+        chain
+    }
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.provenance.is_empty() && self.cause_log.is_empty()
+    }
+    pub(crate) fn forget(&mut self, ofs: usize) {
+        if !self.provenance.is_empty() {
+            self.provenance.remove(&ofs);
+        }
+    }
+}
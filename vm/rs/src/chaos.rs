@@ -0,0 +1,105 @@
+// Helpers for testing actor programs under the `Chaos` scheduling policy,
+// which delivers events (and interleaves continuations) in a random order.
+//
+// Each run is repeatable, given its seed. When a run fails, its seed is
+// reported, and the failing run may be replayed by setting `UFORK_CHAOS_SEED`.
+
+use alloc::boxed::Box;
+
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::*;
+
+pub const SEED_VAR: &str = "UFORK_CHAOS_SEED";
+
+pub fn check_seeds<F: FnMut(&mut Core)>(seeds: Range<u64>, mut test: F) {
+    // call `test` once for each seed, with a Core using the `Chaos` policy.
+    // if `SEED_VAR` names a seed, only that seed is used.
+    let replay = std::env::var(SEED_VAR).ok().and_then(|var| var.parse().ok());
+    let seeds = match replay {
+        Some(seed) => seed..(seed + 1),
+        None => seeds,
+    };
+    for seed in seeds {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut core = Box::new(Core::default());
+            core.init();
+            core.set_scheduling_policy(SchedulingPolicy::Chaos { seed });
+            test(&mut core);
+        }));
+        if let Err(cause) = result {
+            std::eprintln!("chaos: failed with seed {} (replay with {}={})", seed, SEED_VAR, seed);
+            panic::resume_unwind(cause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use ::core::cell::RefCell;
+
+    use super::*;
+
+    struct Recorder {
+        log: Rc<RefCell<Vec<Any>>>,
+    }
+
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            self.log.borrow_mut().push(core.event_message(ep));
+            Ok(UNDEF)
+        }
+    }
+
+    fn deliver(core: &mut Core, count: isize) -> Vec<Any> {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        for n in 0..count {
+            let evt = core.reserve_event(SPONSOR, recorder, Any::fix(n)).unwrap();
+            core.event_enqueue(evt);
+        }
+        assert_eq!(ZERO, core.run_loop(0));
+        let order = log.borrow().to_vec();
+        order
+    }
+
+    #[test]
+    fn chaos_seeds() {
+        let mut orders = Vec::new();
+        check_seeds(0..8, |core| orders.push(deliver(core, 16)));
+        let fifo: Vec<Any> = (0..16).map(Any::fix).collect();
+        for order in &orders {
+            let mut sorted = order.clone();
+            sorted.sort_by_key(|msg| msg.fix_num());
+            assert_eq!(fifo, sorted);  // every event is delivered once
+        }
+        assert!(orders.iter().any(|order| *order != fifo));
+        assert!(orders.iter().any(|order| *order != orders[0]));
+
+        // the same seed gives the same order
+        let mut replay = Vec::new();
+        check_seeds(5..6, |core| {
+            assert_eq!(SchedulingPolicy::Chaos { seed: 5 }, core.scheduling_policy());
+            replay.push(deliver(core, 16));
+        });
+        assert_eq!(orders[5], replay[0]);
+
+        // a failure is reported (and propagated)
+        let mut seeds = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            check_seeds(0..8, |core| {
+                let SchedulingPolicy::Chaos { seed } = core.scheduling_policy() else { panic!() };
+                seeds.push(seed);
+                assert!(seed < 3);
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(vec![0, 1, 2, 3], seeds);
+    }
+
+}
@@ -0,0 +1,115 @@
+// Results posted by helper threads, for a device to collect in `poll`.
+//
+// Blocking `std::net` calls run on helper threads, which post what they
+// produce here. Each post wakes the waker registered by an async host
+// (see `Device::register_waker`), so a Core waiting only on sockets parks.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use ::core::task::Waker;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+struct Posted<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>,
+}
+
+pub(crate) struct Completions<T> {
+    posted: Arc<Mutex<Posted<T>>>,
+}
+
+impl<T> Clone for Completions<T> {
+    fn clone(&self) -> Self {
+        Completions { posted: self.posted.clone() }
+    }
+}
+
+impl<T> Default for Completions<T> {
+    fn default() -> Self {
+        Completions { posted: Arc::new(Mutex::new(Posted { queue: VecDeque::new(), waker: None })) }
+    }
+}
+
+impl<T> Completions<T> {
+    pub(crate) fn post(&self, completion: T) {
+        let waker = {
+            let mut posted = self.lock();
+            posted.queue.push_back(completion);
+            posted.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+    pub(crate) fn take(&self) -> Option<T> {
+        self.lock().queue.pop_front()
+    }
+    pub(crate) fn register(&self, waker: &Waker) {
+        // wake `waker` at the next post, or now if one is waiting
+        let mut posted = self.lock();
+        if posted.queue.is_empty() {
+            posted.waker = Some(waker.clone());
+        } else {
+            waker.wake_by_ref();
+        }
+    }
+    fn lock(&self) -> MutexGuard<'_, Posted<T>> {
+        self.posted.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// accepts connections on a helper thread, until stopped (or dropped),
+// then posts `done` (so its listener's number is not reused too soon)
+pub(crate) struct Acceptor {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Acceptor {
+    pub(crate) fn spawn<T, F>(socket: TcpListener, completions: &Completions<T>, accepted: F, done: T) -> io::Result<Acceptor>
+    where
+        T: Send + 'static,
+        F: Fn(TcpStream) -> T + Send + 'static,
+    {
+        let address = socket.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let completions = completions.clone();
+        let stopping = stopped.clone();
+        thread::spawn(move || {
+            while let Ok((stream, _addr)) = socket.accept() {
+                if stopping.load(Ordering::Acquire) {
+                    break;
+                }
+                completions.post(accepted(stream));
+            }
+            completions.post(done);
+        });
+        Ok(Acceptor { address, stopped })
+    }
+    pub(crate) fn stop(&self) {
+        // `accept` can't be interrupted, so connect to wake it up
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let mut address = self.address;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let _ = TcpStream::connect(address);
+    }
+}
+
+impl Drop for Acceptor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,397 @@
+// Typed conversion between Rust values and uFork values.
+//
+// Conversions allocate with `reserve`, so they are suitable for host code
+// and devices, but are not charged to any sponsor.
+//
+//  Rust            | uFork
+// -----------------|-----------------------------
+// integers         | fixnum (31 bits)
+// `bool`           | `#t`, `#f`
+// `()`             | `#nil`
+// `Option<T>`      | `#?` or value
+// tuples           | list with a dotted tail (`a,b,c`)
+// `Vec<T>`, `[T]`  | list (`a,b,c,#nil`), even for `u8`
+// `BTreeMap<K,V>`  | dictionary (`DICT_T` chain)
+// `Blob`           | blob capability (via the `BlobDevice`)
+// `Cap`            | capability
+// `Any`            | any value (unchecked)
+//
+// Byte slices are not blobs: `[u8]` and `Vec<u8>` are lists of fixnums,
+// like any other `[T]`, since they can't be told apart without specialization.
+// Wrap bytes in a `Blob` to exchange them as a blob.
+//
+// Structs and enums may `#[derive(ToValue, FromValue)]` with the "derive" feature.
+
+use alloc::collections::BTreeMap;
+use alloc::collections::btree_map::Entry;
+use alloc::vec::Vec;
+
+use ::core::cell::RefCell;
+
+use crate::*;
+use crate::blob_dev::BlobDevice;
+
+const FIX_MIN: isize = -(1 << 30);
+const FIX_MAX: isize = (1 << 30) - 1;
+
+pub trait ToValue {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error>;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(heap: &Heap, value: Any) -> Result<Self, Error>;
+}
+
+// the context for conversions, with optional access to blobs
+pub struct Heap<'a> {
+    core: &'a mut Core,
+    blob_dev: Option<&'a RefCell<BlobDevice>>,
+}
+
+impl<'a> Heap<'a> {
+    pub fn new(core: &'a mut Core) -> Heap<'a> {
+        Heap {
+            core,
+            blob_dev: None,
+        }
+    }
+    pub fn with_blobs(self, blob_dev: &'a RefCell<BlobDevice>) -> Heap<'a> {
+        Heap {
+            core: self.core,
+            blob_dev: Some(blob_dev),
+        }
+    }
+    pub fn core(&self) -> &Core {
+        self.core
+    }
+    pub fn core_mut(&mut self) -> &mut Core {
+        self.core
+    }
+    pub fn to_value<T: ToValue + ?Sized>(&mut self, value: &T) -> Result<Any, Error> {
+        value.to_value(self)
+    }
+    pub fn from_value<T: FromValue>(&self, value: Any) -> Result<T, Error> {
+        T::from_value(self, value)
+    }
+    pub fn cons(&mut self, car: Any, cdr: Any) -> Result<Any, Error> {
+        self.core.reserve(&Quad::pair_t(car, cdr))
+    }
+    pub fn uncons(&self, value: Any) -> Result<(Any, Any), Error> {
+        if !self.core.typeq(PAIR_T, value) {
+            return Err(E_NO_TYPE);
+        }
+        Ok((self.core.car(value), self.core.cdr(value)))
+    }
+}
+
+// a blob's contents
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Blob(pub Vec<u8>);
+
+// a capability (actor or proxy)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cap(pub Any);
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        (**self).to_value(heap)
+    }
+}
+
+impl ToValue for Any {
+    fn to_value(&self, _heap: &mut Heap) -> Result<Any, Error> {
+        Ok(*self)
+    }
+}
+impl FromValue for Any {
+    fn from_value(_heap: &Heap, value: Any) -> Result<Self, Error> {
+        Ok(value)
+    }
+}
+
+impl ToValue for Cap {
+    fn to_value(&self, _heap: &mut Heap) -> Result<Any, Error> {
+        if !self.0.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        Ok(self.0)
+    }
+}
+impl FromValue for Cap {
+    fn from_value(_heap: &Heap, value: Any) -> Result<Self, Error> {
+        if !value.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        Ok(Cap(value))
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self, _heap: &mut Heap) -> Result<Any, Error> {
+        Ok(if *self { TRUE } else { FALSE })
+    }
+}
+impl FromValue for bool {
+    fn from_value(_heap: &Heap, value: Any) -> Result<Self, Error> {
+        match value {
+            TRUE => Ok(true),
+            FALSE => Ok(false),
+            _ => Err(E_NO_TYPE),
+        }
+    }
+}
+
+macro_rules! fixnum_conversions {
+    ($($int:ty),*) => {$(
+        impl ToValue for $int {
+            fn to_value(&self, _heap: &mut Heap) -> Result<Any, Error> {
+                match isize::try_from(*self) {
+                    Ok(n) if (FIX_MIN..=FIX_MAX).contains(&n) => Ok(Any::fix(n)),
+                    _ => Err(E_BOUNDS),
+                }
+            }
+        }
+        impl FromValue for $int {
+            fn from_value(_heap: &Heap, value: Any) -> Result<Self, Error> {
+                <$int>::try_from(value.get_fix()?).map_err(|_| E_BOUNDS)
+            }
+        }
+    )*};
+}
+fixnum_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl ToValue for () {
+    fn to_value(&self, _heap: &mut Heap) -> Result<Any, Error> {
+        Ok(NIL)
+    }
+}
+impl FromValue for () {
+    fn from_value(_heap: &Heap, value: Any) -> Result<Self, Error> {
+        if value != NIL {
+            return Err(E_NO_TYPE);
+        }
+        Ok(())
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        match self {
+            Some(value) => value.to_value(heap),
+            None => Ok(UNDEF),
+        }
+    }
+}
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(heap: &Heap, value: Any) -> Result<Self, Error> {
+        if value == UNDEF {
+            return Ok(None);
+        }
+        Ok(Some(T::from_value(heap, value)?))
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($($init:ident)*; $last:ident) => {
+        impl<$($init: ToValue,)* $last: ToValue> ToValue for ($($init,)* $last,) {
+            #[allow(non_snake_case)]
+            fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+                let ($($init,)* $last,) = self;
+                Ok(tuple_conversions!(@encode heap; $($init)* $last))
+            }
+        }
+        impl<$($init: FromValue,)* $last: FromValue> FromValue for ($($init,)* $last,) {
+            #[allow(non_snake_case)]
+            fn from_value(heap: &Heap, value: Any) -> Result<Self, Error> {
+                let rest = value;
+                $(
+                    let (item, rest) = heap.uncons(rest)?;
+                    let $init = $init::from_value(heap, item)?;
+                )*
+                Ok(($($init,)* $last::from_value(heap, rest)?,))
+            }
+        }
+    };
+    (@encode $heap:ident; $last:ident) => {
+        $last.to_value($heap)?
+    };
+    (@encode $heap:ident; $head:ident $($tail:ident)+) => {{
+        let item = $head.to_value($heap)?;
+        let rest = tuple_conversions!(@encode $heap; $($tail)+);
+        $heap.cons(item, rest)?
+    }};
+}
+tuple_conversions!(A; B);
+tuple_conversions!(A B; C);
+tuple_conversions!(A B C; D);
+tuple_conversions!(A B C D; E);
+tuple_conversions!(A B C D E; F);
+tuple_conversions!(A B C D E F; G);
+tuple_conversions!(A B C D E F G; H);
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        let mut list = NIL;
+        for item in self.iter().rev() {
+            let item = item.to_value(heap)?;
+            list = heap.cons(item, list)?;
+        }
+        Ok(list)
+    }
+}
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        self.as_slice().to_value(heap)
+    }
+}
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(heap: &Heap, value: Any) -> Result<Self, Error> {
+        let mut items = Vec::new();
+        let mut list = value;
+        while list != NIL {
+            let (item, rest) = heap.uncons(list)?;
+            items.push(T::from_value(heap, item)?);
+            list = rest;
+        }
+        Ok(items)
+    }
+}
+
+impl<K: ToValue, V: ToValue> ToValue for BTreeMap<K, V> {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        let mut dict = NIL;
+        for (key, value) in self.iter().rev() {
+            let key = key.to_value(heap)?;
+            let value = value.to_value(heap)?;
+            dict = heap.core.reserve(&Quad::dict_t(key, value, dict))?;
+        }
+        Ok(dict)
+    }
+}
+impl<K: FromValue + Ord, V: FromValue> FromValue for BTreeMap<K, V> {
+    fn from_value(heap: &Heap, value: Any) -> Result<Self, Error> {
+        let mut map = BTreeMap::new();
+        let mut dict = value;
+        while dict != NIL {
+            if !heap.core.typeq(DICT_T, dict) {
+                return Err(E_NO_TYPE);
+            }
+            let entry = *heap.core.mem(dict);
+            let key = K::from_value(heap, entry.x())?;
+            if let Entry::Vacant(slot) = map.entry(key) {  // earlier entries hide later ones
+                slot.insert(V::from_value(heap, entry.y())?);
+            }
+            dict = entry.z();
+        }
+        Ok(map)
+    }
+}
+
+impl ToValue for Blob {
+    fn to_value(&self, heap: &mut Heap) -> Result<Any, Error> {
+        let blob_dev = heap.blob_dev.ok_or(E_FAIL)?;  // no blob device
+        blob_dev.borrow_mut().alloc_blob(heap.core, &self.0)
+    }
+}
+impl FromValue for Blob {
+    fn from_value(heap: &Heap, value: Any) -> Result<Self, Error> {
+        let blob_dev = heap.blob_dev.ok_or(E_FAIL)?;  // no blob device
+        let data = blob_dev.borrow().blob_data(heap.core, value)?.to_vec();
+        Ok(Blob(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use alloc::vec;
+
+    use super::*;
+    use crate::{FromValue, ToValue};
+
+    #[derive(Debug, PartialEq, ToValue, FromValue)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, PartialEq, ToValue, FromValue)]
+    struct Labeled<T>(Option<T>, Vec<Point>);
+
+    #[derive(Debug, PartialEq, ToValue, FromValue)]
+    enum Request {
+        Ping,
+        Read { customer: Cap, offset: usize },
+        Write(Cap, Blob),
+    }
+
+    fn round_trip<T: ToValue + FromValue>(heap: &mut Heap, value: &T) -> T {
+        let value = heap.to_value(value).unwrap();
+        heap.from_value(value).unwrap()
+    }
+
+    #[test]
+    fn convert_builtin_types() {
+        let mut core = Core::default();
+        core.init();
+        let mut heap = Heap::new(&mut core);
+        assert_eq!(Any::fix(-42), heap.to_value(&-42_i8).unwrap());
+        assert_eq!(Ok(1 << 29), heap.from_value::<u32>(Any::fix(1 << 29)));
+        assert_eq!(Err(E_BOUNDS), heap.to_value(&(1_u32 << 30)));
+        assert_eq!(Err(E_BOUNDS), heap.from_value::<u8>(MINUS_1));
+        assert_eq!(Err(E_NOT_FIX), heap.from_value::<i64>(TRUE));
+        assert_eq!(Err(E_NO_TYPE), heap.from_value::<bool>(ZERO));
+        assert_eq!(Err(E_NOT_CAP), heap.from_value::<Cap>(NIL));
+        assert_eq!(Err(E_NOT_CAP), heap.to_value(&Cap(NIL)));
+        assert_eq!(UNDEF, heap.to_value(&None::<bool>).unwrap());
+        assert_eq!(Some(false), round_trip(&mut heap, &Some(false)));
+
+        // `1,2,3` vs. `1,2,3,#nil`
+        let tuple = heap.to_value(&(1, 2, 3)).unwrap();
+        assert_eq!(PLUS_3, heap.core().nth(tuple, MINUS_2));
+        let list = heap.to_value(&vec![1, 2, 3]).unwrap();
+        assert_eq!(NIL, heap.core().nth(list, MINUS_3));
+        assert_eq!(Err(E_NO_TYPE), heap.from_value::<(i8, i8, i8, i8)>(tuple));
+        assert_eq!(Err(E_NO_TYPE), heap.from_value::<Vec<i8>>(tuple));
+        assert_eq!(vec![(true, ()), (false, ())], round_trip(&mut heap, &vec![(true, ()), (false, ())]));
+
+        let mut map = BTreeMap::new();
+        map.insert(3, vec![TRUE]);
+        map.insert(-5, vec![]);
+        let dict = heap.to_value(&map).unwrap();
+        assert_eq!(NIL, heap.core().dict_get(dict, Any::fix(-5)));
+        let dict = heap.core_mut().reserve(&Quad::dict_t(PLUS_3, NIL, dict)).unwrap();  // hides `3`
+        map.insert(3, vec![]);
+        assert_eq!(Ok(map), heap.from_value(dict));
+    }
+
+    #[test]
+    fn convert_derived_types() {
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let mut core = Core::default();
+        core.init();
+        core.install_device(BLOB_DEV, Box::new(blob_dev.clone()));
+        let mut heap = Heap::new(&mut core).with_blobs(&blob_dev);
+
+        let point = heap.to_value(&Point { x: 1, y: 2 }).unwrap();
+        assert_eq!((PLUS_1, PLUS_2), heap.uncons(point).unwrap());
+        let labeled = Labeled(Some(true), vec![Point { x: -1, y: 0 }]);
+        assert_eq!(labeled, round_trip(&mut heap, &labeled));
+        assert_eq!(Labeled(None::<u8>, vec![]), round_trip(&mut heap, &Labeled(None, vec![])));
+
+        assert_eq!(ZERO, heap.to_value(&Request::Ping).unwrap());
+        let read = Request::Read { customer: Cap(BLOB_DEV), offset: 7 };
+        let value = heap.to_value(&read).unwrap();
+        assert_eq!(PLUS_1, heap.core().car(value));
+        assert_eq!(read, round_trip(&mut heap, &read));
+        let write = Request::Write(Cap(BLOB_DEV), Blob(b"hello".to_vec()));
+        assert_eq!(write, round_trip(&mut heap, &write));
+        assert_eq!(Err(E_BOUNDS), heap.from_value::<Request>(PLUS_1));
+        assert_eq!(Err(E_NO_TYPE), heap.from_value::<Request>(point));
+        let bad_write = heap.to_value(&(2, 5, 6)).unwrap();
+        assert_eq!(Err(E_NOT_CAP), heap.from_value::<Request>(bad_write));
+        assert_eq!(Err(E_FAIL), Heap::new(&mut core).to_value(&Blob(vec![])));
+    }
+
+}
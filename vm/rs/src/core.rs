@@ -1,8 +1,25 @@
 // uFork virtual CPU core
 
 use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ::core::cmp;
+use ::core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::*;
+use crate::core_handle::{CoreHandle, Inbound, Inbox};
+use crate::budget::TimeBudgets;
+use crate::causality::Causality;
+use crate::sched::{Scheduler, FAIR_SHARE};
+use crate::trace::{Sent, Tracer, TxnOutcome, TxnRecord};
+
+pub use crate::budget::TimeBudget;
+pub use crate::causality::Provenance;
+pub use crate::sched::SchedulingPolicy;
 
 pub const MEMORY: Any       = Any::ram(0x0);
 pub const DDEQUE: Any       = Any::ram(0x1);
@@ -12,16 +29,191 @@ pub const TIMER_DEV: Any    = Any::cap(0x4);
 pub const IO_DEV: Any       = Any::cap(0x5);
 pub const BLOB_DEV: Any     = Any::cap(0x6);
 pub const RANDOM_DEV: Any   = Any::cap(0x7);
-pub const RSVD_8_DEV: Any   = Any::cap(0x8);
-pub const RSVD_9_DEV: Any   = Any::cap(0x9);
-pub const RSVD_A_DEV: Any   = Any::cap(0xA);
-pub const RSVD_B_DEV: Any   = Any::cap(0xB);
-pub const RSVD_C_DEV: Any   = Any::cap(0xC);
-pub const HOST_DEV: Any     = Any::cap(0xD);
-pub const QUOTA: Any        = Any::ram(0xE);
-pub const SPONSOR: Any      = Any::ram(0xF);
+pub const HOST_DEV: Any     = Any::cap(0x8);
+pub const QUOTA: Any        = Any::ram(0x9);
+pub const SPONSOR: Any      = Any::ram(0xA);
+
+pub const RAM_BASE_OFS: usize = 0xB;  // RAM offsets below this value are reserved
+
+pub type StepFn = Box<dyn FnMut(&mut Core)>;  // called at each safe point between run-loop steps
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReplyState {
+    Pending,
+    Received(Any),  // stub holding the reply message
+    Dropped,  // the customer was collected without a reply
+}
+
+const REPLY_BATCH: usize = 64;  // run-loop steps between checks for a reply, see `Core::await_reply`
+
+// a pending reply to a message sent by `Core::call`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReplyHandle {
+    id: isize,
+    customer: Any,
+}
+
+impl ReplyHandle {
+    pub fn customer(&self) -> Any {
+        self.customer
+    }
+}
+
+// sponsor resource limits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub memory: isize,
+    pub events: isize,
+    pub cycles: isize,
+}
+
+impl Quota {
+    pub const ROOT: Quota = Quota { memory: 4096, events: 256, cycles: 8192 };  // default root sponsor quota
+    pub const UNLIMITED: Quota = Quota { memory: FIX_MAX, events: FIX_MAX, cycles: FIX_MAX };
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota::ROOT
+    }
+}
+
+const FIX_MAX: isize = 0x3FFF_FFFF;  // largest fixnum (31 bits)
+
+// a snapshot of a sponsor, as reported by `Core::sponsors`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SponsorInfo {
+    pub sponsor: Any,
+    pub parent: Any,  // the sponsor that created this one (`#?` for the root sponsor)
+    pub quota: Quota,
+    pub signal: Any,  // see `run_loop`
+    pub waiting: usize,  // number of events deferred while suspended
+}
+
+// cells charged to a sponsor, see `Core::sponsor_alloc`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Charges {
+    live: usize,  // cells not yet freed
+    refundable: usize,  // cells charged since the memory quota was last set
+}
+
+// how dict operations match keys (see `Core::dict_get_by`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyMatch {
+    #[default]
+    Identity,  // the same value (as `dict has`)
+    Structural { max_cells: usize },  // equal values (see `Core::compare`)
+}
+
+// the classes of values, in the order given by `Core::compare`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Fixnum,
+    Atom,  // by identity
+    Pair,
+    Dict,
+    Proxy(Raw),  // by device, then by value (if the device can compare them)
+    Actor,  // by identity
+}
+
+// a copy of the heap (and related bookkeeping), see `Core::restore`
+#[derive(Clone)]
+pub struct Snapshot {
+    quad_ram: Vec<Quad>,
+    gc_addr: Any,
+    gc_phase: GcPhase,
+    gc_curr: GcColor,
+    gc_prev: GcColor,
+    gc_marks: Vec<GcColor>,
+    gc_owners: Vec<Any>,
+    sponsor_charges: BTreeMap<usize, Charges>,
+    device_proxies: BTreeMap<usize, BTreeSet<usize>>,
+    reply_next: isize,
+    replies: BTreeMap<isize, ReplyState>,
+    books: Books,
+}
+
+// bookkeeping kept beside the heap, for events and sponsors
+#[derive(Clone, Default)]
+struct Books {
+    sched: Scheduler,
+    causes: Causality,
+    budgets: TimeBudgets,
+}
+
+impl Books {
+    const fn new() -> Self {
+        Books {
+            sched: Scheduler::new(),
+            causes: Causality::new(),
+            budgets: TimeBudgets::new(),
+        }
+    }
+    fn forget(&mut self, ofs: usize, sponsor: bool) {
+        // called as each cell is freed
+        self.sched.forget(ofs, sponsor);
+        self.causes.forget(ofs);
+        self.budgets.forget(ofs, sponsor);
+    }
+}
+
+// the resource exhausted by the root sponsor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaKind {
+    Memory,  // `E_MEM_LIM`
+    Events,  // `E_MSG_LIM`
+    Cycles,  // `E_CPU_LIM`
+    Time,  // `E_TIME_LIM`
+}
 
-pub const RAM_BASE_OFS: usize = 0x10;  // RAM offsets below this value are reserved
+// why `Core::run` returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    Idle,  // no more work
+    StepLimit,  // more work remains
+    RootQuotaExhausted { kind: QuotaKind },
+    Fatal { error: Error },  // any other root sponsor signal
+    WaitingOnDevices,  // no more work, until devices are polled
+    Interrupted,  // stopped by an `InterruptHandle` (more work may remain)
+}
+
+// what `Core::run` did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunReport {
+    pub outcome: RunOutcome,
+    pub steps: usize,  // run-loop iterations
+    pub instructions: usize,  // instructions attempted
+    pub events: usize,  // events dispatched (deferred events are not counted)
+}
+
+// makes `run_loop` return at the next step, from another thread (or a signal handler)
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+    pub fn is_interrupted(&self) -> bool {
+        self.flag.load(Ordering::Acquire)
+    }
+    fn take(&self) -> bool {
+        // clear a pending interrupt, reporting if there was one
+        self.flag.load(Ordering::Relaxed) && self.flag.swap(false, Ordering::AcqRel)
+    }
+}
+
+// how the root sponsor is replenished by `run_loop`.
+// subordinate sponsors are never replenished by the run-loop.
+// when the root time budget runs out (`E_TIME_LIM`), replenishing lifts it.
+pub enum RootSponsorPolicy {
+    Fixed,  // the host replenishes the root sponsor (with `set_sponsor_*`)
+    RefillPerCall,  // the root quota is restored at the start of each `run_loop`
+    Unlimited,  // limits are never reached (the `limit` argument still applies)
+    Callback(Box<dyn FnMut(Error, Quota) -> Option<Quota>>),  // (error, remaining) -> replacement, or `None` to stop
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum GcStrategy {
@@ -54,7 +246,7 @@ const QUAD_ROM_MAX: usize = 1<<13;  // 8K quad-cells of ROM (FPGA size)
 //const QUAD_RAM_MAX: usize = 1<<8;  // 256 quad-cells of RAM
 //const QUAD_RAM_MAX: usize = 1<<10;  // 1K quad-cells of RAM
 const QUAD_RAM_MAX: usize = 1<<12;  // 4K quad-cells of RAM (FPGA size)
-const DEVICE_MAX: usize = 12;  // number of Core devices
+const DEVICE_FIXED: usize = 7;  // number of fixed Core devices, `DEBUG_DEV` through `HOST_DEV`
 
 pub struct Core {
     quad_rom:   [Quad; QUAD_ROM_MAX],
@@ -66,10 +258,25 @@ pub struct Core {
     gc_curr:    GcColor,
     gc_prev:    GcColor,
     gc_marks:   [GcColor; QUAD_RAM_MAX],
-    device:     [Option<Box<dyn Device>>; DEVICE_MAX],
+    gc_owners:  [Any; QUAD_RAM_MAX],  // sponsor charged for each cell (or `#?`)
+    sponsor_charges: BTreeMap<usize, Charges>,  // sponsor offset -> cells charged
+    device:     Vec<Option<Box<dyn Device>>>,
+    device_stub: Vec<Any>,  // GC root for each registered device (UNDEF for fixed devices)
+    device_proxies: BTreeMap<usize, BTreeSet<usize>>,  // registered device id -> proxy offsets
     txn_fn:     Option<Box<dyn Fn(Any, Any)>>,
     audit_fn:   Option<Box<dyn Fn(Any, Any)>>,
     audit_err:  Option<Error>,
+    reply_dev:  Any,  // device for `call` customers (registered on first use)
+    reply_next: isize,
+    replies:    BTreeMap<isize, ReplyState>,
+    root_quota: Quota,
+    root_policy: RootSponsorPolicy,
+    clock_fn:   Option<Box<dyn Fn() -> u64>>,
+    interrupt:  Option<InterruptHandle>,
+    inbox:      Option<Arc<Inbox>>,  // work posted by a `CoreHandle`
+    books:      Books,  // scheduling, causality, and time budgets
+    step_fn:    Option<StepFn>,  // delivers host inputs between steps
+    tracer:     Tracer,
 }
 
 impl Default for Core {
@@ -90,23 +297,25 @@ impl Core {
             gc_curr: GcColor::GenX,
             gc_prev: GcColor::GenY,
             gc_marks: [ GcColor::Free; QUAD_RAM_MAX ],
-            device: [
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-                None,
-            ],
+            gc_owners: [ UNDEF; QUAD_RAM_MAX ],
+            sponsor_charges: BTreeMap::new(),
+            device: Vec::new(),
+            device_stub: Vec::new(),
+            device_proxies: BTreeMap::new(),
             txn_fn: None,
             audit_fn: None,
             audit_err: None,
+            reply_dev: UNDEF,
+            reply_next: 0,
+            replies: BTreeMap::new(),
+            root_quota: Quota::ROOT,
+            root_policy: RootSponsorPolicy::Fixed,
+            clock_fn: None,
+            interrupt: None,
+            inbox: None,
+            books: Books::new(),
+            step_fn: None,
+            tracer: Tracer::new(),
         }
     }
 
@@ -142,18 +351,20 @@ impl Core {
         self.quad_ram[IO_DEV.ofs()]      = Quad::actor_t(PLUS_3, NIL, UNDEF);  // i/o device #3
         self.quad_ram[BLOB_DEV.ofs()]    = Quad::actor_t(PLUS_4, NIL, UNDEF);  // blob device #4
         self.quad_ram[RANDOM_DEV.ofs()]  = Quad::actor_t(PLUS_5, NIL, UNDEF);  // random device #5
-        self.quad_ram[RSVD_8_DEV.ofs()]  = Quad::actor_t(PLUS_6, NIL, UNDEF);  // --reserved-- device #6
-        self.quad_ram[RSVD_9_DEV.ofs()]  = Quad::actor_t(PLUS_7, NIL, UNDEF);  // --reserved-- device #7
-        self.quad_ram[RSVD_A_DEV.ofs()]  = Quad::actor_t(Any::fix(8), NIL, UNDEF);  // --reserved-- device #8
-        self.quad_ram[RSVD_B_DEV.ofs()]  = Quad::actor_t(Any::fix(9), NIL, UNDEF);  // --reserved-- device #9
-        self.quad_ram[RSVD_C_DEV.ofs()]  = Quad::actor_t(Any::fix(10), NIL, UNDEF);  // --reserved-- device #10
-        self.quad_ram[HOST_DEV.ofs()]    = Quad::actor_t(Any::fix(11), NIL, UNDEF);  // host extension device #11
+        self.quad_ram[HOST_DEV.ofs()]    = Quad::actor_t(PLUS_6, NIL, UNDEF);  // host extension device #6
         self.quad_ram[QUOTA.ofs()]       = Quad::quota(
-                                            Any::fix(4096),
-                                            Any::fix(256),
-                                            Any::fix(8192));  // root sponsor quota
+                                            Any::fix(self.root_quota.memory),
+                                            Any::fix(self.root_quota.events),
+                                            Any::fix(self.root_quota.cycles));  // root sponsor quota
         self.quad_ram[SPONSOR.ofs()]     = Quad::sponsor_t(QUOTA, UNDEF);  // root configuration sponsor
 
+        /*
+         * Device table (fixed devices are installed later)
+         */
+        if self.device.len() < DEVICE_FIXED {
+            self.device.resize_with(DEVICE_FIXED, || None);
+            self.device_stub.resize(DEVICE_FIXED, UNDEF);
+        }
     }
 
     pub fn install_device(&mut self, cap: Any, mut dev: Box<dyn Device>) {
@@ -163,6 +374,147 @@ impl Core {
         }
     }
 
+    pub fn register_device(&mut self, mut dev: Box<dyn Device>) -> Result<Any, Error> {
+        // allocate a new device actor, beyond the fixed devices.
+        // the id of an unregistered device is reused, since nothing
+        // refers to it any more (see `unregister_device`).
+        let id = (DEVICE_FIXED..self.device.len())
+            .find(|&id| self.device[id].is_none() && (self.device_stub[id] == UNDEF))
+            .unwrap_or(self.device.len());
+        let ptr = self.reserve(&Quad::actor_t(Any::fix(id as isize), NIL, UNDEF))?;
+        let cap = self.ptr_to_cap(ptr);
+        let stub = match self.reserve_stub(cap, cap) {  // link device actor into GC root-set
+            Ok(stub) => stub,
+            Err(error) => {
+                self.free(ptr);
+                return Err(error);
+            },
+        };
+        if id == self.device.len() {
+            self.device.push(None);
+            self.device_stub.push(UNDEF);
+        }
+        dev.init();
+        self.device[id] = Some(dev);
+        self.device_stub[id] = stub;
+        self.device_proxies.insert(id, BTreeSet::new());
+        Ok(cap)
+    }
+
+    pub fn unregister_device(&mut self, cap: Any) -> Result<Box<dyn Device>, Error> {
+        // remove a registered device, dropping any outstanding proxies.
+        // the device actor and its proxies are retired in place,
+        // so later messages to them are ignored.
+        let id = self.device_id(cap)?;
+        let stub = self.device_stub[id];
+        if !stub.is_ram() || (self.ram(stub).x() != cap) {
+            return Err(E_BOUNDS);  // not a registered device actor
+        }
+        let mut dev = self.device[id].take().ok_or(E_BOUNDS)?;
+        for ofs in self.device_proxies.remove(&id).unwrap_or_default() {
+            dev.drop_proxy(self, self.ptr_to_cap(Any::ram(ofs)));
+            self.quad_ram[ofs] = Quad::actor_t(UNDEF, NIL, UNDEF);  // retired proxy
+        }
+        self.quad_ram[self.cap_to_ptr(cap).ofs()] = Quad::actor_t(UNDEF, NIL, UNDEF);  // retired device actor
+        self.device_stub[id] = UNDEF;
+        self.release_stub(stub);
+        Ok(dev)
+    }
+
+    pub fn poll_devices(&mut self) -> Result<(), Error> {
+        // give each device a chance to complete asynchronous requests
+        // (called by the host between `run_loop` slices)
+        for id in 0..self.device.len() {
+            if let Some(mut dev_mut) = self.device[id].take() {
+                let result = dev_mut.poll(self);
+                self.device[id] = Some(dev_mut);
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+
+    Send `customer,message` to `target` on behalf of `sponsor`,
+    where the _customer_ is a temporary capability whose first message
+    (the reply) is held for the host. A reply is protected from garbage
+    collection until it is taken by `poll_reply` or `await_reply`.
+
+    */
+    pub fn call(&mut self, target: Any, message: Any, sponsor: Any) -> Result<ReplyHandle, Error> {
+        if !target.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        if self.reply_dev == UNDEF {
+            self.reply_dev = self.register_device(Box::new(crate::reply_dev::ReplyDevice))?;
+        }
+        let id = self.reply_next;
+        let customer = self.reserve_proxy(self.reply_dev, Any::fix(id))?;
+        let msg = self.reserve(&Quad::pair_t(customer, message))?;
+        let evt = self.reserve_event(sponsor, target, msg)?;
+        self.reply_next += 1;
+        self.replies.insert(id, ReplyState::Pending);
+        self.event_enqueue(evt);
+        Ok(ReplyHandle { id, customer })
+    }
+    pub fn poll_reply(&mut self, reply: &ReplyHandle) -> Result<Option<Any>, Error> {
+        // take the reply, if it has arrived.
+        // fails if the customer was dropped, or the reply was already taken.
+        match self.replies.get(&reply.id) {
+            Some(ReplyState::Pending) => Ok(None),
+            Some(&ReplyState::Received(stub)) => {
+                self.replies.remove(&reply.id);
+                let msg = self.ram(stub).y();
+                self.release_stub(stub);
+                Ok(Some(msg))
+            },
+            Some(ReplyState::Dropped) => {
+                self.replies.remove(&reply.id);
+                Err(E_FAIL)
+            },
+            None => Err(E_FAIL),
+        }
+    }
+    pub fn await_reply(&mut self, reply: &ReplyHandle, step_limit: usize) -> Result<Option<Any>, Error> {
+        // run (and poll devices) until the reply arrives,
+        // returning `None` if it has not arrived within `step_limit` steps.
+        // fails with the root sponsor's error signal, if one is raised.
+        // this is a single call, as far as the `RootSponsorPolicy` is concerned.
+        self.start_run();
+        let mut remaining = step_limit;
+        while remaining > 0 {
+            if let Some(msg) = self.poll_reply(reply)? {
+                return Ok(Some(msg));
+            }
+            let batch = remaining.min(REPLY_BATCH);
+            self.resume_sponsor(SPONSOR, UNDEF);  // after an idle batch
+            let report = self.run_steps(batch as i32);
+            let sig = self.sponsor_signal(SPONSOR);
+            if sig.is_fix() && (sig != ZERO) {
+                return Err(sig.get_fix()? as Error);
+            }
+            if report.outcome == RunOutcome::Interrupted {
+                break;
+            }
+            self.poll_devices()?;
+            remaining -= report.steps.clamp(1, batch);  // an idle batch counts as a step
+        }
+        self.poll_reply(reply)
+    }
+    pub(crate) fn reply_received(&mut self, id: isize, msg: Any) -> Result<(), Error> {
+        if self.replies.get(&id) == Some(&ReplyState::Pending) {  // only the first reply is kept
+            let stub = self.reserve_stub(self.reply_dev, msg)?;
+            self.replies.insert(id, ReplyState::Received(stub));
+        }
+        Ok(())
+    }
+    pub(crate) fn reply_dropped(&mut self, id: isize) {
+        if self.replies.get(&id) == Some(&ReplyState::Pending) {
+            self.replies.insert(id, ReplyState::Dropped);
+        }
+    }
+
     fn call_txn_fn(&self, ep: Any, kp_or_fx: Any) {
         if let Some(txn) = &self.txn_fn {
             (txn)(ep, kp_or_fx);
@@ -172,7 +524,66 @@ impl Core {
         self.txn_fn = Some(Box::new(txn_fn));
     }
 
-    fn call_audit_fn(&mut self, error: Error, evidence: Any) {
+    pub fn set_trace_fn<F: FnMut(&TxnRecord) + 'static>(&mut self, trace_fn: F) {
+        // decode each transaction, as it concludes (see `trace::writer`)
+        self.tracer.start(Box::new(trace_fn));
+    }
+    pub fn clear_trace_fn(&mut self) {
+        self.tracer.stop();
+    }
+    fn trace_commit(&mut self, ep: Any) {
+        // trace an actor transaction, before its effects are applied
+        if !self.tracer.is_traced(ep) {
+            return;
+        }
+        let effect = self.ram(ep).z();
+        let mut sent = Vec::new();
+        let mut outbox = self.z(effect);
+        while outbox.is_ram() {
+            let message = crate::trace::render(self, self.event_message(outbox));
+            sent.push(Sent { target: self.event_target(outbox), message });
+            outbox = self.z(outbox);
+        }
+        sent.reverse();  // the outbox is in reverse order
+        let actor = *self.ram(self.self_ptr());
+        let update = *self.ram(effect);
+        let became = if (update.x() != actor.x()) || (update.y() != actor.y()) {
+            Some((update.x(), crate::trace::render(self, update.y())))
+        } else {
+            None
+        };
+        self.trace_end(ep, TxnOutcome::Commit, sent, became);
+    }
+    fn trace_end(&mut self, ep: Any, outcome: TxnOutcome, sent: Vec<Sent>, became: Option<(Any, String)>) {
+        if !self.tracer.is_traced(ep) {
+            return;
+        }
+        let event = *self.ram(ep);
+        let record = TxnRecord {
+            id: 0,
+            event: ep,
+            provenance: self.event_provenance(ep),
+            sponsor: event.t(),
+            target: event.x(),
+            device: self.device_id(event.x()).is_ok(),
+            message: crate::trace::render(self, event.y()),
+            sent,
+            created: Vec::new(),
+            became,
+            cycles: 0,
+            start: 0,
+            end: 0,
+            outcome,
+        };
+        self.tracer.end(record);  // with times, from the tracer
+    }
+
+    pub fn set_clock_fn<F: Fn() -> u64 + 'static>(&mut self, clock_fn: F) {
+        // the clock used for sponsor time budgets (without one, they are not enforced)
+        self.clock_fn = Some(Box::new(clock_fn));
+    }
+
+    pub(crate) fn call_audit_fn(&mut self, error: Error, evidence: Any) {
         self.audit_err = Some(error);
         if let Some(audit) = &self.audit_fn {
             let code = Any::fix(error as isize);
@@ -212,6 +623,10 @@ impl Core {
     and the continuation-queue are empty, the root-sponsor _signal_ field is
     set to `ZERO` (aka `Any::fix(E_OK)`), and returned to the host.
 
+    When the root-sponsor reaches a limit (memory, events, or cycles),
+    the `RootSponsorPolicy` may replenish its quota and continue running,
+    rather than returning the error signal to the host.
+
      Signal   | Root Sponsor | Peripheral Sponsor
     ----------|--------------|--------------------
     `E_OK`    | no more work | sponsor stopped
@@ -221,10 +636,31 @@ impl Core {
 
     */
     pub fn run_loop(&mut self, limit: i32) -> Any {
-        self.resume_sponsor(SPONSOR, UNDEF);
+        self.run(limit);
+        self.sponsor_signal(SPONSOR)  // return SPONSOR signal
+    }
+    pub fn run(&mut self, limit: i32) -> RunReport {
+        // like `run_loop`, but the root sponsor signal is decoded for the host
+        self.start_run();
+        self.run_steps(limit)
+    }
+    fn run_steps(&mut self, limit: i32) -> RunReport {
+        // the body of `run`, without refilling the root sponsor
+        let mut report = RunReport {
+            outcome: RunOutcome::StepLimit,
+            steps: 0,
+            instructions: 0,
+            events: 0,
+        };
         let mut steps = 0;
         while (limit <= 0) || (steps < limit) {
-            if !self.k_first().is_ram() && !self.e_first().is_ram() {
+            self.host_inputs();  // safe point, between steps
+            if self.interrupt.as_ref().is_some_and(InterruptHandle::take) {
+                report.outcome = RunOutcome::Interrupted;
+                break;  // return signal (still runnable)
+            }
+            if !self.k_first().is_ram() && !self.has_events() {
+                self.books.sched.idle();
                 self.gc_collect_all();  // full GC collection before becoming idle
                 self.set_sponsor_signal(SPONSOR, ZERO);  // processor idle
                 break;  // return signal
@@ -233,18 +669,291 @@ impl Core {
             // if self.sponsor_signal(SPONSOR).is_fix() {
             //     break;
             // }
-            if let Err(_error) = self.execute_instruction() {
-                break;  // return signal
+            if self.k_first().is_ram() {
+                report.instructions += 1;
             }
-            if let Err(_error) = self.dispatch_event() {
-                break;  // return signal
+            if let Err(error) = self.execute_instruction() {
+                if !self.replenish_root_sponsor(error) {
+                    break;  // return signal
+                }
+            }
+            match self.dispatch_event() {
+                Ok(dispatched) => {
+                    report.events += dispatched as usize;
+                },
+                Err(error) => {
+                    if !self.replenish_root_sponsor(error) {
+                        break;  // return signal
+                    }
+                },
             }
             if GC_STRATEGY == GcStrategy::Interleaved {
                 self.gc_increment();  // take `self.gc_stride` incremental GC steps
             }
             steps += 1;  // count step
         }
-        self.sponsor_signal(SPONSOR)  // return SPONSOR signal
+        report.steps = steps as usize;
+        report.outcome = match self.sponsor_signal(SPONSOR).fix_num() {
+            None => report.outcome,  // `StepLimit` or `Interrupted`
+            Some(0) if self.devices_pending() => RunOutcome::WaitingOnDevices,
+            Some(0) => RunOutcome::Idle,
+            Some(code) => match code as Error {
+                E_MEM_LIM => RunOutcome::RootQuotaExhausted { kind: QuotaKind::Memory },
+                E_MSG_LIM => RunOutcome::RootQuotaExhausted { kind: QuotaKind::Events },
+                E_CPU_LIM => RunOutcome::RootQuotaExhausted { kind: QuotaKind::Cycles },
+                E_TIME_LIM => RunOutcome::RootQuotaExhausted { kind: QuotaKind::Time },
+                error => RunOutcome::Fatal { error },
+            },
+        };
+        report
+    }
+    fn start_run(&mut self) {
+        // resume (and refill) the root sponsor, for a call to `run`
+        self.resume_sponsor(SPONSOR, UNDEF);
+        match self.root_policy {
+            RootSponsorPolicy::RefillPerCall => self.set_sponsor_quota(SPONSOR, self.root_quota),
+            RootSponsorPolicy::Unlimited => self.set_sponsor_quota(SPONSOR, Quota::UNLIMITED),
+            _ => {},
+        }
+    }
+    pub fn interrupt_handle(&mut self) -> InterruptHandle {
+        // each interrupt stops one call to `run_loop` (or `run`), leaving it runnable
+        self.interrupt.get_or_insert_with(InterruptHandle::default).clone()
+    }
+    pub fn handle(&mut self) -> CoreHandle {
+        // a handle other threads may use to post work (see `drain_inbox`)
+        let inbox = self.inbox.get_or_insert_with(Arc::default);
+        CoreHandle::new(inbox.clone())
+    }
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn register_waker(&mut self, waker: &::core::task::Waker) -> bool {
+        // wake `waker` when a handle next posts work,
+        // returning `true` if work has already been posted.
+        let inbox = self.inbox.get_or_insert_with(Arc::default);
+        inbox.register(waker);
+        !inbox.is_empty()
+    }
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn register_device_wakers(&mut self, waker: &::core::task::Waker) -> bool {
+        // wake `waker` when a pending device has work for `poll_devices`,
+        // returning `false` if some pending device can't arrange that.
+        self.device.iter_mut().flatten()
+            .filter(|dev| dev.pending())
+            .all(|dev| dev.register_waker(waker))
+    }
+    pub fn drain_inbox(&mut self) {
+        // deliver work posted by handles (called by `run_loop` between steps)
+        for item in self.take_inbound() {
+            self.deliver_inbound(item);
+        }
+    }
+    pub(crate) fn take_inbound(&mut self) -> Vec<Inbound> {
+        match &self.inbox {
+            Some(inbox) if !inbox.is_empty() => inbox.take_all(),
+            _ => Vec::new(),
+        }
+    }
+    pub(crate) fn deliver_inbound(&mut self, item: Inbound) {
+        match item {
+            Inbound::Event { sponsor, target, message } => {
+                match self.reserve_event(sponsor, target, message) {
+                    Ok(evt) => self.event_enqueue(evt),
+                    Err(error) => self.call_audit_fn(error, target),
+                }
+            },
+            Inbound::Release(stub) => {
+                if stub.is_ram() && (self.ram(stub).t() == STUB_T) {
+                    self.release_stub(stub);
+                }
+            },
+            Inbound::Task(task) => task(self),
+        }
+    }
+    pub fn set_step_fn<F: FnMut(&mut Core) + 'static>(&mut self, step_fn: F) {
+        // deliver host inputs between run-loop steps, instead of `drain_inbox`
+        // (see `replay::Session`)
+        self.step_fn = Some(Box::new(step_fn));
+    }
+    pub fn clear_step_fn(&mut self) {
+        self.step_fn = None;
+    }
+    fn host_inputs(&mut self) {
+        match self.step_fn.take() {
+            Some(mut step_fn) => {
+                step_fn(self);
+                self.step_fn.get_or_insert(step_fn);  // unless it was replaced
+            },
+            None => self.drain_inbox(),
+        }
+    }
+    pub fn devices_pending(&self) -> bool {
+        // true if some device holds work that `poll_devices` will complete
+        self.device.iter().flatten().any(|dev| dev.pending())
+    }
+    fn read_clock(&self) -> Option<u64> {
+        self.clock_fn.as_ref().map(|clock| clock())
+    }
+    pub(crate) fn heap_hash(&self) -> u64 {
+        // FNV-1a, over every cell
+        let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+        let mut mix = |raw: Raw| {
+            hash = (hash ^ raw as u64).wrapping_mul(0x0100_0000_01B3);
+        };
+        for ((quad, &mark), owner) in self.quad_ram.iter().zip(&self.gc_marks).zip(&self.gc_owners) {
+            for value in [quad.t(), quad.x(), quad.y(), quad.z(), *owner] {
+                mix(value.raw());
+            }
+            mix(mark as Raw);
+        }
+        hash
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        // ROM, devices, and host hooks are not included
+        Snapshot {
+            quad_ram: self.quad_ram.to_vec(),
+            gc_addr: self.gc_addr,
+            gc_phase: self.gc_phase,
+            gc_curr: self.gc_curr,
+            gc_prev: self.gc_prev,
+            gc_marks: self.gc_marks.to_vec(),
+            gc_owners: self.gc_owners.to_vec(),
+            sponsor_charges: self.sponsor_charges.clone(),
+            device_proxies: self.device_proxies.clone(),
+            reply_next: self.reply_next,
+            replies: self.replies.clone(),
+            books: self.books.clone(),
+        }
+    }
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // return the heap to the state captured by `snapshot`
+        self.quad_ram.copy_from_slice(&snapshot.quad_ram);
+        self.gc_addr = snapshot.gc_addr;
+        self.gc_phase = snapshot.gc_phase;
+        self.gc_curr = snapshot.gc_curr;
+        self.gc_prev = snapshot.gc_prev;
+        self.gc_marks.copy_from_slice(&snapshot.gc_marks);
+        self.gc_owners.copy_from_slice(&snapshot.gc_owners);
+        self.sponsor_charges = snapshot.sponsor_charges.clone();
+        self.device_proxies = snapshot.device_proxies.clone();
+        self.reply_next = snapshot.reply_next;
+        self.replies = snapshot.replies.clone();
+        self.books = snapshot.books.clone();
+    }
+    pub fn pending_events(&self) -> Vec<Any> {
+        // the events in the event queue, oldest first
+        let mut events = self.books.sched.ready_events();
+        let mut ep = self.e_first();
+        while ep.is_ram() {
+            events.push(ep);
+            ep = self.z(ep);
+        }
+        events
+    }
+    pub(crate) fn run_transaction(&mut self, index: usize) -> Result<(), Error> {
+        // dispatch the `index`-th pending event, and run to completion
+        // (no other event is dispatched, so the continuation queue must be empty)
+        assert!(!self.k_first().is_ram());
+        self.unready();  // so events are found in `pending_events` order
+        let (mut prev, mut ep) = (UNDEF, self.e_first());
+        for _ in 0..index {
+            (prev, ep) = (ep, self.z(ep));
+        }
+        if !ep.is_ram() {
+            return Err(E_BOUNDS);
+        }
+        if prev.is_ram() {
+            // move event to the front of the queue
+            let next = self.z(ep);
+            self.set_z(prev, next);
+            if !next.is_ram() {
+                self.set_e_last(prev);
+            }
+            self.set_z(ep, self.e_first());
+            self.set_e_first(ep);
+        }
+        self.resume_sponsor(SPONSOR, UNDEF);  // as in `run`
+        let policy = self.books.sched.replace_policy(SchedulingPolicy::Fifo);
+        let mut result = self.dispatch_event().map(|_| ());
+        while result.is_ok() && self.k_first().is_ram() {
+            result = self.execute_instruction();
+        }
+        self.books.sched.replace_policy(policy);
+        self.gc_collect_all();  // so equivalent states have equal fingerprints
+        result
+    }
+    pub(crate) fn take_audit_err(&mut self) -> Option<Error> {
+        self.audit_err.take()
+    }
+    pub(crate) fn fingerprint(&self) -> Vec<Raw> {
+        // a description of the heap reachable from the GC roots,
+        // where cells are numbered in the order they are found,
+        // so equivalent heaps have equal fingerprints.
+        // sponsor quotas are ignored.
+        let mut labels: BTreeMap<usize, Raw> = BTreeMap::new();
+        let mut found: Vec<Any> = Vec::new();
+        let token = |val: Any, labels: &mut BTreeMap<usize, Raw>, found: &mut Vec<Any>| -> Raw {
+            if !val.is_ptr() || val.is_rom() || (val.ofs() < RAM_BASE_OFS) || !self.in_heap(self.cap_to_ptr(val)) {
+                return val.raw();  // a direct, ROM, or reserved value
+            }
+            let ptr = self.cap_to_ptr(val);
+            let next = (RAM_BASE_OFS + labels.len()) as Raw;
+            let label = *labels.entry(ptr.ofs()).or_insert_with(|| {
+                found.push(ptr);
+                next
+            });
+            (val.raw() & MSK_RAW) | label
+        };
+        let mut print = Vec::new();
+        print.push(token(self.ram_root(), &mut labels, &mut found));
+        print.push(token(self.e_first(), &mut labels, &mut found));
+        let mut n = 0;
+        while n < found.len() {
+            let quad = *self.ram(found[n]);
+            print.push(token(quad.t(), &mut labels, &mut found));
+            print.push(match quad.t() {
+                SPONSOR_T => token(self.z(quad.x()), &mut labels, &mut found),  // parent, not quota
+                _ => token(quad.x(), &mut labels, &mut found),
+            });
+            print.push(token(quad.y(), &mut labels, &mut found));
+            print.push(token(quad.z(), &mut labels, &mut found));
+            n += 1;
+        }
+        print
+    }
+    fn replenish_root_sponsor(&mut self, error: Error) -> bool {
+        // apply the root sponsor policy when a limit is reached,
+        // returning `true` if the root sponsor was resumed.
+        if (error != E_MEM_LIM) && (error != E_CPU_LIM) && (error != E_MSG_LIM) && (error != E_TIME_LIM) {
+            return false;
+        }
+        let remaining = self.sponsor_quota(SPONSOR);
+        let quota = match &mut self.root_policy {
+            RootSponsorPolicy::Unlimited => Quota::UNLIMITED,
+            RootSponsorPolicy::Callback(refill) => match refill(error, remaining) {
+                Some(quota) => quota,
+                None => return false,
+            },
+            _ => return false,
+        };
+        if error == E_TIME_LIM {
+            self.books.budgets.set(SPONSOR.ofs(), TimeBudget::default());  // lift the time budget
+        }
+        self.set_sponsor_quota(SPONSOR, quota);
+        self.resume_sponsor(SPONSOR, UNDEF);
+        true
+    }
+    pub fn root_quota(&self) -> Quota {
+        self.root_quota
+    }
+    pub fn set_root_quota(&mut self, quota: Quota) {
+        // applied immediately, and by `init` and the `RefillPerCall` policy
+        self.root_quota = quota;
+        if self.ram(SPONSOR).t() == SPONSOR_T {  // already initialized
+            self.set_sponsor_quota(SPONSOR, quota);
+        }
+    }
+    pub fn set_root_sponsor_policy(&mut self, policy: RootSponsorPolicy) {
+        self.root_policy = policy;
     }
     fn resume_sponsor(&mut self, sponsor: Any, signal: Any) {
         // Requeue any waiting events and enable the sponsor.
@@ -264,33 +973,28 @@ impl Core {
     Otherwise, enqueue a new _continuation_ to handle the transaction.
 
     */
-    fn dispatch_event(&mut self) -> Result<(), Error> {
-        let ep = self.e_first();
+    fn dispatch_event(&mut self) -> Result<bool, Error> {
+        // returns `true` if an event was dispatched (rather than deferred)
+        let ep = self.take_event();
         if !ep.is_ram() {
-            return Ok(());  // event queue empty
+            return Ok(false);  // event queue empty
         }
-        // remove first event from the queue...
         let event = self.ram(ep);
         let sponsor = event.t();
         let target = event.x();
-        let next = event.z();
-        self.set_e_first(next);
-        if !next.is_ram() {
-            self.set_e_last(NIL)
-        }
         // if sponsor is suspended, move event to waiting (or discard)
         let sig = self.sponsor_signal(sponsor);
         if sig.is_fix() {  // sponsor suspended
             if sig != ZERO {  // sponsor not stopped
                 self.waiting_event(ep);  // defer event
             }
-            return Ok(());
+            return Ok(false);
         }
         // if target actor is busy, move event to actor inbox
         let ptr = self.cap_to_ptr(target);
         if self.z(ptr) != UNDEF {  // actor is busy
             self.inbox_event(ep);  // defer event
-            return Ok(());
+            return Ok(false);
         }
         // begin event processing (may create a continuation)
         if let Err(error) = self.process_event(sponsor, target, ep) {
@@ -298,9 +1002,140 @@ impl Core {
             if !self.report_error(sponsor, error) {
                 return Err(error);  // signal root sponsor
             }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+    fn has_events(&self) -> bool {
+        self.e_first().is_ram() || self.books.sched.has_ready()
+    }
+    fn take_event(&mut self) -> Any {
+        // remove the next event from the queue, as chosen by the scheduling policy
+        let first = self.e_first();
+        if self.books.sched.policy() == SchedulingPolicy::Fifo {
+            if first.is_ram() {
+                let next = self.z(first);
+                self.set_e_first(next);
+                if !next.is_ram() {
+                    self.set_e_last(NIL);
+                }
+            }
+            return first;
         }
+        // other policies move events into ready queues, as they arrive
+        let mut ep = first;
+        while ep.is_ram() {
+            let next = self.z(ep);
+            self.set_z(ep, NIL);
+            self.books.sched.make_ready(ep, self.event_sponsor(ep));
+            ep = next;
+        }
+        self.set_e_first(NIL);
+        self.set_e_last(NIL);
+        let quad_ram = &self.quad_ram;
+        let sponsor_of = |ep: Any| quad_ram[ep.ofs()].t();
+        self.books.sched.next_ready(sponsor_of).unwrap_or(NIL)
+    }
+    fn unready(&mut self) {
+        // return ready events to the front of the event queue, oldest first
+        for ep in self.books.sched.take_ready().into_iter().rev() {
+            let first = self.e_first();
+            if !first.is_ram() {
+                self.set_e_last(ep);
+            }
+            self.set_z(ep, first);
+            self.set_e_first(ep);
+        }
+    }
+    pub fn scheduling_policy(&self) -> SchedulingPolicy {
+        self.books.sched.policy()
+    }
+    pub fn set_scheduling_policy(&mut self, policy: SchedulingPolicy) {
+        self.unready();
+        self.books.sched.set_policy(policy);
+    }
+    fn chaos_continuation(&mut self) {
+        // move a random continuation to the front of the queue
+        let mut count = 0;
+        let mut kp = self.k_first();
+        while kp.is_ram() {
+            count += 1;
+            kp = self.z(kp);
+        }
+        if count < 2 {
+            return;
+        }
+        let (mut prev, mut kp) = (UNDEF, self.k_first());
+        for _ in 0..(self.books.sched.random() % count) {
+            (prev, kp) = (kp, self.z(kp));
+        }
+        if prev.is_ram() {
+            let next = self.z(kp);
+            self.set_z(prev, next);
+            if !next.is_ram() {
+                self.set_k_last(prev);
+            }
+            self.set_z(kp, self.k_first());
+            self.set_k_first(kp);
+        }
+    }
+    pub fn sponsor_weight(&self, sponsor: Any) -> u64 {
+        self.books.sched.weight(sponsor)
+    }
+    pub fn set_sponsor_weight(&mut self, sponsor: Any, weight: u64) -> Result<(), Error> {
+        // a sponsor's share of events dispatched by the `WeightedFair` policy
+        if !sponsor.is_ram() || (self.ram(sponsor).t() != SPONSOR_T) {
+            return Err(E_BOUNDS);
+        }
+        if (weight == 0) || (weight > FAIR_SHARE) {
+            return Err(E_BOUNDS);
+        }
+        self.books.sched.set_weight(sponsor, weight);
         Ok(())
     }
+    pub fn event_priority(&self, ep: Any) -> i32 {
+        self.books.sched.priority(ep)
+    }
+    pub fn set_event_priority(&mut self, ep: Any, priority: i32) -> Result<(), Error> {
+        // events sent while processing this event inherit its priority
+        if !self.in_heap(ep) {
+            return Err(E_BOUNDS);
+        }
+        self.books.sched.set_priority(ep, priority);
+        Ok(())
+    }
+    fn inherit_cause(&mut self, cause: Any, ep: Any) {
+        // the priority and provenance of an event sent while processing `cause`
+        self.books.sched.inherit(cause, ep);
+        self.books.causes.inherit(cause.ofs(), ep.ofs());
+    }
+
+    /*
+
+    With causality tracking on, each event is given an id, and the id of
+    the event that caused it: the event whose transaction sent it, or the
+    device event that returned it. Events enqueued by the host have no cause.
+    A device that holds an event to enqueue later (like a timer) should name
+    its cause with `set_event_cause`.
+
+    */
+    pub fn set_causality(&mut self, enabled: bool) {
+        self.books.causes.set_enabled(enabled);
+    }
+    pub fn event_provenance(&self, ep: Any) -> Option<Provenance> {
+        self.books.causes.provenance(ep.ofs())
+    }
+    pub fn set_event_cause(&mut self, ep: Any, cause: Any) -> Result<(), Error> {
+        if !self.in_heap(ep) || !self.in_heap(cause) {
+            return Err(E_BOUNDS);
+        }
+        self.books.causes.inherit(cause.ofs(), ep.ofs());
+        Ok(())
+    }
+    pub fn causal_chain(&self, id: u64) -> Vec<u64> {
+        // `id`, its cause, that event's cause, ... as far as is known
+        self.books.causes.chain(id)
+    }
     fn waiting_event(&mut self, ep: Any) {
         // link event into sponsor's waiting queue
         let sponsor = self.event_sponsor(ep);
@@ -325,10 +1160,11 @@ impl Core {
         }
         return false;  // root sponsor
     }
-    fn is_recoverable(&self, error: Error) -> bool {
+    pub(crate) fn is_recoverable(&self, error: Error) -> bool {
         (error == E_MEM_LIM) ||
         (error == E_CPU_LIM) ||
         (error == E_MSG_LIM) ||
+        (error == E_TIME_LIM) ||
         (error == E_STOP)
     }
     /*
@@ -339,6 +1175,7 @@ impl Core {
 
     */
     fn process_event(&mut self, sponsor: Any, target: Any, ep: Any) -> Result<(), Error> {
+        self.check_time_budget(sponsor, ep)?;
         // consume event (communication) quota
         let mut limit = self.sponsor_events(sponsor).fix_num().unwrap_or(0);
         if limit <= 0 {
@@ -351,10 +1188,23 @@ impl Core {
             // synchronous message-event to device
             if self.device[id].is_some() {  // ignore unavailable devices
                 let mut dev_mut = self.device[id].take().unwrap();
+                self.tracer.begin(ep);
                 let result = dev_mut.handle_event(self, ep);
                 self.device[id] = Some(dev_mut);
+                if self.tracer.is_traced(ep) {
+                    let (outcome, sent) = match result {
+                        Ok(evt) if evt.is_ram() => {
+                            let message = crate::trace::render(self, self.event_message(evt));
+                            (TxnOutcome::Commit, vec![Sent { target: self.event_target(evt), message }])
+                        },
+                        Ok(_) => (TxnOutcome::Commit, Vec::new()),
+                        Err(error) => (TxnOutcome::Failed { error }, Vec::new()),
+                    };
+                    self.trace_end(ep, outcome, sent, None);
+                }
                 if let Ok(evt) = result {
                     if evt.is_ram() {
+                        self.inherit_cause(ep, evt);
                         self.event_enqueue(evt);
                         self.call_txn_fn(ep, evt);  // trace transactional effects
                     }
@@ -362,6 +1212,8 @@ impl Core {
                     return Err(error);
                 }
             }
+        } else if self.mem(self.cap_to_ptr(target)).x() == UNDEF {
+            // ignore retired devices (see `unregister_device`)
         } else {
             // begin actor-event transaction
             let ptr = self.cap_to_ptr(target);
@@ -377,6 +1229,8 @@ impl Core {
             let beh = actor.x();
             let kp = self.reserve_cont(beh, NIL, ep)?;  // create continuation
             self.cont_enqueue(kp);
+            self.start_event_clock(sponsor, ep);
+            self.tracer.begin(ep);
         }
         Ok(())
     }
@@ -389,6 +1243,9 @@ impl Core {
 
     */
     fn execute_instruction(&mut self) -> Result<(), Error> {
+        if let SchedulingPolicy::Chaos { .. } = self.books.sched.policy() {
+            self.chaos_continuation();
+        }
         let kp = self.k_first();
         if !kp.is_ram() {
             return Ok(());  // continuation queue is empty
@@ -402,6 +1259,8 @@ impl Core {
         let sponsor = self.event_sponsor(ep);
         let sig = self.sponsor_signal(sponsor);
         if sig.is_fix() {  // sponsor suspended
+            let error = if sig == ZERO { E_STOP } else { sig.fix_num().unwrap_or(0) as Error };
+            self.trace_end(ep, TxnOutcome::Failed { error }, Vec::new(), None);
             self.actor_abort();
             if sig != ZERO {  // sponsor not stopped
                 self.waiting_event(ep);  // defer event
@@ -413,7 +1272,7 @@ impl Core {
             return Ok(())
         }
         // execute current instruction
-        match self.perform_op(ip) {
+        match self.check_time_budget(sponsor, ep).and_then(|_| self.perform_op(ip)) {
             Ok(()) => {
                 let kp_ = self.cont_dequeue().unwrap();
                 assert_eq!(kp, kp_);
@@ -425,6 +1284,7 @@ impl Core {
                 }
             },
             Err(error) => {
+                self.trace_end(ep, TxnOutcome::Failed { error }, Vec::new(), None);
                 if self.is_recoverable(error) {
                     self.actor_abort();
                     self.waiting_event(ep);  // defer event
@@ -747,6 +1607,9 @@ impl Core {
                 let v = self.stack_pop();
                 let r = if imm == CMP_EQ {
                     if v == vv { TRUE } else { FALSE }
+                } else if imm == CMP_EQUAL {
+                    let order = self.compare_charged(v, vv)?;
+                    if order == cmp::Ordering::Equal { TRUE } else { FALSE }
                 } else if imm == CMP_NE {
                     if v != vv { TRUE } else { FALSE }
                 } else {
@@ -857,12 +1720,17 @@ impl Core {
                 let rv = match imm {
                     END_ABORT => {
                         let reason = self.stack_pop();  // reason for abort
+                        if self.tracer.is_traced(self.ep()) {
+                            let reason = crate::trace::render(self, reason);
+                            self.trace_end(self.ep(), TxnOutcome::Abort { reason }, Vec::new(), None);
+                        }
                         self.audit_abort(E_ABORT, reason)
                     },
                     END_STOP => {
                         return Err(E_STOP);  // End::Stop terminated continuation
                     },
                     END_COMMIT => {
+                        self.trace_commit(self.ep());
                         self.actor_commit(me)
                     },
                     _ => {  // unknown END op
@@ -892,9 +1760,9 @@ impl Core {
                         if n >= limit {
                             return Err(E_MEM_LIM);  // Sponsor memory limit reached
                         }
-                        self.set_sponsor_memory(ctl_spn, Any::fix(limit - n));
+                        self.put_sponsor_memory(ctl_spn, Any::fix(limit - n));
                         let m = self.sponsor_memory(per_spn).fix_num().unwrap_or(0);
-                        self.set_sponsor_memory(per_spn, Any::fix(m + n));
+                        self.put_sponsor_memory(per_spn, Any::fix(m + n));
                     },
                     SPONSOR_EVENTS => {
                         let num = self.stack_pop();
@@ -994,6 +1862,7 @@ impl Core {
 
     pub fn event_enqueue(&mut self, ep: Any) {
         // add event to the back of the event queue
+        self.books.causes.enqueued(ep.ofs());
         self.set_z(ep, NIL);
         if !self.e_first().is_ram() {
             self.set_e_first(ep);
@@ -1098,6 +1967,7 @@ impl Core {
         let effect = self.txn_effect();
         let next = self.z(effect);
         let ep = self.new_event(sponsor, target, msg)?;
+        self.inherit_cause(self.ep(), ep);
         self.set_z(ep, next);
         self.set_z(effect, ep);
         Ok(())
@@ -1108,7 +1978,9 @@ impl Core {
         }
         let actor = Quad::new_actor(beh, state);
         let ptr = self.alloc(&actor)?;
-        Ok(self.ptr_to_cap(ptr))
+        let cap = self.ptr_to_cap(ptr);
+        self.tracer.created(self.ep(), cap);
+        Ok(cap)
     }
     fn effect_become(&mut self, beh: Any, state: Any) -> Result<(), Error> {
         if !self.typeq(INSTR_T, beh) {
@@ -1187,19 +2059,140 @@ impl Core {
         memory += self.sponsor_memory(per_spn).get_fix()?;
         events += self.sponsor_events(per_spn).get_fix()?;
         cycles += self.sponsor_cycles(per_spn).get_fix()?;
-        self.set_sponsor_memory(ctl_spn, Any::fix(memory));
+        self.put_sponsor_memory(ctl_spn, Any::fix(memory));
         self.set_sponsor_events(ctl_spn, Any::fix(events));
         self.set_sponsor_cycles(ctl_spn, Any::fix(cycles));
-        self.set_sponsor_memory(per_spn, ZERO);
+        self.put_sponsor_memory(per_spn, ZERO);
         self.set_sponsor_events(per_spn, ZERO);
         self.set_sponsor_cycles(per_spn, ZERO);
         Ok(())
     }
+    pub fn sponsors(&self) -> Vec<SponsorInfo> {
+        // enumerate the root sponsor, followed by every allocated sponsor
+        let mut list = Vec::new();
+        list.extend(self.sponsor_info(SPONSOR));
+        for ofs in RAM_BASE_OFS..self.ram_top().ofs() {
+            list.extend(self.sponsor_info(Any::ram(ofs)));
+        }
+        list
+    }
+    pub fn sponsor_info(&self, sponsor: Any) -> Result<SponsorInfo, Error> {
+        if !sponsor.is_ram() || (self.ram(sponsor).t() != SPONSOR_T) {
+            return Err(E_BOUNDS);
+        }
+        let mut waiting = 0;
+        let mut ep = self.z(sponsor);
+        while ep.is_ram() {
+            waiting += 1;
+            ep = self.z(ep);
+        }
+        Ok(SponsorInfo {
+            sponsor,
+            parent: self.z(self.x(sponsor)),
+            quota: self.sponsor_quota(sponsor),
+            signal: self.sponsor_signal(sponsor),
+            waiting,
+        })
+    }
+    pub fn suspend_sponsor(&mut self, sponsor: Any) -> Result<(), Error> {
+        // halt a running peripheral sponsor with `E_STOP`, notifying its controller.
+        // deferred events are released when the sponsor is started again.
+        let info = self.sponsor_info(sponsor)?;
+        if (sponsor == SPONSOR) || !info.signal.is_ram() {
+            return Err(E_BOUNDS);  // root sponsor, or not running
+        }
+        self.report_error(sponsor, E_STOP);
+        Ok(())
+    }
+    pub fn start_sponsor(&mut self, sponsor: Any, control: Any) -> Result<(), Error> {
+        // run a halted peripheral sponsor, as `sponsor start` does,
+        // except that the control event is sponsored by the root sponsor.
+        let info = self.sponsor_info(sponsor)?;
+        if (sponsor == SPONSOR) || !info.signal.is_fix() {
+            return Err(E_BOUNDS);  // root sponsor, or already running
+        }
+        if !self.typeq(ACTOR_T, control) {
+            return Err(E_NOT_CAP);
+        }
+        let evt = self.reserve_event(SPONSOR, control, sponsor)?;
+        self.resume_sponsor(sponsor, evt);
+        Ok(())
+    }
+    pub fn refill_sponsor(&mut self, sponsor: Any, quota: Quota) -> Result<(), Error> {
+        // add to a sponsor's quota, on behalf of the host
+        let info = self.sponsor_info(sponsor)?;
+        if (quota.memory < 0) || (quota.events < 0) || (quota.cycles < 0) {
+            return Err(E_BOUNDS);
+        }
+        self.set_sponsor_quota(sponsor, Quota {
+            memory: (info.quota.memory + quota.memory).min(FIX_MAX),
+            events: (info.quota.events + quota.events).min(FIX_MAX),
+            cycles: (info.quota.cycles + quota.cycles).min(FIX_MAX),
+        });
+        Ok(())
+    }
+    pub fn stop_sponsor(&mut self, sponsor: Any) -> Result<(), Error> {
+        // stop a peripheral sponsor, as `sponsor stop` does, returning its quota
+        // to its parent. deferred events are discarded, and a running sponsor's
+        // controller is notified.
+        let info = self.sponsor_info(sponsor)?;
+        if sponsor == SPONSOR {
+            return Err(E_BOUNDS);
+        }
+        if self.typeq(SPONSOR_T, info.parent) {
+            self.reclaim_sponsor(info.parent, sponsor)?;
+        } else {
+            self.set_sponsor_quota(sponsor, Quota { memory: 0, events: 0, cycles: 0 });
+        }
+        self.set_z(sponsor, NIL);  // discard waiting events
+        self.set_sponsor_signal(sponsor, ZERO);  // mark sponsor for removal
+        if info.signal.is_ram() {
+            self.event_enqueue(info.signal);  // controller notified
+        }
+        Ok(())
+    }
+    pub fn sponsor_time_budget(&self, sponsor: Any) -> TimeBudget {
+        if !sponsor.is_ram() {
+            return TimeBudget::default();
+        }
+        self.books.budgets.get(sponsor.ofs())
+    }
+    pub fn set_sponsor_time_budget(&mut self, sponsor: Any, budget: TimeBudget) -> Result<(), Error> {
+        // exceeding the budget signals `E_TIME_LIM`, like other quotas
+        if !sponsor.is_ram() || (self.ram(sponsor).t() != SPONSOR_T) {
+            return Err(E_BOUNDS);
+        }
+        self.books.budgets.set(sponsor.ofs(), budget);
+        Ok(())
+    }
+    fn check_time_budget(&mut self, sponsor: Any, ep: Any) -> Result<(), Error> {
+        if !self.books.budgets.has(sponsor.ofs()) {
+            return Ok(());  // fast path, no budget (the clock is not read)
+        }
+        let Some(now) = self.read_clock() else {
+            return Ok(());
+        };
+        self.books.budgets.check(sponsor.ofs(), ep.ofs(), now)
+    }
+    fn start_event_clock(&mut self, sponsor: Any, ep: Any) {
+        if self.books.budgets.times_events(sponsor.ofs()) {
+            if let Some(now) = self.read_clock() {
+                self.books.budgets.start_event(ep.ofs(), now);
+            }
+        }
+    }
     pub fn sponsor_memory(&self, sponsor: Any) -> Any {
         let quota = self.x(sponsor);
         self.t(quota)
     }
     pub fn set_sponsor_memory(&mut self, sponsor: Any, num: Any) {
+        // cells already charged are not refunded beyond the new quota
+        if let Some(charges) = self.sponsor_charges.get_mut(&sponsor.ofs()) {
+            charges.refundable = 0;
+        }
+        self.put_sponsor_memory(sponsor, num);
+    }
+    fn put_sponsor_memory(&mut self, sponsor: Any, num: Any) {
         let quota = self.x(sponsor);
         self.set_t(quota, num);
     }
@@ -1219,6 +2212,18 @@ impl Core {
         let quota = self.x(sponsor);
         self.set_y(quota, num);
     }
+    pub fn sponsor_quota(&self, sponsor: Any) -> Quota {
+        Quota {
+            memory: self.sponsor_memory(sponsor).fix_num().unwrap_or(0),
+            events: self.sponsor_events(sponsor).fix_num().unwrap_or(0),
+            cycles: self.sponsor_cycles(sponsor).fix_num().unwrap_or(0),
+        }
+    }
+    pub fn set_sponsor_quota(&mut self, sponsor: Any, quota: Quota) {
+        self.set_sponsor_memory(sponsor, Any::fix(quota.memory));
+        self.set_sponsor_events(sponsor, Any::fix(quota.events));
+        self.set_sponsor_cycles(sponsor, Any::fix(quota.cycles));
+    }
     pub fn sponsor_signal(&self, sponsor: Any) -> Any {
         self.y(sponsor)
     }
@@ -1233,6 +2238,7 @@ impl Core {
             return Err(E_CPU_LIM);  // Sponsor instruction limit reached
         }
         self.set_sponsor_cycles(sponsor, Any::fix(limit - cost));
+        self.tracer.cycles(ep, cost as u64);
         Ok(())
     }
 
@@ -1289,7 +2295,104 @@ impl Core {
                 v = self.cdr(p);
             }
         }
-        v
+        v
+    }
+
+    /*
+
+    Structural comparison of values, giving a total order (for sorting).
+    Fixnums come first, in numeric order. Pairs and dicts compare by their
+    contents, a dict as its list of entries (in order), so dicts with the
+    same entries in a different order are not equal. Proxies compare by
+    value if their device can compare them (blobs compare by contents),
+    failing with `E_BOUNDS` while the device is busy handling an event.
+    Other values compare by identity.
+
+    At most `max_cells` cells are visited (failing with `E_CPU_LIM`),
+    so cyclic structures fail.
+
+    */
+    pub fn compare(&self, a: Any, b: Any, max_cells: usize) -> Result<cmp::Ordering, Error> {
+        let mut budget = max_cells;
+        self.compare_within(a, b, &mut budget)
+    }
+    pub fn deep_eq(&self, a: Any, b: Any, max_cells: usize) -> Result<bool, Error> {
+        Ok(self.compare(a, b, max_cells)? == cmp::Ordering::Equal)
+    }
+    fn compare_charged(&mut self, a: Any, b: Any) -> Result<cmp::Ordering, Error> {
+        // compare within the sponsor's remaining cycles, charging a cycle for each cell
+        let sponsor = self.event_sponsor(self.ep());
+        let limit = self.sponsor_cycles(sponsor).fix_num().unwrap_or(0).max(0) as usize;
+        let mut budget = limit;
+        let order = self.compare_within(a, b, &mut budget);
+        self.count_cpu_cycles((limit - budget) as isize)?;
+        order
+    }
+    fn compare_within(&self, a: Any, b: Any, budget: &mut usize) -> Result<cmp::Ordering, Error> {
+        // pairs of values still to be compared, the next pair last.
+        // each cell visited adds at most three pairs, so the budget bounds the stack.
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            if a == b {
+                continue;
+            }
+            if *budget == 0 {
+                return Err(E_CPU_LIM);
+            }
+            *budget -= 1;
+            let rank = self.compare_rank(a);
+            let order = rank.cmp(&self.compare_rank(b));
+            if order != cmp::Ordering::Equal {
+                return Ok(order);
+            }
+            let order = match rank {
+                Rank::Fixnum => a.fix_num().cmp(&b.fix_num()),
+                Rank::Pair | Rank::Dict => {
+                    let (a_quad, b_quad) = (*self.mem(a), *self.mem(b));
+                    if rank == Rank::Dict {
+                        pending.push((a_quad.z(), b_quad.z()));  // next entry
+                    }
+                    pending.push((a_quad.y(), b_quad.y()));  // rest of list (or value)
+                    pending.push((a_quad.x(), b_quad.x()));  // first element (or key)
+                    cmp::Ordering::Equal
+                },
+                Rank::Proxy(_) => {
+                    // a busy (or missing) device can not be asked, so fail rather than guess
+                    let id = self.device_id(a)?;
+                    let dev = self.device[id].as_ref().ok_or(E_BOUNDS)?;
+                    let a_handle = self.ram(self.cap_to_ptr(a)).y();
+                    let b_handle = self.ram(self.cap_to_ptr(b)).y();
+                    dev.compare_proxies(a_handle, b_handle).unwrap_or_else(|| a.raw().cmp(&b.raw()))
+                },
+                Rank::Atom | Rank::Actor => a.raw().cmp(&b.raw()),
+            };
+            if order != cmp::Ordering::Equal {
+                return Ok(order);
+            }
+        }
+        Ok(cmp::Ordering::Equal)
+    }
+    fn compare_rank(&self, value: Any) -> Rank {
+        if value.is_fix() {
+            Rank::Fixnum
+        } else if value.is_cap() {
+            let ptr = self.cap_to_ptr(value);
+            if !self.in_heap(ptr) {
+                Rank::Actor
+            } else if self.ram(ptr).t() == PROXY_T {
+                Rank::Proxy(self.ram(ptr).x().raw())
+            } else {
+                Rank::Actor
+            }
+        } else if value.is_rom() || self.in_heap(value) {
+            match self.mem(value).t() {
+                PAIR_T => Rank::Pair,
+                DICT_T => Rank::Dict,
+                _ => Rank::Atom,
+            }
+        } else {
+            Rank::Atom
+        }
     }
 
     pub fn dict_has(&self, dict: Any, key: Any) -> bool {
@@ -1345,6 +2448,25 @@ impl Core {
         }
     }
 
+    fn key_matches(&self, key: Any, k: Any, keys: KeyMatch) -> Result<bool, Error> {
+        match keys {
+            KeyMatch::Identity => Ok(key == k),
+            KeyMatch::Structural { max_cells } => self.deep_eq(key, k, max_cells),
+        }
+    }
+    pub fn dict_get_by(&self, dict: Any, key: Any, keys: KeyMatch) -> Result<Option<Any>, Error> {
+        // the value of the first binding for `key`, if any
+        let mut d = dict;
+        while self.typeq(DICT_T, d) {
+            let entry = self.mem(d);
+            if self.key_matches(key, entry.x(), keys)? {
+                return Ok(Some(entry.y()));
+            }
+            d = entry.z();  // next
+        }
+        Ok(None)
+    }
+
     pub fn deque_new(&self) -> Any { EMPTY_DQ }
     pub fn deque_empty(&self, deque: Any) -> bool {
         if self.typeq(PAIR_T, deque) {
@@ -1415,9 +2537,9 @@ impl Core {
         self.list_len(front) + self.list_len(back)
     }
 
-    fn e_first(&self) -> Any { self.t(DDEQUE) }
+    pub(crate) fn e_first(&self) -> Any { self.t(DDEQUE) }
     fn set_e_first(&mut self, ptr: Any) { self.set_t(DDEQUE, ptr); }
-    fn e_last(&self) -> Any { self.x(DDEQUE) }
+    pub(crate) fn e_last(&self) -> Any { self.x(DDEQUE) }
     fn set_e_last(&mut self, ptr: Any) { self.set_x(DDEQUE, ptr); }
     fn k_first(&self) -> Any { self.y(DDEQUE) }
     fn set_k_first(&mut self, ptr: Any) { self.set_y(DDEQUE, ptr); }
@@ -1440,6 +2562,8 @@ impl Core {
     fn new_sponsor(&mut self) -> Result<Any, Error> {
         let quad = Quad::quota(ZERO, ZERO, ZERO);
         let quota = self.alloc(&quad)?;
+        let parent = self.event_sponsor(self.ep());
+        self.set_z(quota, parent);  // the controlling sponsor
         let spn = Quad::sponsor_t(quota, ZERO);
         self.alloc(&spn)
     }
@@ -1718,12 +2842,24 @@ impl Core {
     pub fn alloc(&mut self, init: &Quad) -> Result<Any, Error> {
         let ep = self.ep();
         let sponsor = self.event_sponsor(ep);
+        self.sponsor_alloc(sponsor, init)
+    }
+    pub fn sponsor_alloc(&mut self, sponsor: Any, init: &Quad) -> Result<Any, Error> {
+        // allocate a cell charged to `sponsor`,
+        // which is refunded when the cell is freed
+        // (except to the root sponsor, which the host replenishes)
         let limit = self.sponsor_memory(sponsor).fix_num().unwrap_or(0);
         if limit <= 0 {
             return Err(E_MEM_LIM);  // Sponsor memory limit reached
         }
         let ptr = self.reserve(init)?;
-        self.set_sponsor_memory(sponsor, Any::fix(limit - 1));
+        self.put_sponsor_memory(sponsor, Any::fix(limit - 1));
+        if sponsor != SPONSOR {
+            self.gc_owners[ptr.ofs()] = sponsor;
+            let charges = self.sponsor_charges.entry(sponsor.ofs()).or_default();
+            charges.live += 1;
+            charges.refundable += 1;
+        }
         Ok(ptr)
     }
     pub fn reserve(&mut self, init: &Quad) -> Result<Any, Error> {
@@ -1756,6 +2892,14 @@ impl Core {
         };
         self.gc_store(ptr, *init);  // copy initial value
         self.gc_mark_cell(ptr);  // mark cell in-use when first allocated
+        if (init.t() == PROXY_T) && !self.device_proxies.is_empty() {
+            // remember proxies for registered devices, see `unregister_device`
+            if let Ok(id) = self.device_id(ptr) {
+                if let Some(proxies) = self.device_proxies.get_mut(&id) {
+                    proxies.insert(ptr.ofs());
+                }
+            }
+        }
         Ok(ptr)
     }
     pub fn free(&mut self, _ptr: Any) {
@@ -1771,18 +2915,61 @@ impl Core {
         if t == PROXY_T {
             // drop proxy
             if let Ok(id) = self.device_id(ptr) {
-                let mut dev_mut = self.device[id].take().unwrap();
-                let cap = self.ptr_to_cap(ptr);
-                dev_mut.drop_proxy(self, cap);
-                self.device[id] = Some(dev_mut);
+                if let Some(proxies) = self.device_proxies.get_mut(&id) {
+                    proxies.remove(&ptr.ofs());
+                }
+                if let Some(mut dev_mut) = self.device[id].take() {  // ignore unavailable devices
+                    let cap = self.ptr_to_cap(ptr);
+                    dev_mut.drop_proxy(self, cap);
+                    self.device[id] = Some(dev_mut);
+                }
+            }
+        }
+        let owner = self.gc_owners[ptr.ofs()];
+        if owner != UNDEF {
+            self.gc_owners[ptr.ofs()] = UNDEF;
+            self.refund_memory(owner);
+        }
+        if t == SPONSOR_T {
+            if let Some(charges) = self.sponsor_charges.remove(&ptr.ofs()) {
+                if charges.live > 0 {
+                    // cells may outlive the sponsor that was charged for them
+                    for owner in self.gc_owners.iter_mut() {
+                        if *owner == ptr {
+                            *owner = UNDEF;
+                        }
+                    }
+                }
             }
         }
+        self.books.forget(ptr.ofs(), t == SPONSOR_T);
         *self.ram_mut(ptr) = Quad::free_t(self.ram_next());  // clear cell to "free"
         self.gc_free_cell(ptr);  // mark cell as not-in-use when freed
         self.set_ram_next(ptr);  // link into free-list
         let n = self.ram_free().fix_num().unwrap();
         self.set_ram_free(Any::fix(n + 1));  // increment cells available
     }
+    fn refund_memory(&mut self, sponsor: Any) {
+        let Some(charges) = self.sponsor_charges.get_mut(&sponsor.ofs()) else {
+            return;
+        };
+        charges.live -= 1;
+        let refundable = charges.refundable > 0;
+        if refundable {
+            charges.refundable -= 1;
+        }
+        if charges.live == 0 {
+            self.sponsor_charges.remove(&sponsor.ofs());
+        }
+        if !refundable {
+            return;  // charged before the quota was last set
+        }
+        if (self.gc_phase == GcPhase::Sweep) && (self.gc_marks[sponsor.ofs()] == self.gc_prev) {
+            return;  // sponsor (and its quota) are also garbage
+        }
+        let n = self.sponsor_memory(sponsor).fix_num().unwrap_or(0);
+        self.put_sponsor_memory(sponsor, Any::fix(n + 1));
+    }
 
     pub fn gc_color(&self, ptr: Any) -> Any {  // report color to debugger
         if ptr.is_ram() {
@@ -1840,6 +3027,9 @@ impl Core {
                     self.gc_addr = Any::ram(RAM_BASE_OFS);  // start after reserved RAM
                     self.gc_scan_cell(self.ram_root());
                     self.gc_scan_cell(self.e_first());
+                    for ep in self.books.sched.ready_events() {
+                        self.gc_scan_cell(ep);
+                    }
                     self.gc_scan_cell(self.k_first());
                     self.gc_scan_cell(self.sponsor_signal(SPONSOR));
                     self.gc_phase = GcPhase::Mark;
@@ -1930,7 +3120,7 @@ impl Core {
         }
         if quad.t() == ACTOR_T {
             let id = quad.x().get_fix()? as usize;
-            if id < DEVICE_MAX {
+            if id < self.device.len() {
                 Ok(id)
             } else {
                 Err(E_BOUNDS)
@@ -2599,4 +3789,966 @@ pub const COUNT_TO: Any = Any { raw: COUNT_TO_OFS as Raw };
         assert_eq!(None, core.audit_err);
     }
 
+
+    #[derive(Default)]
+    struct Counter {
+        events: usize,
+        dropped: usize,
+    }
+
+    impl Device for Counter {
+        fn handle_event(&mut self, _core: &mut Core, _ep: Any) -> Result<Any, Error> {
+            self.events += 1;
+            Ok(UNDEF)
+        }
+        fn drop_proxy(&mut self, _core: &mut Core, _cap: Any) {
+            self.dropped += 1;
+        }
+    }
+
+    #[test]
+    fn register_devices() {
+        let mut core = Core::default();
+        core.init();
+        let counters: Vec<_> = (0..3).map(|_| {
+            alloc::rc::Rc::new(::core::cell::RefCell::new(Counter::default()))
+        }).collect();
+        let caps: Vec<Any> = counters.iter().map(|counter| {
+            core.register_device(Box::new(counter.clone())).unwrap()
+        }).collect();
+        assert_eq!(Ok(DEVICE_FIXED + 2), core.device_id(caps[2]));
+        let proxy = core.reserve_proxy(caps[1], UNDEF).unwrap();
+        core.reserve_stub(caps[1], proxy).unwrap();  // keep proxy alive
+        core.gc_collect_all();  // registered devices are GC roots
+        for &cap in &caps {
+            assert_eq!(ACTOR_T, core.ram(core.cap_to_ptr(cap)).t());
+            let evt = core.reserve_event(SPONSOR, cap, UNDEF).unwrap();
+            core.event_enqueue(evt);
+        }
+        assert_eq!(ZERO, core.run_loop(0));
+        assert!(counters.iter().all(|counter| counter.borrow().events == 1));
+        assert_eq!(0, counters[1].borrow().dropped);
+        let freed = core.reserve_proxy(caps[1], UNDEF).unwrap();
+        core.free(core.cap_to_ptr(freed));
+        assert_eq!(1, counters[1].borrow().dropped);
+
+        // unregistering drops (and retires) outstanding proxies (only)
+        core.unregister_device(caps[1]).unwrap();
+        assert_eq!(2, counters[1].borrow().dropped);
+        assert!(core.device_id(proxy).is_err());
+        assert!(core.device_id(caps[1]).is_err());
+        assert!(core.unregister_device(caps[1]).is_err());
+        assert_eq!(Err(E_BOUNDS), core.unregister_device(BLOB_DEV).map(|_| ()));
+        let evt = core.reserve_event(SPONSOR, proxy, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(1, counters[1].borrow().events);  // ignored
+
+        // ids are reused, but not by retired actors
+        let counter = alloc::rc::Rc::new(::core::cell::RefCell::new(Counter::default()));
+        let cap = core.register_device(Box::new(counter.clone())).unwrap();
+        assert_eq!(Ok(DEVICE_FIXED + 1), core.device_id(cap));
+        for target in [caps[1], proxy] {
+            let evt = core.reserve_event(SPONSOR, target, UNDEF).unwrap();
+            core.event_enqueue(evt);
+        }
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(0, counter.borrow().events);
+        assert_eq!(1, counters[1].borrow().events);
+    }
+
+
+    #[test]
+    fn call_and_await_reply() {
+        let mut core = Core::default();
+        core.init();
+        load_fib_test(&mut core);
+        let fib_beh = Any::rom(ROM_BASE_OFS+3);  // F_FIB_BEH, message: cust,n
+        let fib_ptr = core.reserve(&Quad::new_actor(fib_beh, UNDEF)).unwrap();
+        let a_fib = core.ptr_to_cap(fib_ptr);
+        core.reserve_stub(DEBUG_DEV, a_fib).unwrap();  // keep `a_fib` alive between calls
+        let reply = core.call(a_fib, PLUS_6, SPONSOR).unwrap();
+        assert_eq!(Ok(None), core.poll_reply(&reply));
+        assert_eq!(Ok(Some(Any::fix(8))), core.await_reply(&reply, 10_000));
+        assert_eq!(Err(E_FAIL), core.poll_reply(&reply));  // already taken
+
+        // time out, then resume waiting
+        core.set_sponsor_events(SPONSOR, Any::fix(1024));
+        let reply = core.call(a_fib, Any::fix(9), SPONSOR).unwrap();
+        assert_eq!(Ok(None), core.await_reply(&reply, 10));
+        assert_eq!(Ok(Some(Any::fix(34))), core.await_reply(&reply, 10_000));
+
+        // the root sponsor's error signal is reported
+        let reply = core.call(a_fib, Any::fix(9), SPONSOR).unwrap();
+        core.set_sponsor_cycles(SPONSOR, Any::fix(100));
+        assert_eq!(Err(E_CPU_LIM), core.await_reply(&reply, 10_000));
+
+        // the root quota is refilled once per wait, not once per step
+        let mut core = Core::default();
+        core.init();
+        load_fib_test(&mut core);
+        let fib_ptr = core.reserve(&Quad::new_actor(fib_beh, UNDEF)).unwrap();
+        let a_fib = core.ptr_to_cap(fib_ptr);
+        core.set_root_quota(Quota { cycles: 100, ..Quota::ROOT });
+        core.set_root_sponsor_policy(RootSponsorPolicy::RefillPerCall);
+        let reply = core.call(a_fib, Any::fix(9), SPONSOR).unwrap();
+        assert_eq!(Err(E_CPU_LIM), core.await_reply(&reply, 10_000));
+        core.set_root_quota(Quota::ROOT);
+        assert_eq!(Ok(Some(Any::fix(34))), core.await_reply(&reply, 10_000));
+
+        // the customer is dropped without a reply
+        let mut core = Core::default();
+        core.init();
+        let sink_beh = load_sink(&mut core);
+        let sink_ptr = core.reserve(&Quad::new_actor(sink_beh, UNDEF)).unwrap();
+        let a_sink = core.ptr_to_cap(sink_ptr);
+        let reply = core.call(a_sink, UNDEF, SPONSOR).unwrap();
+        assert_eq!(Err(E_FAIL), core.await_reply(&reply, 100));
+    }
+
+    #[test]
+    fn root_sponsor_policies() {
+        const OUT_OF_CPU: Any = Any { raw: DIR_RAW | E_CPU_LIM as u32 };
+        const SMALL: Quota = Quota { memory: 1024, events: 1024, cycles: 64 };
+        fn boot_fib(core: &mut Core) {
+            let boot_beh = load_fib_test(core);
+            let boot_ptr = core.reserve(&Quad::new_actor(boot_beh, NIL)).unwrap();
+            let a_boot = core.ptr_to_cap(boot_ptr);
+            let evt = core.reserve_event(SPONSOR, a_boot, UNDEF);
+            core.event_enqueue(evt.unwrap());
+        }
+
+        // the root quota is configurable
+        let mut core = Core::default();
+        core.set_root_quota(SMALL);
+        core.init();
+        assert_eq!(SMALL, core.sponsor_quota(SPONSOR));
+        boot_fib(&mut core);
+        assert_eq!(OUT_OF_CPU, core.run_loop(0));
+
+        // refill at the start of each call
+        core.set_root_sponsor_policy(RootSponsorPolicy::RefillPerCall);
+        let mut calls = 1;
+        while core.run_loop(0) == OUT_OF_CPU {
+            calls += 1;
+        }
+        assert_eq!(ZERO, core.sponsor_signal(SPONSOR));
+        assert!(calls > 2);
+        assert_eq!(None, core.audit_err);
+
+        // never reach a limit
+        let mut core = Core::default();
+        core.set_root_quota(SMALL);
+        core.init();
+        core.set_root_sponsor_policy(RootSponsorPolicy::Unlimited);
+        boot_fib(&mut core);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert!(core.sponsor_cycles(SPONSOR).get_fix().unwrap() > SMALL.cycles);
+
+        // refill on demand, until the host's budget is spent
+        let mut core = Core::default();
+        core.set_root_quota(SMALL);
+        core.init();
+        let mut refills = 0;
+        core.set_root_sponsor_policy(RootSponsorPolicy::Callback(Box::new(move |error, remaining| {
+            assert_eq!(E_CPU_LIM, error);
+            assert!(remaining.cycles < SMALL.cycles);
+            refills += 1;
+            if refills > 3 {
+                return None;
+            }
+            Some(Quota { cycles: SMALL.cycles, ..remaining })
+        })));
+        boot_fib(&mut core);
+        assert_eq!(OUT_OF_CPU, core.run_loop(0));
+        core.set_root_sponsor_policy(RootSponsorPolicy::Callback(Box::new(|_, _| Some(Quota::ROOT))));
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(None, core.audit_err);
+
+        // subordinate sponsors are still limited
+        let mut core = Core::default();
+        core.init();
+        core.set_root_sponsor_policy(RootSponsorPolicy::Unlimited);
+        let boot_beh = load_fib_test(&mut core);
+        let sink_beh = load_sink(&mut core);
+        let sink_ptr = core.reserve(&Quad::new_actor(sink_beh, NIL)).unwrap();
+        let a_sink = core.ptr_to_cap(sink_ptr);
+        let ctl_evt = core.reserve_event(SPONSOR, a_sink, UNDEF).unwrap();  // controller notification
+        let quota = core.reserve(&Quad::quota(PLUS_16K, PLUS_16K, Any::fix(8))).unwrap();
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ctl_evt)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive after it stops
+        let boot_ptr = core.reserve(&Quad::new_actor(boot_beh, NIL)).unwrap();
+        let a_boot = core.ptr_to_cap(boot_ptr);
+        let evt = core.reserve_event(sponsor, a_boot, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(OUT_OF_CPU, core.sponsor_signal(sponsor));
+    }
+
+    #[test]
+    fn memory_quota_is_refunded() {
+        const MEMORY_QUOTA: isize = 128;  // much less than fib(6) allocates
+        let mut core = Core::default();
+        core.init();
+        let boot_beh = load_fib_test(&mut core);
+        let sink_beh = load_sink(&mut core);
+        let sink_ptr = core.reserve(&Quad::new_actor(sink_beh, NIL)).unwrap();
+        let a_sink = core.ptr_to_cap(sink_ptr);
+        let ctl_evt = core.reserve_event(SPONSOR, a_sink, UNDEF).unwrap();  // controller notification
+        let quota = core.reserve(&Quad::quota(Any::fix(MEMORY_QUOTA), PLUS_16K, PLUS_16K)).unwrap();
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ctl_evt)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+        let boot_ptr = core.reserve(&Quad::new_actor(boot_beh, NIL)).unwrap();
+        let a_boot = core.ptr_to_cap(boot_ptr);
+        let evt = core.reserve_event(sponsor, a_boot, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(ctl_evt, core.sponsor_signal(sponsor));  // no limit reached
+        core.gc_collect_all();
+        assert_eq!(Any::fix(MEMORY_QUOTA), core.sponsor_memory(sponsor));
+        assert_eq!(None, core.audit_err);
+    }
+
+    #[test]
+    fn memory_refunds_are_capped() {
+        let mut core = Core::default();
+        core.init();
+        let quota = core.reserve(&Quad::quota(Any::fix(8), PLUS_16K, PLUS_16K)).unwrap();
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+        for _ in 0..4 {
+            core.sponsor_alloc(sponsor, &Quad::pair_t(UNDEF, UNDEF)).unwrap();
+        }
+        assert_eq!(PLUS_4, core.sponsor_memory(sponsor));
+
+        // cells charged before the host sets the quota are not refunded
+        core.set_sponsor_memory(sponsor, Any::fix(6));
+        core.sponsor_alloc(sponsor, &Quad::pair_t(UNDEF, UNDEF)).unwrap();
+        core.gc_collect_all();
+        assert_eq!(Any::fix(6), core.sponsor_memory(sponsor));
+        assert!(core.sponsor_charges.is_empty());
+    }
+
+    #[test]
+    fn sponsor_time_budgets() {
+        use alloc::rc::Rc;
+        use ::core::cell::Cell;
+        const OUT_OF_TIME: Any = Any { raw: DIR_RAW | E_TIME_LIM as u32 };
+        let mut core = Core::default();
+        core.init();
+        let ticks = Rc::new(Cell::new(0));
+        let clock = ticks.clone();
+        core.set_clock_fn(move || {
+            clock.set(clock.get() + 1);  // time advances each time the clock is read
+            clock.get()
+        });
+        let boot_beh = load_fib_test(&mut core);
+        let sink_beh = load_sink(&mut core);
+        let sink_ptr = core.reserve(&Quad::new_actor(sink_beh, NIL)).unwrap();
+        let a_sink = core.ptr_to_cap(sink_ptr);
+        core.reserve_stub(DEBUG_DEV, a_sink).unwrap();  // keep `a_sink` alive
+        let quota = core.reserve(&Quad::quota(PLUS_16K, PLUS_16K, PLUS_16K)).unwrap();
+        core.set_z(quota, SPONSOR);  // parent
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+        core.start_sponsor(sponsor, a_sink).unwrap();
+        let boot_ptr = core.reserve(&Quad::new_actor(boot_beh, NIL)).unwrap();
+        let a_boot = core.ptr_to_cap(boot_ptr);
+        core.reserve_stub(DEBUG_DEV, a_boot).unwrap();  // keep `a_boot` alive
+
+        // the deadline passes before the work is done
+        let budget = TimeBudget { deadline: Some(ticks.get() + 50), per_event: None };
+        core.set_sponsor_time_budget(sponsor, budget).unwrap();
+        assert_eq!(budget, core.sponsor_time_budget(sponsor));
+        let evt = core.reserve_event(sponsor, a_boot, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(OUT_OF_TIME, core.sponsor_signal(sponsor));
+        assert!(core.sponsor_info(sponsor).unwrap().waiting > 0);
+
+        // extend the deadline, and resume
+        let budget = TimeBudget { deadline: Some(ticks.get() + 10_000), per_event: Some(1_000) };
+        core.set_sponsor_time_budget(sponsor, budget).unwrap();
+        core.start_sponsor(sponsor, a_sink).unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        assert!(core.sponsor_signal(sponsor).is_ram());  // no limit reached
+        assert_eq!(None, core.audit_err);
+
+        // each event must finish promptly
+        core.set_sponsor_time_budget(sponsor, TimeBudget { deadline: None, per_event: Some(2) }).unwrap();
+        let evt = core.reserve_event(sponsor, a_boot, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(OUT_OF_TIME, core.sponsor_signal(sponsor));
+
+        // a root sponsor deadline stops the run-loop
+        core.set_sponsor_time_budget(SPONSOR, TimeBudget { deadline: Some(0), per_event: None }).unwrap();
+        let evt = core.reserve_event(SPONSOR, a_sink, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(OUT_OF_TIME, core.run_loop(0));
+        core.set_sponsor_time_budget(SPONSOR, TimeBudget::default()).unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+
+        // unless the root sponsor policy replenishes it
+        let deadline = TimeBudget { deadline: Some(0), per_event: None };
+        core.set_root_sponsor_policy(RootSponsorPolicy::Unlimited);
+        core.set_sponsor_time_budget(SPONSOR, deadline).unwrap();
+        let evt = core.reserve_event(SPONSOR, a_sink, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(RunOutcome::Idle, core.run(0).outcome);
+        assert_eq!(TimeBudget::default(), core.sponsor_time_budget(SPONSOR));  // lifted
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let log = errors.clone();
+        core.set_root_sponsor_policy(RootSponsorPolicy::Callback(Box::new(move |error, remaining| {
+            log.borrow_mut().push(error);
+            Some(remaining)
+        })));
+        core.set_sponsor_time_budget(SPONSOR, deadline).unwrap();
+        let evt = core.reserve_event(SPONSOR, a_sink, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(RunOutcome::Idle, core.run(0).outcome);
+        assert_eq!(vec![E_TIME_LIM], *errors.borrow());
+        core.set_root_sponsor_policy(RootSponsorPolicy::Fixed);
+        assert_eq!(Err(E_BOUNDS), core.set_sponsor_time_budget(a_sink, budget));
+    }
+
+    #[test]
+    fn run_outcomes() {
+        let mut core = Core::default();
+        core.init();
+        let boot_beh = load_fib_test(&mut core);
+        let boot_ptr = core.reserve(&Quad::new_actor(boot_beh, NIL)).unwrap();
+        let a_boot = core.ptr_to_cap(boot_ptr);
+        core.reserve_stub(DEBUG_DEV, a_boot).unwrap();  // keep `a_boot` alive while idle
+        let evt = core.reserve_event(SPONSOR, a_boot, UNDEF);
+        core.event_enqueue(evt.unwrap());
+        let events = |core: &Core| core.sponsor_events(SPONSOR).fix_num().unwrap() as usize;
+        let before = events(&core);
+        let report = core.run(16);
+        assert_eq!(RunOutcome::StepLimit, report.outcome);
+        assert_eq!(16, report.steps);
+        assert!(report.instructions > 0);
+        assert!(report.events > 0);
+        assert_eq!(before - events(&core), report.events);  // each charged to the sponsor
+        core.set_sponsor_cycles(SPONSOR, PLUS_4);
+        let report = core.run(0);
+        assert_eq!(RunOutcome::RootQuotaExhausted { kind: QuotaKind::Cycles }, report.outcome);
+        assert!(report.instructions >= 4);
+        core.set_sponsor_cycles(SPONSOR, Any::fix(8192));
+        let report = core.run(0);
+        assert_eq!(RunOutcome::Idle, report.outcome);
+        assert_eq!(ZERO, core.run_loop(0));
+        let report = core.run(0);
+        assert_eq!(RunReport { outcome: RunOutcome::Idle, steps: 0, instructions: 0, events: 0 }, report);
+
+        // an event deferred while its target is busy is counted once, when dispatched
+        for _ in 0..2 {
+            let evt = core.reserve_event(SPONSOR, a_boot, UNDEF).unwrap();
+            core.event_enqueue(evt);
+        }
+        core.set_sponsor_events(SPONSOR, Any::fix(256));
+        let report = core.run(3);
+        assert_eq!(1, report.events);  // the second event waits for the first
+        let report = core.run(0);
+        assert_eq!(RunOutcome::Idle, report.outcome);
+        assert_eq!(256 - events(&core), report.events + 1);
+    }
+
+    #[test]
+    fn interrupt_run_loop() {
+        let mut core = Core::default();
+        core.init();
+        // an actor that sends itself messages forever
+        fn k(ofs: usize) -> Any { Any::rom(ROM_BASE_OFS+ofs) }
+        let quad_rom = &mut core.quad_rom;
+        quad_rom[k(0).ofs()] = Quad::vm_end_commit();
+        quad_rom[k(1).ofs()] = Quad::vm_actor_send(k(0));
+        quad_rom[k(2).ofs()] = Quad::vm_actor_self(k(1));
+        quad_rom[k(3).ofs()] = Quad::vm_push(UNDEF, k(2));
+        core.rom_top = k(4);
+        let loop_ptr = core.reserve(&Quad::new_actor(k(3), NIL)).unwrap();
+        let a_loop = core.ptr_to_cap(loop_ptr);
+        let evt = core.reserve_event(SPONSOR, a_loop, UNDEF);
+        core.event_enqueue(evt.unwrap());
+        core.set_root_sponsor_policy(RootSponsorPolicy::Unlimited);
+
+        // a pending interrupt stops the next run
+        let handle = core.interrupt_handle();
+        handle.interrupt();
+        assert!(handle.is_interrupted());
+        let report = core.run(0);
+        assert_eq!(RunOutcome::Interrupted, report.outcome);
+        assert_eq!(0, report.steps);
+        assert!(!handle.is_interrupted());
+        assert_eq!(RunOutcome::StepLimit, core.run(100).outcome);
+
+        // interrupt from another thread
+        let remote = handle.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            remote.interrupt();
+        });
+        assert_eq!(UNDEF, core.run_loop(0));  // runnable
+        thread.join().unwrap();
+        assert_eq!(RunOutcome::StepLimit, core.run(100).outcome);  // heap is consistent
+        assert_eq!(None, core.audit_err);
+    }
+
+    #[test]
+    fn host_sponsor_control() {
+        use alloc::rc::Rc;
+        use alloc::vec;
+        use ::core::cell::RefCell;
+        struct Controller {
+            log: Rc<RefCell<Vec<Any>>>,
+        }
+        impl Device for Controller {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                let sponsor = core.event_message(ep);
+                self.log.borrow_mut().push(core.sponsor_signal(sponsor));
+                Ok(UNDEF)
+            }
+        }
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Core::default();
+        core.init();
+        let control = core.register_device(Box::new(Controller { log: log.clone() })).unwrap();
+        let sink_beh = load_sink(&mut core);
+        let sink_ptr = core.reserve(&Quad::new_actor(sink_beh, NIL)).unwrap();
+        let a_sink = core.ptr_to_cap(sink_ptr);
+        core.reserve_stub(DEBUG_DEV, a_sink).unwrap();  // keep `a_sink` alive
+        let quota = core.reserve(&Quad::quota(ZERO, ZERO, ZERO)).unwrap();
+        core.set_z(quota, SPONSOR);  // parent
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+
+        // enumerate sponsors
+        let sponsors = core.sponsors();
+        assert_eq!(2, sponsors.len());
+        assert_eq!(SPONSOR, sponsors[0].sponsor);
+        assert_eq!(UNDEF, sponsors[0].parent);
+        assert_eq!(SponsorInfo {
+            sponsor,
+            parent: SPONSOR,
+            quota: Quota { memory: 0, events: 0, cycles: 0 },
+            signal: ZERO,
+            waiting: 0,
+        }, sponsors[1]);
+        assert_eq!(Err(E_BOUNDS), core.sponsor_info(quota));
+
+        // refill and start
+        let refill = Quota { memory: 16, events: 16, cycles: 64 };
+        core.refill_sponsor(sponsor, refill).unwrap();
+        assert_eq!(refill, core.sponsor_quota(sponsor));
+        assert_eq!(Err(E_NOT_CAP), core.start_sponsor(sponsor, UNDEF));
+        core.start_sponsor(sponsor, control).unwrap();
+        assert_eq!(Err(E_BOUNDS), core.start_sponsor(sponsor, control));  // already running
+        assert!(core.sponsor_signal(sponsor).is_ram());
+
+        // suspended sponsors defer their events
+        core.suspend_sponsor(sponsor).unwrap();
+        assert_eq!(Err(E_BOUNDS), core.suspend_sponsor(sponsor));
+        assert_eq!(Err(E_BOUNDS), core.suspend_sponsor(SPONSOR));
+        let evt = core.reserve_event(sponsor, a_sink, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![Any::fix(E_STOP as isize)], *log.borrow());
+        assert_eq!(1, core.sponsor_info(sponsor).unwrap().waiting);
+        core.start_sponsor(sponsor, control).unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(0, core.sponsor_info(sponsor).unwrap().waiting);
+        assert_eq!(15, core.sponsor_quota(sponsor).events);
+
+        // stopped sponsors return their quota to their parent
+        let root_events = core.sponsor_quota(SPONSOR).events;
+        core.stop_sponsor(sponsor).unwrap();
+        assert_eq!(root_events + 15, core.sponsor_quota(SPONSOR).events);
+        assert_eq!(ZERO, core.sponsor_signal(sponsor));
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![Any::fix(E_STOP as isize), ZERO], *log.borrow());
+        assert_eq!(Err(E_BOUNDS), core.stop_sponsor(SPONSOR));
+    }
+
+    #[test]
+    fn scheduling_policies() {
+        use alloc::rc::Rc;
+        use alloc::vec;
+        use ::core::cell::RefCell;
+        type Log = Rc<RefCell<Vec<(Any, Any)>>>;
+        struct Recorder {
+            log: Log,
+        }
+        impl Device for Recorder {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                self.log.borrow_mut().push((core.event_sponsor(ep), core.event_message(ep)));
+                Ok(UNDEF)
+            }
+        }
+        struct Relay {
+            target: Any,
+        }
+        impl Device for Relay {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                core.reserve_event(core.event_sponsor(ep), self.target, core.event_message(ep))
+            }
+        }
+        let log: Log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Core::default();
+        core.init();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        let relay = core.register_device(Box::new(Relay { target: recorder })).unwrap();
+        let mut new_sponsor = || {
+            let quota = core.reserve(&Quad::quota(PLUS_16K, PLUS_16K, PLUS_16K)).unwrap();
+            core.set_z(quota, SPONSOR);  // parent
+            let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+            core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+            core.start_sponsor(sponsor, recorder).unwrap();
+            sponsor
+        };
+        let (a, b) = (new_sponsor(), new_sponsor());
+        let run = |core: &mut Core, policy: SchedulingPolicy, events: &[(Any, usize)]| {
+            core.set_scheduling_policy(policy);
+            for &(sponsor, n) in events {
+                for _ in 0..n {
+                    let evt = core.reserve_event(sponsor, recorder, UNDEF).unwrap();
+                    core.event_enqueue(evt);
+                }
+            }
+            assert_eq!(ZERO, core.run_loop(0));
+            let order: Vec<Any> = log.borrow().iter().map(|&(sponsor, _)| sponsor).collect();
+            log.borrow_mut().clear();
+            order
+        };
+
+        // a chatty sponsor delays a quiet one
+        assert_eq!(SchedulingPolicy::Fifo, core.scheduling_policy());
+        assert_eq!(vec![a, a, a, a, a, a, b, b], run(&mut core, SchedulingPolicy::Fifo, &[(a, 6), (b, 2)]));
+
+        // sponsors take turns
+        assert_eq!(vec![a, b, a, b, a, a, a, a], run(&mut core, SchedulingPolicy::SponsorRoundRobin, &[(a, 6), (b, 2)]));
+
+        // sponsors take turns in proportion to their weights
+        assert_eq!(Err(E_BOUNDS), core.set_sponsor_weight(b, 0));
+        assert_eq!(Err(E_BOUNDS), core.set_sponsor_weight(UNDEF, 1));
+        core.set_sponsor_weight(b, 3).unwrap();
+        assert_eq!(3, core.sponsor_weight(b));
+        assert_eq!(1, core.sponsor_weight(a));
+        assert_eq!(vec![a, b, b, b, b, a, b, b, a, a], run(&mut core, SchedulingPolicy::WeightedFair, &[(a, 4), (b, 6)]));
+
+        // higher priority events first, inherited by the events they cause
+        core.set_scheduling_policy(SchedulingPolicy::Priority);
+        for (target, msg, priority) in [(recorder, PLUS_1, 0), (recorder, PLUS_2, 1), (relay, PLUS_3, 5), (recorder, PLUS_4, 1)] {
+            let evt = core.reserve_event(a, target, msg).unwrap();
+            core.set_event_priority(evt, priority).unwrap();
+            assert_eq!(priority, core.event_priority(evt));
+            core.event_enqueue(evt);
+        }
+        assert_eq!(ZERO, core.run_loop(0));
+        let order: Vec<Any> = log.borrow().iter().map(|&(_, msg)| msg).collect();
+        assert_eq!(vec![PLUS_3, PLUS_2, PLUS_4, PLUS_1], order);
+        assert!(!core.books.sched.has_priorities());
+        log.borrow_mut().clear();
+
+        // events waiting to be chosen are kept when the policy changes
+        core.set_scheduling_policy(SchedulingPolicy::SponsorRoundRobin);
+        for sponsor in [a, a, a, b] {
+            let evt = core.reserve_event(sponsor, recorder, UNDEF).unwrap();
+            core.event_enqueue(evt);
+        }
+        core.run_loop(1);
+        assert_eq!(3, core.pending_events().len());
+        core.gc_collect_all();  // waiting events are GC roots
+        assert_eq!(vec![a, a, a, b], run(&mut core, SchedulingPolicy::Fifo, &[]));
+    }
+
+    #[test]
+    fn chaos_interleaves_continuations() {
+        crate::chaos::check_seeds(0..8, |core| {
+            load_fib_test(core);
+            let fib_beh = Any::rom(ROM_BASE_OFS+3);  // F_FIB_BEH, message: cust,n
+            let fib_ptr = core.reserve(&Quad::new_actor(fib_beh, UNDEF)).unwrap();
+            let a_fib = core.ptr_to_cap(fib_ptr);
+            let reply = core.call(a_fib, PLUS_6, SPONSOR).unwrap();
+            assert_eq!(Ok(Some(Any::fix(8))), core.await_reply(&reply, 10_000));
+            assert_eq!(None, core.audit_err);
+        });
+    }
+
+    #[test]
+    fn explore_dispatch_orders() {
+        use alloc::vec;
+        use crate::explore::*;
+        let mut core = Core::default();
+        core.init();
+        let sink = load_sink(&mut core);
+        let sinks: Vec<Any> = (0..3).map(|n| {
+            let ptr = core.reserve(&Quad::new_actor(sink, UNDEF)).unwrap();
+            let evt = core.reserve_event(SPONSOR, core.ptr_to_cap(ptr), Any::fix(n)).unwrap();
+            core.event_enqueue(evt);
+            ptr
+        }).collect();
+        let start = core.fingerprint();
+        let mut checked = 0;
+        let result = explore(&mut core, 8, |_| { checked += 1; Ok(()) });
+        assert_eq!(None, result.counterexample);
+        assert_eq!(8, result.states);  // one for each subset of delivered events
+        assert_eq!(8, checked);
+        assert_eq!(12, result.transitions);
+        assert_eq!(5, result.duplicates);
+        assert_eq!(0, result.truncated);
+        assert_eq!(start, core.fingerprint());  // restored
+        assert_eq!(3, core.pending_events().len());
+        assert_eq!(ACTOR_T, core.ram(sinks[2]).t());
+
+        // the depth bound
+        let result = explore(&mut core, 1, |_| Ok(()));
+        assert_eq!((4, 3, 3), (result.states, result.transitions, result.truncated));
+
+        // a failed check
+        let result = explore(&mut core, 8, |core| match core.pending_events().len() {
+            1 => Err("one left".into()),
+            _ => Ok(()),
+        });
+        let found = result.counterexample.unwrap();
+        assert_eq!(Violation::Check("one left".into()), found.violation);
+        let trace: Vec<Any> = found.trace.iter().map(|step| step.message).collect();
+        assert_eq!(vec![ZERO, PLUS_1], trace);
+        assert_eq!(1, core.pending_events().len());  // left in the violating state
+    }
+
+    #[test]
+    fn explore_finds_assert() {
+        use crate::explore::*;
+        let mut core = Core::default();
+        core.init();
+        // an actor that expects messages in order: 1, 2, ...
+pub const COMMIT: Any = Any { raw: ROM_BASE_OFS as Raw };
+pub const EXPECT_BEH: Any = Any { raw: (ROM_BASE_OFS+1) as Raw };
+        let quad_rom = &mut core.quad_rom;
+        quad_rom[COMMIT.ofs()]      = Quad::vm_end_commit();
+        quad_rom[EXPECT_BEH.ofs()]      = Quad::vm_msg(ZERO, Any::rom(EXPECT_BEH.ofs()+1));  // msg
+        quad_rom[EXPECT_BEH.ofs()+1]    = Quad::vm_state(ZERO, Any::rom(EXPECT_BEH.ofs()+2));  // msg state
+        quad_rom[EXPECT_BEH.ofs()+2]    = Quad::vm_cmp_eq(Any::rom(EXPECT_BEH.ofs()+3));  // msg==state
+        quad_rom[EXPECT_BEH.ofs()+3]    = Quad::vm_assert(TRUE, Any::rom(EXPECT_BEH.ofs()+4));  // --
+        quad_rom[EXPECT_BEH.ofs()+4]    = Quad::vm_state(ZERO, Any::rom(EXPECT_BEH.ofs()+5));  // state
+        quad_rom[EXPECT_BEH.ofs()+5]    = Quad::vm_push(PLUS_1, Any::rom(EXPECT_BEH.ofs()+6));  // state 1
+        quad_rom[EXPECT_BEH.ofs()+6]    = Quad::vm_alu_add(Any::rom(EXPECT_BEH.ofs()+7));  // state+1
+        quad_rom[EXPECT_BEH.ofs()+7]    = Quad::vm_push(EXPECT_BEH, Any::rom(EXPECT_BEH.ofs()+8));  // state+1 beh
+        quad_rom[EXPECT_BEH.ofs()+8]    = Quad::vm_actor_become(COMMIT);  // --
+        core.rom_top = Any::rom(EXPECT_BEH.ofs()+9);
+
+        let ptr = core.reserve(&Quad::new_actor(EXPECT_BEH, PLUS_1)).unwrap();
+        core.reserve_stub(DEBUG_DEV, core.ptr_to_cap(ptr)).unwrap();
+        for msg in [PLUS_1, PLUS_2] {
+            let evt = core.reserve_event(SPONSOR, core.ptr_to_cap(ptr), msg).unwrap();
+            core.event_enqueue(evt);
+        }
+        assert_eq!(ZERO, core.run_loop(0));  // FIFO order works
+        assert_eq!(PLUS_3, core.ram(ptr).y());
+
+        core.set_y(ptr, PLUS_1);
+        for msg in [PLUS_1, PLUS_2] {
+            let evt = core.reserve_event(SPONSOR, core.ptr_to_cap(ptr), msg).unwrap();
+            core.event_enqueue(evt);
+        }
+        let result = explore(&mut core, 8, |_| Ok(()));
+        let found = result.counterexample.unwrap();
+        assert_eq!(Violation::Audit { error: E_ASSERT }, found.violation);
+        assert_eq!(1, found.trace.len());
+        assert_eq!(Step { index: 1, target: core.ptr_to_cap(ptr), message: PLUS_2 }, found.trace[0]);
+        assert_eq!(3, result.states);
+    }
+
+    #[test]
+    fn trace_transactions() {
+        use alloc::rc::Rc;
+        use alloc::string::String;
+        use ::core::cell::RefCell;
+        use crate::trace::*;
+        struct Sink;
+        impl Device for Sink {
+            fn handle_event(&mut self, _core: &mut Core, _ep: Any) -> Result<Any, Error> {
+                Ok(UNDEF)
+            }
+        }
+        let mut core = Core::default();
+        core.init();
+pub const COMMIT: Any = Any { raw: ROM_BASE_OFS as Raw };
+pub const FWD_BEH: Any = Any { raw: (ROM_BASE_OFS+1) as Raw };
+pub const ABORT_BEH: Any = Any { raw: (ROM_BASE_OFS+9) as Raw };
+        let quad_rom = &mut core.quad_rom;
+        quad_rom[COMMIT.ofs()]      = Quad::vm_end_commit();
+        quad_rom[FWD_BEH.ofs()]         = Quad::vm_msg(ZERO, Any::rom(FWD_BEH.ofs()+1));  // msg
+        quad_rom[FWD_BEH.ofs()+1]       = Quad::vm_state(ZERO, Any::rom(FWD_BEH.ofs()+2));  // msg state
+        quad_rom[FWD_BEH.ofs()+2]       = Quad::vm_actor_send(Any::rom(FWD_BEH.ofs()+3));  // --
+        quad_rom[FWD_BEH.ofs()+3]       = Quad::vm_push(UNDEF, Any::rom(FWD_BEH.ofs()+4));  // #?
+        quad_rom[FWD_BEH.ofs()+4]       = Quad::vm_push(COMMIT, Any::rom(FWD_BEH.ofs()+5));  // #? sink_beh
+        quad_rom[FWD_BEH.ofs()+5]       = Quad::vm_actor_create(Any::rom(FWD_BEH.ofs()+6));  // sink
+        quad_rom[FWD_BEH.ofs()+6]       = Quad::vm_push(FWD_BEH, Any::rom(FWD_BEH.ofs()+7));  // sink fwd_beh
+        quad_rom[FWD_BEH.ofs()+7]       = Quad::vm_actor_become(COMMIT);  // --
+        quad_rom[ABORT_BEH.ofs()]       = Quad::vm_push(PLUS_1, Any::rom(ABORT_BEH.ofs()+1));  // reason=+1
+        quad_rom[ABORT_BEH.ofs()+1]     = Quad::vm_end_abort();
+        core.rom_top = Any::rom(ABORT_BEH.ofs()+2);
+        let sink = core.register_device(Box::new(Sink)).unwrap();
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let lines = Rc::new(RefCell::new(String::new()));
+        let (log, out) = (records.clone(), lines.clone());
+        let mut write = writer(TraceFormat::JsonLines, move |line| out.borrow_mut().push_str(line));
+        core.set_trace_fn(move |record| {
+            log.borrow_mut().push(record.clone());
+            write(record);
+        });
+        let fwd = core.reserve(&Quad::new_actor(FWD_BEH, sink)).unwrap();
+        let fwd = core.ptr_to_cap(fwd);
+        let abort = core.reserve(&Quad::new_actor(ABORT_BEH, UNDEF)).unwrap();
+        let abort = core.ptr_to_cap(abort);
+        let list = core.reserve(&Quad::pair_t(PLUS_2, NIL)).unwrap();
+        let list = core.reserve(&Quad::pair_t(PLUS_1, list)).unwrap();
+        for (target, msg) in [(fwd, list), (abort, TRUE)] {
+            let evt = core.reserve_event(SPONSOR, target, msg).unwrap();
+            core.event_enqueue(evt);
+        }
+        core.run_loop(0);
+        let records = records.borrow();
+        assert_eq!(3, records.len());
+
+        // the abort concludes first, since continuations are interleaved
+        let txn = &records[0];
+        assert_eq!((1, abort), (txn.id, txn.target));
+        assert_eq!(("#t", TxnOutcome::Abort { reason: String::from("+1") }), (txn.message.as_str(), txn.outcome.clone()));
+        assert!(txn.sent.is_empty() && txn.created.is_empty() && txn.became.is_none());
+
+        // an actor sends, creates, and becomes
+        let txn = &records[1];
+        assert_eq!((0, fwd, false), (txn.id, txn.target, txn.device));
+        assert_eq!("(+1 +2)", txn.message);
+        assert_eq!(vec![Sent { target: sink, message: String::from("(+1 +2)") }], txn.sent);
+        assert_eq!(1, txn.created.len());
+        assert_eq!(Some((FWD_BEH, print(txn.created[0]))), txn.became);
+        assert_eq!(TxnOutcome::Commit, txn.outcome);
+        assert_eq!(9, txn.cycles);
+        assert_eq!(records[0].cycles + txn.cycles, txn.end - txn.start);  // overlapped
+
+        // a device handles the message sent
+        let txn = &records[2];
+        assert_eq!((2, sink, true), (txn.id, txn.target, txn.device));
+        assert_eq!((String::from("(+1 +2)"), TxnOutcome::Commit), (txn.message.clone(), txn.outcome.clone()));
+
+        let lines = lines.borrow();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[1].starts_with("{\"id\":0,"));
+        assert!(lines[1].contains(",\"message\":\"(+1 +2)\",\"sent\":[{\"target\":"));
+        assert!(lines[0].ends_with(",\"outcome\":\"abort\",\"reason\":\"+1\"}"));
+        assert!(lines[2].contains(",\"device\":true,"));
+
+        // the Chrome trace format
+        let mut chrome = String::new();
+        let mut write = writer(TraceFormat::Chrome, |line| chrome.push_str(line));
+        write(&records[0]);
+        drop(write);
+        assert!(chrome.starts_with("[\n{\"name\":"));
+        assert!(chrome.contains(",\"cat\":\"actor\",\"ph\":\"X\","));
+        assert!(chrome.ends_with("\"}},\n"));
+    }
+
+    #[test]
+    fn causality_tracking() {
+        use alloc::rc::Rc;
+        use ::core::cell::RefCell;
+        type Log = Rc<RefCell<Vec<Option<Provenance>>>>;
+        struct Recorder {
+            log: Log,
+        }
+        impl Device for Recorder {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                self.log.borrow_mut().push(core.event_provenance(ep));
+                Ok(UNDEF)
+            }
+        }
+        struct Relay {
+            target: Any,
+        }
+        impl Device for Relay {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                core.reserve_event(core.event_sponsor(ep), self.target, core.event_message(ep))
+            }
+        }
+        struct Delay {  // like a timer, holds an event for the host to enqueue
+            target: Any,
+            held: Rc<RefCell<Option<Any>>>,
+        }
+        impl Device for Delay {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                let delayed = Quad::new_event(core.event_sponsor(ep), self.target, core.event_message(ep));
+                let ptr = core.reserve(&delayed)?;
+                core.set_event_cause(ptr, ep)?;
+                let stub = core.reserve_stub(self.target, ptr)?;
+                *self.held.borrow_mut() = Some(stub);
+                Ok(UNDEF)
+            }
+        }
+        let mut core = Core::default();
+        core.init();
+pub const FWD_BEH: Any = Any { raw: ROM_BASE_OFS as Raw };
+        let quad_rom = &mut core.quad_rom;
+        quad_rom[FWD_BEH.ofs()]         = Quad::vm_msg(ZERO, Any::rom(FWD_BEH.ofs()+1));  // msg
+        quad_rom[FWD_BEH.ofs()+1]       = Quad::vm_state(ZERO, Any::rom(FWD_BEH.ofs()+2));  // msg state
+        quad_rom[FWD_BEH.ofs()+2]       = Quad::vm_actor_send(Any::rom(FWD_BEH.ofs()+3));  // --
+        quad_rom[FWD_BEH.ofs()+3]       = Quad::vm_end_commit();
+        core.rom_top = Any::rom(FWD_BEH.ofs()+4);
+        let log: Log = Rc::new(RefCell::new(Vec::new()));
+        let held = Rc::new(RefCell::new(None));
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        let relay = core.register_device(Box::new(Relay { target: recorder })).unwrap();
+        let delay = core.register_device(Box::new(Delay { target: recorder, held: held.clone() })).unwrap();
+        let send = |core: &mut Core, state: Any| {
+            let fwd = core.reserve(&Quad::new_actor(FWD_BEH, state)).unwrap();
+            let evt = core.reserve_event(SPONSOR, core.ptr_to_cap(fwd), UNDEF).unwrap();
+            core.event_enqueue(evt);
+            assert_eq!(ZERO, core.run_loop(0));
+        };
+
+        // off by default
+        send(&mut core, relay);
+        assert_eq!(vec![None], *log.borrow());
+
+        // actor -> relay device -> recorder device
+        log.borrow_mut().clear();
+        core.set_causality(true);
+        send(&mut core, relay);
+        assert_eq!(vec![Some(Provenance { id: 2, cause: Some(1) })], *log.borrow());
+        assert_eq!(vec![2, 1, 0], core.causal_chain(2));
+
+        // actor -> delay device ... host -> recorder device
+        log.borrow_mut().clear();
+        send(&mut core, delay);
+        assert!(log.borrow().is_empty());
+        let stub = held.borrow_mut().take().unwrap();
+        let evt = core.ram(stub).y();
+        core.release_stub(stub);
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![Some(Provenance { id: 5, cause: Some(4) })], *log.borrow());
+        assert_eq!(vec![5, 4, 3], core.causal_chain(5));
+        assert_eq!(vec![9], core.causal_chain(9));  // unknown
+
+        core.set_causality(false);
+        assert!(core.books.causes.is_empty());
+    }
+
+    #[test]
+    fn structural_compare() {
+        use alloc::rc::Rc;
+        use ::core::cell::RefCell;
+        use ::core::cmp::Ordering::*;
+        use blob_dev::BlobDevice;
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let mut core = Core::default();
+        core.init();
+        core.install_device(BLOB_DEV, Box::new(blob_dev.clone()));
+        let list = |core: &mut Core, items: &[Any]| {
+            items.iter().rev().fold(NIL, |tail, &item| core.reserve(&Quad::pair_t(item, tail)).unwrap())
+        };
+        let a = list(&mut core, &[PLUS_1, PLUS_2]);
+        let b = list(&mut core, &[PLUS_1, PLUS_2]);
+        let c = list(&mut core, &[PLUS_1, PLUS_2, PLUS_3]);
+        let d = list(&mut core, &[PLUS_1, b]);
+        let e = list(&mut core, &[PLUS_1, a]);
+        assert_ne!(a, b);
+        assert!(core.deep_eq(a, b, 16).unwrap());
+        assert!(core.deep_eq(d, e, 16).unwrap());
+        assert_eq!(Less, core.compare(a, c, 16).unwrap());  // a prefix comes first
+        assert_eq!(Greater, core.compare(d, c, 16).unwrap());  // a pair after a fixnum
+        assert_eq!(Less, core.compare(MINUS_1, NIL, 16).unwrap());  // fixnums first
+
+        // blobs compare by contents
+        let x = blob_dev.borrow_mut().alloc_blob(&mut core, b"abc").unwrap();
+        let y = blob_dev.borrow_mut().alloc_blob(&mut core, b"abc").unwrap();
+        let z = blob_dev.borrow_mut().alloc_blob(&mut core, b"abd").unwrap();
+        assert_ne!(x, y);
+        assert!(core.deep_eq(x, y, 16).unwrap());
+        assert_eq!(Less, core.compare(y, z, 16).unwrap());
+        assert_eq!(Less, core.compare(x, DEBUG_DEV, 16).unwrap());  // proxies before actors
+
+        // bounds
+        assert_eq!(Err(E_CPU_LIM), core.compare(a, b, 1));  // two cells
+        let cycle = list(&mut core, &[PLUS_1]);
+        core.set_y(cycle, cycle);
+        let other = list(&mut core, &[PLUS_1]);
+        core.set_y(other, other);
+        assert_eq!(Err(E_CPU_LIM), core.compare(cycle, other, 1000));
+        let deep = (0..500).fold(NIL, |v, _| list(&mut core, &[v]));
+        let deeper = (0..500).fold(PLUS_1, |v, _| list(&mut core, &[v]));
+        assert_eq!(Less, core.compare(NIL, deep, 1000).unwrap());
+        assert_eq!(Greater, core.compare(deep, deeper, 1000).unwrap());  // nesting is not limited
+        assert_eq!(Err(E_CPU_LIM), core.compare(deep, deeper, 100));
+
+        // dicts compare as lists of entries
+        let ab = core.reserve(&Quad::dict_t(PLUS_2, PLUS_2, NIL)).unwrap();
+        let ab = core.reserve(&Quad::dict_t(PLUS_1, PLUS_1, ab)).unwrap();
+        let ba = core.reserve(&Quad::dict_t(PLUS_1, PLUS_1, NIL)).unwrap();
+        let ba = core.reserve(&Quad::dict_t(PLUS_2, PLUS_2, ba)).unwrap();
+        assert_eq!(Less, core.compare(ab, ba, 16).unwrap());
+
+        // proxies of a busy device are not compared
+        let blob_id = core.device_id(BLOB_DEV).unwrap();
+        let busy = core.device[blob_id].take();
+        assert_eq!(Err(E_BOUNDS), core.compare(x, y, 16));
+        core.device[blob_id] = busy;
+        assert!(core.deep_eq(x, y, 16).unwrap());
+
+        // dicts with structural keys
+        let dict = core.reserve(&Quad::dict_t(a, PLUS_1, NIL)).unwrap();
+        assert_eq!(None, core.dict_get_by(dict, b, KeyMatch::Identity).unwrap());
+        let keys = KeyMatch::Structural { max_cells: 16 };
+        assert_eq!(Some(PLUS_1), core.dict_get_by(dict, b, keys).unwrap());
+        let dict = core.reserve(&Quad::dict_t(x, PLUS_3, dict)).unwrap();
+        assert_eq!(Some(PLUS_3), core.dict_get_by(dict, y, keys).unwrap());
+        assert_eq!(Some(PLUS_1), core.dict_get_by(dict, b, keys).unwrap());
+        assert_eq!(None, core.dict_get_by(dict, z, keys).unwrap());
+        let dict = core.reserve(&Quad::dict_t(cycle, PLUS_4, dict)).unwrap();
+        assert_eq!(Some(PLUS_4), core.dict_get_by(dict, cycle, keys).unwrap());
+        assert_eq!(Err(E_CPU_LIM), core.dict_get_by(dict, other, keys));
+    }
+
+    #[test]
+    fn structural_equality_instruction() {
+        use alloc::rc::Rc;
+        use ::core::cell::Cell;
+        let mut core = Core::default();
+        core.init();
+pub const COMMIT: Any = Any { raw: ROM_BASE_OFS as Raw };
+pub const EQUAL_BEH: Any = Any { raw: (ROM_BASE_OFS+1) as Raw };
+        let quad_rom = &mut core.quad_rom;
+        quad_rom[COMMIT.ofs()]          = Quad::vm_end_commit();
+        quad_rom[EQUAL_BEH.ofs()]       = Quad::vm_msg(ZERO, Any::rom(EQUAL_BEH.ofs()+1));  // msg
+        quad_rom[EQUAL_BEH.ofs()+1]     = Quad::vm_state(ZERO, Any::rom(EQUAL_BEH.ofs()+2));  // msg state
+        quad_rom[EQUAL_BEH.ofs()+2]     = Quad::vm_cmp_equal(Any::rom(EQUAL_BEH.ofs()+3));  // msg==state
+        quad_rom[EQUAL_BEH.ofs()+3]     = Quad::vm_assert(TRUE, COMMIT);  // --
+        core.rom_top = Any::rom(EQUAL_BEH.ofs()+4);
+        let list = |core: &mut Core| {
+            [PLUS_3, PLUS_2, PLUS_1].iter().fold(NIL, |tail, &item| core.reserve(&Quad::pair_t(item, tail)).unwrap())
+        };
+        let state = list(&mut core);
+        let actor = core.reserve(&Quad::new_actor(EQUAL_BEH, state)).unwrap();
+        core.reserve_stub(DEBUG_DEV, core.ptr_to_cap(actor)).unwrap();
+        let cycles = Rc::new(Cell::new(0));
+        let used = cycles.clone();
+        core.set_trace_fn(move |record| used.set(record.cycles));
+        let run = |core: &mut Core, msg: Any| {
+            let evt = core.reserve_event(SPONSOR, core.ptr_to_cap(actor), msg).unwrap();
+            core.event_enqueue(evt);
+            assert_eq!(ZERO, core.run_loop(0));
+            cycles.get()
+        };
+        let same = run(&mut core, state);
+        assert_eq!(None, core.audit_err);
+        let msg = list(&mut core);
+        assert_eq!(same + 3, run(&mut core, msg));  // a cycle for each cell compared
+        let msg = core.reserve(&Quad::pair_t(PLUS_1, NIL)).unwrap();
+        run(&mut core, msg);
+        assert_eq!(Some(E_ASSERT), core.audit_err);
+    }
+
 }
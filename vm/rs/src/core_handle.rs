@@ -0,0 +1,205 @@
+// A CoreHandle lets other threads deliver work to a Core, such as the
+// completion of asynchronous device I/O performed on a worker thread.
+//
+// Posted work is pushed onto a lock-free inbox, which the Core drains
+// (in order) between run-loop steps, or when the host calls `drain_inbox`.
+// Values posted from another thread must remain valid until they are
+// drained, so heap references should be protected by a stub.
+//
+// An async host may register a waker, which is woken by the next post.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use ::core::cell::UnsafeCell;
+use ::core::ptr;
+use ::core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use ::core::task::Waker;
+
+use crate::*;
+
+pub type Task = Box<dyn FnOnce(&mut Core) + Send>;
+
+pub(crate) enum Inbound {
+    Event { sponsor: Any, target: Any, message: Any },
+    Release(Any),  // stub
+    Task(Task),
+}
+
+struct Node {
+    item: Inbound,
+    next: *mut Node,
+}
+
+// a multiple-producer, single-consumer stack of inbound work
+pub(crate) struct Inbox {
+    head: AtomicPtr<Node>,
+    locked: AtomicBool,  // guards `waker`
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl Inbox {
+    fn push(&self, item: Inbound) {
+        let node = Box::into_raw(Box::new(Node { item, next: ptr::null_mut() }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: `node` is not shared until the exchange succeeds
+            unsafe { (*node).next = head; }
+            match self.head.compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        if let Some(waker) = self.with_waker(Option::take) {
+            waker.wake();
+        }
+    }
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn register(&self, waker: &Waker) {
+        // wake `waker` when work is next posted
+        self.with_waker(|slot| match slot {
+            Some(current) if current.will_wake(waker) => {},
+            _ => *slot = Some(waker.clone()),
+        });
+    }
+    fn with_waker<T>(&self, f: impl FnOnce(&mut Option<Waker>) -> T) -> T {
+        while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            ::core::hint::spin_loop();  // held only briefly
+        }
+        // SAFETY: `locked` gives exclusive access to the slot
+        let result = f(unsafe { &mut *self.waker.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    pub(crate) fn take_all(&self) -> Vec<Inbound> {
+        // the consumer takes the whole stack at once, so nodes are never shared
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        let mut items = Vec::new();
+        while !node.is_null() {
+            // SAFETY: each node was leaked by `push`, and is reclaimed exactly once
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next;
+            items.push(boxed.item);
+        }
+        items.reverse();  // oldest first
+        items
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+}
+
+impl Default for Inbox {
+    fn default() -> Self {
+        Inbox {
+            head: AtomicPtr::new(ptr::null_mut()),
+            locked: AtomicBool::new(false),
+            waker: UnsafeCell::new(None),
+        }
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        self.take_all();  // free undelivered work
+    }
+}
+
+// SAFETY: only `Send` work is pushed, and nodes are owned by one thread at a time
+unsafe impl Send for Inbox {}
+unsafe impl Sync for Inbox {}
+
+// a reference to a Core, which may be cloned and sent to other threads
+#[derive(Clone)]
+pub struct CoreHandle {
+    inbox: Arc<Inbox>,
+}
+
+impl CoreHandle {
+    pub(crate) fn new(inbox: Arc<Inbox>) -> CoreHandle {
+        CoreHandle { inbox }
+    }
+    pub fn post_event(&self, sponsor: Any, target: Any, message: Any) {
+        // send `message` to `target`, on behalf of `sponsor`
+        self.inbox.push(Inbound::Event { sponsor, target, message });
+    }
+    pub fn post_release(&self, stub: Any) {
+        // release a stub (ignored if `stub` is no longer a stub)
+        self.inbox.push(Inbound::Release(stub));
+    }
+    pub fn post_task<F: FnOnce(&mut Core) + Send + 'static>(&self, task: F) {
+        // run `task` on the Core's thread, to allocate a reply (for example)
+        self.inbox.push(Inbound::Task(Box::new(task)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use alloc::vec;
+
+    use ::core::cell::RefCell;
+
+    use super::*;
+
+    struct Recorder {
+        log: Rc<RefCell<Vec<Any>>>,
+    }
+
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            self.log.borrow_mut().push(core.event_message(ep));
+            Ok(UNDEF)
+        }
+    }
+
+    #[test]
+    fn post_from_other_threads() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Core::default();
+        core.init();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        let handle = core.handle();
+        let workers: Vec<_> = (0..4).map(|n| {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                for i in 0..8 {
+                    handle.post_event(SPONSOR, recorder, Any::fix(n * 100 + i));
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(32, log.borrow().len());
+        for n in 0..4 {
+            let sent: Vec<Any> = log.borrow().iter().copied()
+                .filter(|msg| msg.get_fix().unwrap() / 100 == n)
+                .collect();
+            let expect: Vec<Any> = (0..8).map(|i| Any::fix(n * 100 + i)).collect();
+            assert_eq!(expect, sent);  // in order, for each thread
+        }
+
+        // a worker completes a request, replying with a heap value
+        log.borrow_mut().clear();
+        let stub = core.reserve_stub(recorder, recorder).unwrap();
+        let worker = handle.clone();
+        std::thread::spawn(move || {
+            worker.post_task(move |core: &mut Core| {
+                let msg = core.reserve(&Quad::pair_t(PLUS_1, PLUS_2)).unwrap();
+                core.reserve_stub(recorder, msg).unwrap();  // keep `msg` for inspection
+                let evt = core.reserve_event(SPONSOR, recorder, msg).unwrap();
+                core.event_enqueue(evt);
+            });
+            worker.post_release(stub);
+            worker.post_release(stub);  // ignored
+        }).join().unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        let msg = log.borrow()[0];
+        assert_eq!(vec![PLUS_1, PLUS_2], vec![core.car(msg), core.cdr(msg)]);
+        assert_ne!(STUB_T, core.ram(stub).t());
+    }
+
+}
@@ -0,0 +1,134 @@
+// An exhaustive explorer of event dispatch orders, for small actor systems.
+//
+// Starting from the current state of a Core, each pending event is tried
+// first (running its transaction to completion), recursively, up to a depth
+// bound. States are compared by a canonical fingerprint of the reachable
+// heap, so each distinct state is explored only once. The first violation
+// found is reported with the sequence of dispatches leading to it.
+//
+// Devices are not part of the explored state, so events sent to devices
+// should be handled deterministically (and without lasting side-effects).
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::*;
+
+// the dispatch of the `index`-th pending event (in queue order)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub index: usize,
+    pub target: Any,
+    pub message: Any,  // heap values are only meaningful before the step
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Audit { error: Error },  // reported to the audit hook (including `E_ASSERT`)
+    Stalled { waiting: Vec<Any> },  // sponsors with waiting events, but no pending events
+    Fatal(Error),  // signalled to the root sponsor
+    Check(String),  // from the caller's check
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Counterexample {
+    pub trace: Vec<Step>,
+    pub violation: Violation,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Exploration {
+    pub states: usize,  // distinct states visited
+    pub transitions: usize,  // transactions run
+    pub duplicates: usize,  // transitions to a state already visited
+    pub truncated: usize,  // states with pending events at the depth bound
+    pub counterexample: Option<Counterexample>,
+}
+
+pub fn explore<F>(core: &mut Core, max_depth: usize, check: F) -> Exploration
+where
+    F: FnMut(&Core) -> Result<(), String>,
+{
+    // explore every dispatch order, up to `max_depth` transactions,
+    // calling `check` in each distinct state.
+    // on a violation, `core` is left in the violating state,
+    // otherwise it is restored to its starting state.
+    core.take_audit_err();
+    let start = core.snapshot();
+    let mut search = Search {
+        check,
+        seen: BTreeSet::new(),
+        trace: Vec::new(),
+        result: Exploration::default(),
+    };
+    if !search.visit(core, max_depth) {
+        core.restore(&start);
+    }
+    search.result
+}
+
+struct Search<F> {
+    check: F,
+    seen: BTreeSet<Vec<Raw>>,
+    trace: Vec<Step>,
+    result: Exploration,
+}
+
+impl<F: FnMut(&Core) -> Result<(), String>> Search<F> {
+    fn visit(&mut self, core: &mut Core, depth: usize) -> bool {
+        // return `true` if a violation is found (from this state)
+        if !self.seen.insert(core.fingerprint()) {
+            self.result.duplicates += 1;
+            return false;
+        }
+        self.result.states += 1;
+        if let Err(reason) = (self.check)(core) {
+            return self.fail(Violation::Check(reason));
+        }
+        let events = core.pending_events();
+        if events.is_empty() {
+            let waiting: Vec<Any> = core.sponsors().iter()
+                .filter(|info| info.waiting > 0)
+                .map(|info| info.sponsor)
+                .collect();
+            if !waiting.is_empty() {
+                return self.fail(Violation::Stalled { waiting });
+            }
+            return false;
+        }
+        if depth == 0 {
+            self.result.truncated += 1;
+            return false;
+        }
+        let snapshot = core.snapshot();
+        for (index, &ep) in events.iter().enumerate() {
+            if index > 0 {
+                core.restore(&snapshot);
+            }
+            self.trace.push(Step {
+                index,
+                target: core.event_target(ep),
+                message: core.event_message(ep),
+            });
+            self.result.transitions += 1;
+            let violation = match core.run_transaction(index) {
+                Err(error) => Some(Violation::Fatal(error)),
+                Ok(()) => core.take_audit_err().map(|error| Violation::Audit { error }),
+            };
+            if let Some(violation) = violation {
+                return self.fail(violation);
+            }
+            if self.visit(core, depth - 1) {
+                return true;
+            }
+            self.trace.pop();
+        }
+        false
+    }
+    fn fail(&mut self, violation: Violation) -> bool {
+        let trace = self.trace.clone();
+        self.result.counterexample = Some(Counterexample { trace, violation });
+        true
+    }
+}
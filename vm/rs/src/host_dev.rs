@@ -0,0 +1,361 @@
+// The HostDevice provides "dynamic" devices, implemented by the embedder,
+// as described in `host_dev.md`.
+//
+// Each dynamic device hands out any number of proxies, each with a tag.
+// The tag is the proxy's handle, and the dynamic device that made the proxy
+// is found in a table keyed by the proxy's offset (so drops are routed
+// correctly, whatever else the garbage collector has already swept).
+// Messages sent to a proxy are held by an event stub until the dynamic device
+// releases them, so replies may be deferred (until `poll`, for example).
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+
+use ::core::cell::RefCell;
+use ::core::task::Waker;
+
+use crate::*;
+
+// an event received by one of a dynamic device's proxies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    stub: Any,  // protects the event (and its message) from garbage collection
+    pub sponsor: Any,  // sponsor of the event, charged for replies
+    pub tag: Any,  // tag of the proxy that received the message
+    pub message: Any,
+}
+
+impl Event {
+    pub fn stub(&self) -> Any {
+        self.stub
+    }
+    pub fn reply(self, core: &mut Core, target: Any, msg: Any) -> Result<(), Error> {
+        // send `msg` to `target` (on the event's sponsor), then release the event
+        let evt = core.reserve_event(self.sponsor, target, msg);
+        self.release(core);
+        core.event_enqueue(evt?);
+        Ok(())
+    }
+    pub fn release(self, core: &mut Core) {
+        core.release_stub(self.stub);
+    }
+}
+
+// abstract dynamic device interface
+pub trait DynamicDevice {
+    // On success, the dynamic device becomes responsible for releasing the event,
+    // otherwise the event is released by the host device.
+    fn handle_event(&mut self, core: &mut Core, event: Event) -> Result<(), Error>;
+    fn drop_proxy(&mut self, _core: &mut Core, _tag: Any) {}  // default: no-op
+    fn poll(&mut self, _core: &mut Core) -> Result<(), Error> { Ok(()) }  // default: no-op
+    fn pending(&self) -> bool { false }  // events held for `poll`, default: none
+    fn register_waker(&mut self, _waker: &Waker) -> bool { false }  // wake when `poll` has work, default: can't
+}
+
+// a dynamic device that only handles events
+impl<F: FnMut(&mut Core, Event) -> Result<(), Error>> DynamicDevice for F {
+    fn handle_event(&mut self, core: &mut Core, event: Event) -> Result<(), Error> {
+        self(core, event)
+    }
+}
+
+type Routes = Rc<RefCell<BTreeMap<usize, isize>>>;  // proxy offset -> dynamic device key
+
+// a reference to an installed dynamic device, used to make its proxies
+#[derive(Clone, Debug)]
+pub struct DynamicDev {
+    dev_cap: Any,
+    key: isize,
+    routes: Routes,
+}
+
+impl DynamicDev {
+    pub fn dev_cap(&self) -> Any {
+        self.dev_cap
+    }
+    pub fn reserve_proxy(&self, core: &mut Core, tag: Any) -> Result<Any, Error> {
+        let proxy = core.reserve_proxy(self.dev_cap, tag)?;
+        self.routes.borrow_mut().insert(core.cap_to_ptr(proxy).ofs(), self.key);
+        Ok(proxy)
+    }
+    pub fn tag(&self, core: &Core, proxy: Any) -> Option<Any> {
+        // the tag of a proxy made by this dynamic device, if any
+        if !self.owns_proxy(core, proxy) {
+            return None;
+        }
+        Some(core.ram(core.cap_to_ptr(proxy)).y())
+    }
+    pub fn owns_proxy(&self, core: &Core, proxy: Any) -> bool {
+        if !proxy.is_cap() {
+            return false;
+        }
+        let ptr = core.cap_to_ptr(proxy);
+        let quad = core.ram(ptr);
+        (quad.t() == PROXY_T)
+            && (quad.x() == self.dev_cap)
+            && (self.routes.borrow().get(&ptr.ofs()) == Some(&self.key))
+    }
+}
+
+pub struct HostDevice {
+    dev_cap: Any,
+    next_key: isize,
+    ddevs: BTreeMap<isize, Box<dyn DynamicDevice>>,
+    routes: Routes,
+}
+
+impl HostDevice {
+    pub fn new(dev_cap: Any) -> HostDevice {
+        // `dev_cap` is where this device is installed, usually `HOST_DEV`
+        HostDevice {
+            dev_cap,
+            next_key: 0,
+            ddevs: BTreeMap::new(),
+            routes: Rc::default(),
+        }
+    }
+    pub fn install(&mut self, ddev: Box<dyn DynamicDevice>) -> DynamicDev {
+        let key = self.next_key;
+        self.next_key += 1;
+        self.ddevs.insert(key, ddev);
+        DynamicDev {
+            dev_cap: self.dev_cap,
+            key,
+            routes: self.routes.clone(),
+        }
+    }
+    pub fn dispose(&mut self, ddev: &DynamicDev) -> Option<Box<dyn DynamicDevice>> {
+        // events and drops are no longer delivered to a disposed dynamic device
+        self.ddevs.remove(&ddev.key)
+    }
+    fn proxy_route(&self, core: &Core, proxy: Any) -> Result<(isize, Any), Error> {
+        let ptr = core.cap_to_ptr(proxy);
+        let quad = core.ram(ptr);
+        if quad.t() != PROXY_T {
+            return Err(E_NOT_CAP);  // sent directly to the host device
+        }
+        let key = *self.routes.borrow().get(&ptr.ofs()).ok_or(E_NOT_CAP)?;
+        Ok((key, quad.y()))
+    }
+}
+
+impl Device for HostDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let event = core.mem(ep);
+        let sponsor = event.t();
+        let target = event.x();
+        let message = event.y();
+        let (key, tag) = self.proxy_route(core, target)?;
+        let ddev = self.ddevs.get_mut(&key).ok_or(E_BOUNDS)?;
+        let stub = core.reserve_stub(self.dev_cap, ep)?;
+        if let Err(error) = ddev.handle_event(core, Event { stub, sponsor, tag, message }) {
+            core.release_stub(stub);
+            return Err(error);
+        }
+        Ok(UNDEF)  // effects are delivered as new events
+    }
+    fn drop_proxy(&mut self, core: &mut Core, cap: Any) {
+        if let Ok((key, tag)) = self.proxy_route(core, cap) {
+            self.routes.borrow_mut().remove(&core.cap_to_ptr(cap).ofs());
+            if let Some(ddev) = self.ddevs.get_mut(&key) {
+                ddev.drop_proxy(core, tag);
+            }
+        }
+    }
+    fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+        for ddev in self.ddevs.values_mut() {
+            ddev.poll(core)?;
+        }
+        Ok(())
+    }
+    fn pending(&self) -> bool {
+        self.ddevs.values().any(|ddev| ddev.pending())
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        // every pending dynamic device must arrange a wake-up
+        self.ddevs.values_mut()
+            .filter(|ddev| ddev.pending())
+            .all(|ddev| ddev.register_waker(waker))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use ::core::cell::RefCell;
+
+    use super::*;
+
+    struct Recorder {
+        log: Rc<RefCell<Vec<(Any, Any)>>>,
+    }
+
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);
+            self.log.borrow_mut().push((core.car(msg), core.cdr(msg)));
+            Ok(UNDEF)
+        }
+    }
+
+    // replies to `customer,value` with `tag,value`, once polled
+    struct Deferred {
+        pending: Vec<Event>,
+        dropped: Rc<RefCell<Vec<Any>>>,
+    }
+
+    impl DynamicDevice for Deferred {
+        fn handle_event(&mut self, core: &mut Core, event: Event) -> Result<(), Error> {
+            if !core.car(event.message).is_cap() {
+                return Err(E_NOT_CAP);
+            }
+            self.pending.push(event);
+            Ok(())
+        }
+        fn drop_proxy(&mut self, _core: &mut Core, tag: Any) {
+            self.dropped.borrow_mut().push(tag);
+        }
+        fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+            for event in self.pending.drain(..) {
+                let customer = core.car(event.message);
+                let value = core.cdr(event.message);
+                let msg = core.reserve(&Quad::pair_t(event.tag, value))?;
+                event.reply(core, customer, msg)?;
+            }
+            Ok(())
+        }
+        fn pending(&self) -> bool {
+            !self.pending.is_empty()
+        }
+    }
+
+    fn send(core: &mut Core, target: Any, msg: Any) {
+        let evt = core.reserve_event(SPONSOR, target, msg).unwrap();
+        core.event_enqueue(evt);
+    }
+
+    #[test]
+    fn host_dev_dynamic_devices() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let echo_log = Rc::new(RefCell::new(Vec::new()));
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let host = Rc::new(RefCell::new(HostDevice::new(HOST_DEV)));
+        let mut core = Core::default();
+        core.init();
+        core.install_device(HOST_DEV, Box::new(host.clone()));
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        let deferred = host.borrow_mut().install(Box::new(Deferred {
+            pending: Vec::new(),
+            dropped: dropped.clone(),
+        }));
+        let echoed = echo_log.clone();
+        let echo = host.borrow_mut().install(Box::new(move |core: &mut Core, event: Event| {
+            echoed.borrow_mut().push(event.message);
+            event.release(core);
+            Ok(())
+        }));
+
+        let proxy_t = deferred.reserve_proxy(&mut core, TRUE).unwrap();
+        let proxy_f = deferred.reserve_proxy(&mut core, FALSE).unwrap();
+        let proxy_e = echo.reserve_proxy(&mut core, UNDEF).unwrap();
+        let root = core.reserve_stub(HOST_DEV, proxy_t).unwrap();  // keep `proxy_t` alive
+        assert_eq!(Some(FALSE), deferred.tag(&core, proxy_f));
+        assert!(!echo.owns_proxy(&core, proxy_f));
+        assert_eq!(None, echo.tag(&core, proxy_t));
+
+        let msg = core.reserve(&Quad::pair_t(recorder, PLUS_1)).unwrap();
+        send(&mut core, proxy_t, msg);
+        let msg = core.reserve(&Quad::pair_t(recorder, PLUS_2)).unwrap();
+        send(&mut core, proxy_f, msg);
+        send(&mut core, proxy_e, PLUS_3);
+        assert_eq!(RunOutcome::WaitingOnDevices, core.run(0).outcome);
+        assert_eq!(vec![PLUS_3], *echo_log.borrow());
+        assert!(log.borrow().is_empty());  // replies are deferred
+        assert!(core.devices_pending());
+        core.poll_devices().unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![(TRUE, PLUS_1), (FALSE, PLUS_2)], *log.borrow());
+        assert!(!core.devices_pending());
+
+        // unreferenced proxies are dropped
+        core.gc_collect_all();
+        assert_eq!(vec![FALSE], *dropped.borrow());
+
+        // disposed dynamic devices are not informed
+        assert!(host.borrow_mut().dispose(&deferred).is_some());
+        assert!(host.borrow_mut().dispose(&deferred).is_none());
+        core.release_stub(root);
+        core.gc_collect_all();
+        assert_eq!(vec![FALSE], *dropped.borrow());
+    }
+
+    #[test]
+    fn host_dev_drop_after_tag() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let host = Rc::new(RefCell::new(HostDevice::new(HOST_DEV)));
+        let mut core = Core::default();
+        core.init();
+        core.install_device(HOST_DEV, Box::new(host.clone()));
+        let ddev = host.borrow_mut().install(Box::new(Deferred {
+            pending: Vec::new(),
+            dropped: dropped.clone(),
+        }));
+        // reuse cells from the free-list, so the tag is above its proxy
+        let low = core.reserve(&Quad::empty_t()).unwrap();
+        let high = core.reserve(&Quad::empty_t()).unwrap();
+        core.free(low);
+        core.free(high);
+        let tag = core.reserve(&Quad::pair_t(PLUS_1, NIL)).unwrap();
+        let proxy = ddev.reserve_proxy(&mut core, tag).unwrap();
+        assert!(tag.ofs() > core.cap_to_ptr(proxy).ofs());  // swept first (GC sweeps downward)
+        core.gc_collect_all();
+        assert_eq!(vec![tag], *dropped.borrow());
+        assert!(!ddev.owns_proxy(&core, proxy));
+    }
+
+    #[test]
+    fn host_dev_reply_sponsor() {
+        struct Sponsors {
+            log: Rc<RefCell<Vec<Any>>>,
+        }
+        impl Device for Sponsors {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                self.log.borrow_mut().push(core.event_sponsor(ep));
+                Ok(UNDEF)
+            }
+        }
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let host = Rc::new(RefCell::new(HostDevice::new(HOST_DEV)));
+        let mut core = Core::default();
+        core.init();
+        core.install_device(HOST_DEV, Box::new(host.clone()));
+        let sponsors = core.register_device(Box::new(Sponsors { log: log.clone() })).unwrap();
+        let ddev = host.borrow_mut().install(Box::new(Deferred {
+            pending: Vec::new(),
+            dropped: Rc::default(),
+        }));
+        let proxy = ddev.reserve_proxy(&mut core, UNDEF).unwrap();
+        core.reserve_stub(HOST_DEV, proxy).unwrap();  // keep `proxy` alive
+        let quota = core.reserve(&Quad::quota(PLUS_16K, PLUS_16K, PLUS_16K)).unwrap();
+        core.set_z(quota, SPONSOR);  // parent
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+        core.reserve_stub(HOST_DEV, sponsor).unwrap();  // keep `sponsor` alive
+        core.start_sponsor(sponsor, sponsors).unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        log.borrow_mut().clear();  // the control event
+
+        // replies are charged to the sponsor of the request
+        let msg = core.reserve(&Quad::pair_t(sponsors, PLUS_1)).unwrap();
+        let evt = core.reserve_event(sponsor, proxy, msg).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        core.poll_devices().unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![sponsor], *log.borrow());
+    }
+
+}
@@ -1,13 +1,52 @@
 #![cfg_attr(feature = "no_std", no_std)]
 
 extern crate alloc;
+extern crate self as ufork;  // for paths generated by `ufork-derive`
+#[cfg(any(test, not(feature = "no_std")))]
+extern crate std;
+
+use alloc::rc::Rc;
+
+use ::core::cell::RefCell;
+use ::core::cmp::Ordering;
+use ::core::task::Waker;
 
 pub mod any;
 pub mod quad;
 pub mod core;
+pub mod core_handle;
+mod sched;
+mod causality;
+mod budget;
+#[cfg(any(test, feature = "async"))]
+pub mod async_core;
 pub mod null_dev;
 pub mod fail_dev;
 pub mod blob_dev;
+pub mod host_dev;
+pub mod native_dev;
+mod reply_dev;
+mod slots;
+pub mod oed;
+pub mod convert;
+pub mod awp_dev;
+pub mod memory_transport;
+pub mod explore;
+pub mod replay;
+pub mod trace;
+#[cfg(any(test, not(feature = "no_std")))]
+pub mod bridge_dev;
+#[cfg(any(test, not(feature = "no_std")))]
+pub mod chaos;
+#[cfg(any(test, not(feature = "no_std")))]
+mod completions;
+#[cfg(any(test, not(feature = "no_std")))]
+pub mod tcp_dev;
+#[cfg(any(test, not(feature = "no_std")))]
+pub mod tcp_transport;
+
+#[cfg(any(test, feature = "derive"))]
+pub use ufork_derive::{FromValue, ToValue};
 
 use crate::any::*;
 use crate::core::*;
@@ -31,6 +70,7 @@ pub const E_MSG_LIM: Error  = -13;  // Sponsor event limit reached
 pub const E_ASSERT: Error   = -14;  // assertion failed
 pub const E_STOP: Error     = -15;  // actor transaction stopped
 pub const E_ABORT: Error    = -16;  // actor transaction aborted
+pub const E_TIME_LIM: Error = -17;  // Sponsor time limit reached
 
 pub type Raw = u32;  // univeral value type
 pub type Num = i32;  // fixnum integer type
@@ -46,4 +86,33 @@ pub trait Device {
     fn init(&mut self) {}  // runtime initialization, default: no-op
     fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error>;
     fn drop_proxy(&mut self, _core: &mut Core, _cap: Any) {}  // default: no-op
+    fn poll(&mut self, _core: &mut Core) -> Result<(), Error> { Ok(()) }  // default: no-op
+    fn pending(&self) -> bool { false }  // work remains for `poll`, default: none
+    fn register_waker(&mut self, _waker: &Waker) -> bool { false }  // wake when `poll` has work, default: can't
+    fn compare_proxies(&self, _a: Any, _b: Any) -> Option<Ordering> { None }  // by value, default: by identity
+}
+
+// a device shared with the host (or other devices)
+impl<D: Device> Device for Rc<RefCell<D>> {
+    fn init(&mut self) {
+        self.borrow_mut().init()
+    }
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        self.borrow_mut().handle_event(core, ep)
+    }
+    fn drop_proxy(&mut self, core: &mut Core, cap: Any) {
+        self.borrow_mut().drop_proxy(core, cap)
+    }
+    fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+        self.borrow_mut().poll(core)
+    }
+    fn pending(&self) -> bool {
+        self.borrow().pending()
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        self.borrow_mut().register_waker(waker)
+    }
+    fn compare_proxies(&self, a: Any, b: Any) -> Option<Ordering> {
+        self.borrow().compare_proxies(a, b)
+    }
 }
@@ -0,0 +1,153 @@
+// An in-memory AWP transport that is ordered and reliable,
+// like `memory_transport.js`. Each party joins a shared `MemoryNetwork`.
+//
+// Names and addresses are arbitrary values.
+// The bind_info equals the address, the identity equals the name.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+
+use ::core::cell::RefCell;
+use ::core::task::Waker;
+
+use crate::*;
+use crate::awp_dev::{ConnId, ListenId, Transport, TransportEvent};
+use crate::oed::Value;
+
+struct Listening {
+    party: usize,
+    identity: Value,
+    bind_info: Value,
+}
+
+struct Link {
+    party: usize,
+    peer: ConnId,
+    name: Value,  // name of the remote party
+}
+
+#[derive(Default)]
+pub struct MemoryNetwork {
+    listeners: Vec<Option<Listening>>,  // listener -> listening party
+    links: Vec<Option<Link>>,  // conn -> one end of a connection
+    events: Vec<VecDeque<TransportEvent>>,  // party -> pending events
+    wakers: Vec<Option<Waker>>,  // party -> woken by the next event
+}
+
+impl MemoryNetwork {
+    fn deliver(&mut self, party: usize, event: TransportEvent) {
+        self.events[party].push_back(event);
+        if let Some(waker) = self.wakers[party].take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct MemoryTransport {
+    network: Rc<RefCell<MemoryNetwork>>,
+    party: usize,
+}
+
+impl MemoryTransport {
+    pub fn new(network: &Rc<RefCell<MemoryNetwork>>) -> MemoryTransport {
+        let mut net = network.borrow_mut();
+        let party = net.events.len();
+        net.events.push(VecDeque::new());
+        net.wakers.push(None);
+        MemoryTransport {
+            network: network.clone(),
+            party,
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn listen(&mut self, identity: &Value, bind_info: &Value) -> Result<ListenId, Error> {
+        let mut net = self.network.borrow_mut();
+        let in_use = net.listeners.iter().flatten()
+            .any(|l| (l.identity == *identity) && (l.bind_info == *bind_info));
+        if in_use {
+            return Err(E_FAIL);  // address in use
+        }
+        let listener = net.listeners.len();
+        net.listeners.push(Some(Listening {
+            party: self.party,
+            identity: identity.clone(),
+            bind_info: bind_info.clone(),
+        }));
+        Ok(listener)
+    }
+    fn stop(&mut self, listener: ListenId) {
+        let mut net = self.network.borrow_mut();
+        if let Some(slot) = net.listeners.get_mut(listener) {
+            if matches!(slot, Some(l) if l.party == self.party) {
+                *slot = None;
+            }
+        }
+    }
+    fn connect(&mut self, identity: &Value, name: &Value, address: &Value) -> Result<ConnId, Error> {
+        let mut net = self.network.borrow_mut();
+        let found = net.listeners.iter().enumerate()
+            .find_map(|(nr, slot)| match slot {
+                Some(l) if (l.identity == *name) && (l.bind_info == *address) => Some((nr, l.party)),
+                _ => None,
+            });
+        let conn = net.links.len();
+        match found {
+            Some((listener, party)) => {
+                let accepted = conn + 1;
+                net.links.push(Some(Link { party: self.party, peer: accepted, name: name.clone() }));
+                net.links.push(Some(Link { party, peer: conn, name: identity.clone() }));
+                net.deliver(party, TransportEvent::Opened(listener, accepted));
+                net.deliver(self.party, TransportEvent::Connected(conn));
+            },
+            None => {
+                net.links.push(None);  // connect failed
+                net.deliver(self.party, TransportEvent::Closed(conn));
+            },
+        }
+        Ok(conn)
+    }
+    fn name(&self, conn: ConnId) -> Option<Value> {
+        let net = self.network.borrow();
+        match net.links.get(conn) {
+            Some(Some(link)) if link.party == self.party => Some(link.name.clone()),
+            _ => None,
+        }
+    }
+    fn send(&mut self, conn: ConnId, frame: &[u8]) {
+        let mut net = self.network.borrow_mut();
+        let peer = match net.links.get(conn) {
+            Some(Some(link)) if link.party == self.party => link.peer,
+            _ => return,  // closed
+        };
+        if let Some(Some(remote)) = net.links.get(peer) {
+            let party = remote.party;
+            net.deliver(party, TransportEvent::Received(peer, frame.to_vec()));
+        }
+    }
+    fn close(&mut self, conn: ConnId) {
+        let mut net = self.network.borrow_mut();
+        let peer = match net.links.get(conn) {
+            Some(Some(link)) if link.party == self.party => link.peer,
+            _ => return,
+        };
+        net.links[conn] = None;
+        if let Some(remote) = net.links.get_mut(peer).and_then(Option::take) {
+            net.deliver(remote.party, TransportEvent::Closed(peer));
+        }
+    }
+    fn poll(&mut self) -> Option<TransportEvent> {
+        self.network.borrow_mut().events[self.party].pop_front()
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        let mut net = self.network.borrow_mut();
+        if net.events[self.party].is_empty() {
+            net.wakers[self.party] = Some(waker.clone());
+        } else {
+            waker.wake_by_ref();
+        }
+        true
+    }
+}
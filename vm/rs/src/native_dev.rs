@@ -0,0 +1,327 @@
+// The NativeDevice hosts actors whose behavior is a Rust closure.
+//
+// Each native actor is a proxy of this device, whose handle is its `state`.
+// Its closure is kept by the device, keyed by the offset of the proxy
+// (so it can be found when the proxy is dropped, whatever else has been swept).
+// The `state` is visible to the garbage collector, but values captured by
+// a closure are not, so heap references must be kept in the state.
+//
+// A closure handles one message per transaction. Its effects (send, create,
+// and become) are batched, then committed only if the closure succeeds.
+// Cycles and memory are charged to the sponsor of the event.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::*;
+
+pub type Behavior = Box<dyn FnMut(&mut Txn, Any) -> Result<(), Error>>;  // (txn, msg)
+
+const BEH_COST: isize = 1;  // cycles charged for each message handled
+
+pub struct NativeDevice {
+    dev_cap: Any,
+    behaviors: BTreeMap<usize, Behavior>,  // proxy offset -> behavior
+}
+
+// the context of a native actor's transaction
+pub struct Txn<'a> {
+    core: &'a mut Core,
+    dev: &'a mut NativeDevice,
+    sponsor: Any,
+    me: Any,
+    state: Any,
+    outbox: Vec<Any>,  // events to send on commit
+    created: Vec<(usize, Behavior)>,  // behaviors to install on commit
+    became: Option<Behavior>,
+}
+
+impl Txn<'_> {
+    pub fn core(&self) -> &Core {
+        self.core
+    }
+    pub fn sponsor(&self) -> Any {
+        self.sponsor
+    }
+    pub fn me(&self) -> Any {
+        self.me
+    }
+    pub fn state(&self) -> Any {
+        self.state
+    }
+    pub fn charge(&mut self, cycles: isize) -> Result<(), Error> {
+        let limit = self.core.sponsor_cycles(self.sponsor).fix_num().unwrap_or(0);
+        if cycles > limit {
+            return Err(E_CPU_LIM);  // Sponsor instruction limit reached
+        }
+        self.core.set_sponsor_cycles(self.sponsor, Any::fix(limit - cycles));
+        Ok(())
+    }
+    pub fn alloc(&mut self, init: &Quad) -> Result<Any, Error> {
+        self.core.sponsor_alloc(self.sponsor, init)
+    }
+    pub fn cons(&mut self, car: Any, cdr: Any) -> Result<Any, Error> {
+        self.alloc(&Quad::pair_t(car, cdr))
+    }
+    pub fn send(&mut self, target: Any, msg: Any) -> Result<(), Error> {
+        if !target.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let ep = self.alloc(&Quad::new_event(self.sponsor, target, msg))?;
+        self.outbox.push(ep);
+        Ok(())
+    }
+    pub fn create(&mut self, beh: Behavior, state: Any) -> Result<Any, Error> {
+        let ptr = self.alloc(&Quad::proxy_t(self.dev.dev_cap, state))?;
+        self.created.push((ptr.ofs(), beh));
+        Ok(self.core.ptr_to_cap(ptr))
+    }
+    pub fn update(&mut self, state: Any) {
+        // replace state data
+        self.state = state;
+    }
+    pub fn become_beh(&mut self, beh: Behavior, state: Any) {
+        // replace behavior function and state data
+        self.became = Some(beh);
+        self.state = state;
+    }
+}
+
+impl NativeDevice {
+    pub fn new(dev_cap: Any) -> NativeDevice {
+        // `dev_cap` is where this device is installed (or registered)
+        NativeDevice {
+            dev_cap,
+            behaviors: BTreeMap::new(),
+        }
+    }
+    pub fn create(&mut self, core: &mut Core, beh: Behavior, state: Any) -> Result<Any, Error> {
+        // make a native actor on behalf of the host (not charged to any sponsor)
+        let proxy = core.reserve_proxy(self.dev_cap, state)?;
+        self.behaviors.insert(core.cap_to_ptr(proxy).ofs(), beh);
+        Ok(proxy)
+    }
+    pub fn len(&self) -> usize {
+        // number of live native actors
+        self.behaviors.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.behaviors.is_empty()
+    }
+    fn proxy_key(&self, core: &Core, proxy: Any) -> Result<usize, Error> {
+        let ptr = core.cap_to_ptr(proxy);
+        let quad = core.ram(ptr);
+        if (quad.t() != PROXY_T) || (quad.x() != self.dev_cap) {
+            return Err(E_NOT_CAP);  // sent directly to the device
+        }
+        Ok(ptr.ofs())
+    }
+}
+
+impl Device for NativeDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let event = *core.mem(ep);
+        let sponsor = event.t();
+        let me = event.x();
+        let msg = event.y();
+        let key = self.proxy_key(core, me)?;
+        let state = core.ram(core.cap_to_ptr(me)).y();
+        let mut beh = match self.behaviors.remove(&key) {
+            Some(beh) => beh,
+            None => return Ok(UNDEF),  // ignore messages to dropped actors
+        };
+        let mut txn = Txn {
+            core,
+            dev: self,
+            sponsor,
+            me,
+            state,
+            outbox: Vec::new(),
+            created: Vec::new(),
+            became: None,
+        };
+        let result = txn.charge(BEH_COST).and_then(|_| beh(&mut txn, msg));
+        let Txn { core, dev, state, outbox, created, became, .. } = txn;
+        match result {
+            Ok(()) => {
+                // commit transaction effects
+                core.set_y(core.cap_to_ptr(me), state);
+                dev.behaviors.insert(key, became.unwrap_or(beh));
+                dev.behaviors.extend(created);
+                for ep in outbox {
+                    core.event_enqueue(ep);
+                }
+                Ok(UNDEF)
+            },
+            Err(error) => {
+                // discard transaction effects (created actors are left for GC)
+                dev.behaviors.insert(key, beh);
+                for ep in outbox {
+                    core.free(ep);
+                }
+                if core.is_recoverable(error) {
+                    return Err(error);  // event is deferred until the sponsor is resumed
+                }
+                core.call_audit_fn(error, ep);
+                Ok(UNDEF)
+            },
+        }
+    }
+    fn drop_proxy(&mut self, core: &mut Core, cap: Any) {
+        if let Ok(key) = self.proxy_key(core, cap) {
+            self.behaviors.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use alloc::vec;
+
+    use ::core::cell::RefCell;
+
+    use super::*;
+
+    struct Recorder {
+        log: Log,
+    }
+
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            self.log.borrow_mut().push(core.event_message(ep));
+            Ok(UNDEF)
+        }
+    }
+
+    // counts messages, reporting the count to each `customer`
+    fn counter() -> Behavior {
+        Box::new(|txn: &mut Txn, customer: Any| {
+            let n = txn.state().get_fix()? + 1;
+            txn.send(customer, Any::fix(n))?;
+            txn.update(Any::fix(n));
+            Ok(())
+        })
+    }
+
+    type Log = Rc<RefCell<Vec<Any>>>;
+
+    fn setup() -> (Core, Rc<RefCell<NativeDevice>>, Any, Log) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Core::default();
+        core.init();
+        let native = Rc::new(RefCell::new(NativeDevice::new(UNDEF)));
+        native.borrow_mut().dev_cap = core.register_device(Box::new(native.clone())).unwrap();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        (core, native, recorder, log)
+    }
+
+    fn send(core: &mut Core, target: Any, msg: Any) {
+        let evt = core.reserve_event(SPONSOR, target, msg).unwrap();
+        core.event_enqueue(evt);
+    }
+
+    #[test]
+    fn native_dev_effects() {
+        let (mut core, native, recorder, log) = setup();
+        // creates a counter, then becomes a forwarder to it
+        let factory = native.borrow_mut().create(&mut core, Box::new(|txn: &mut Txn, customer: Any| {
+            let counter = txn.create(counter(), ZERO)?;
+            txn.send(counter, customer)?;
+            txn.become_beh(Box::new(|txn: &mut Txn, customer: Any| {
+                let counter = txn.state();
+                txn.send(counter, customer)
+            }), counter);
+            Ok(())
+        }), UNDEF).unwrap();
+        core.reserve_stub(DEBUG_DEV, factory).unwrap();  // keep `factory` alive
+        for _ in 0..3 {
+            send(&mut core, factory, recorder);
+            assert_eq!(ZERO, core.run_loop(0));
+        }
+        assert_eq!(vec![PLUS_1, PLUS_2, PLUS_3], *log.borrow());
+        assert_eq!(2, native.borrow().len());
+    }
+
+    #[test]
+    fn native_dev_abort() {
+        let (mut core, native, recorder, log) = setup();
+        let token = Rc::new(());
+        let held = token.clone();
+        let failing = native.borrow_mut().create(&mut core, Box::new(move |txn: &mut Txn, customer: Any| {
+            let _ = &held;
+            txn.send(customer, TRUE)?;
+            txn.create(counter(), ZERO)?;
+            txn.update(FALSE);
+            Err(E_ASSERT)
+        }), ZERO).unwrap();
+        let root = core.reserve_stub(DEBUG_DEV, failing).unwrap();  // keep `failing` alive
+        let audit = Rc::new(RefCell::new(Vec::new()));
+        let audited = audit.clone();
+        core.set_audit_fn(move |code, _evidence| audited.borrow_mut().push(code));
+        send(&mut core, failing, recorder);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![Any::fix(E_ASSERT as isize)], *audit.borrow());
+        assert!(log.borrow().is_empty());  // no effects
+        assert_eq!(1, native.borrow().len());  // the created actor was dropped
+        assert_eq!(ZERO, core.ram(core.cap_to_ptr(failing)).y());  // state unchanged
+
+        // unreferenced actors are dropped with their closures
+        assert_eq!(2, Rc::strong_count(&token));
+        core.release_stub(root);
+        core.gc_collect_all();
+        assert!(native.borrow().is_empty());
+        assert_eq!(1, Rc::strong_count(&token));
+    }
+
+    #[test]
+    fn native_dev_drop_after_state() {
+        let (mut core, native, _, _) = setup();
+        let token = Rc::new(());
+        let held = token.clone();
+        // reuse cells from the free-list, so the state is above its proxy
+        let low = core.reserve(&Quad::empty_t()).unwrap();
+        let high = core.reserve(&Quad::empty_t()).unwrap();
+        core.free(low);
+        core.free(high);
+        let state = core.reserve(&Quad::pair_t(PLUS_1, NIL)).unwrap();
+        let actor = native.borrow_mut().create(&mut core, Box::new(move |_: &mut Txn, _: Any| {
+            let _ = &held;
+            Ok(())
+        }), state).unwrap();
+        assert!(state.ofs() > core.cap_to_ptr(actor).ofs());  // swept first (GC sweeps downward)
+        assert_eq!(1, native.borrow().len());
+        core.gc_collect_all();
+        assert!(native.borrow().is_empty());
+        assert_eq!(1, Rc::strong_count(&token));
+    }
+
+    #[test]
+    fn native_dev_sponsor_limits() {
+        let (mut core, native, recorder, log) = setup();
+        let greedy = native.borrow_mut().create(&mut core, Box::new(|txn: &mut Txn, customer: Any| {
+            let mut list = NIL;
+            for _ in 0..8 {
+                list = txn.cons(UNDEF, list)?;
+            }
+            txn.send(customer, list)
+        }), UNDEF).unwrap();
+        core.reserve_stub(DEBUG_DEV, greedy).unwrap();  // keep `greedy` alive
+        core.set_sponsor_memory(SPONSOR, Any::fix(4));
+        send(&mut core, greedy, recorder);
+        assert_eq!(Any::fix(E_MEM_LIM as isize), core.run_loop(0));
+        assert!(log.borrow().is_empty());
+
+        // the deferred event is retried when the sponsor is refilled
+        core.set_sponsor_memory(SPONSOR, Any::fix(16));
+        core.set_sponsor_cycles(SPONSOR, ZERO);
+        assert_eq!(Any::fix(E_CPU_LIM as isize), core.run_loop(0));
+        core.set_sponsor_cycles(SPONSOR, PLUS_1);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(1, log.borrow().len());
+        assert_eq!(ZERO, core.sponsor_cycles(SPONSOR));
+        assert_eq!(Any::fix(7), core.sponsor_memory(SPONSOR));
+    }
+
+}
@@ -0,0 +1,666 @@
+// Octet-Encoded Data (OED) encoding and decoding.
+// The format is described in https://github.com/organix/mycelia/blob/master/OED.md
+// and implemented for JavaScript in `lib/oed.js`.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::*;
+
+// OED prefix octets
+pub const OED_FALSE: u8 =       0x80;   // `false`
+pub const OED_TRUE: u8 =        0x81;   // `true`
+pub const OED_POS_INT: u8 =     0x82;   // Positive Integer
+pub const OED_NEG_INT: u8 =     0x83;   // Negative Integer
+pub const OED_POS_DEC: u8 =     0x84;   // Positive Decimal
+pub const OED_NEG_DEC: u8 =     0x85;   // Negative Decimal
+pub const OED_POS_RAT: u8 =     0x86;   // Positive Rational
+pub const OED_NEG_RAT: u8 =     0x87;   // Negative Rational
+pub const OED_ARRAY: u8 =       0x88;   // Array
+pub const OED_OBJECT: u8 =      0x89;   // Object
+pub const OED_BLOB: u8 =        0x8A;   // Octet Blob
+pub const OED_EXTENSION: u8 =   0x8B;   // Extension Blob
+pub const OED_STRING: u8 =      0x8C;   // String
+pub const OED_MEMO: u8 =        0x8D;   // String, memoized
+pub const OED_MEMO_REF: u8 =    0x8E;   // memoized String reference
+pub const OED_NULL: u8 =        0x8F;   // `null`
+
+const MEMO_MAX: usize = 256;  // memo references are a single octet
+const DEPTH_MAX: usize = 256;  // nesting limit, so hostile input can not exhaust the stack
+
+// an arbitrary-precision integer, in sign-magnitude form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Number {
+    pub negative: bool,
+    pub natural: Vec<u8>,  // magnitude, least-significant octet first
+}
+
+impl Number {
+    pub fn new(negative: bool, natural: &[u8]) -> Number {
+        let len = natural.iter().rposition(|octet| *octet != 0).map_or(0, |i| i + 1);
+        Number { negative, natural: natural[..len].to_vec() }  // without padding
+    }
+    pub fn from_i64(n: i64) -> Number {
+        Number::new(n < 0, &n.unsigned_abs().to_le_bytes())
+    }
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.natural.len() > 8 {
+            return None;
+        }
+        let mut octets = [0_u8; 8];
+        octets[..self.natural.len()].copy_from_slice(&self.natural);
+        let n = u64::from_le_bytes(octets);
+        if self.negative {
+            0_i64.checked_sub_unsigned(n)
+        } else {
+            i64::try_from(n).ok()
+        }
+    }
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.natural.iter().rev().fold(0.0, |n, octet| n * 256.0 + (*octet as f64));
+        if self.negative { -magnitude } else { magnitude }
+    }
+    fn size(&self) -> usize {  // significant bits
+        match self.natural.last() {
+            Some(msb) => 8 * self.natural.len() - (msb.leading_zeros() as usize),
+            None => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    BigInteger(Number),  // beyond the range of `Integer`
+    Decimal(Number, i64),  // coefficient * 10^exponent
+    Rational(Number, u64, i64),  // coefficient * base^exponent
+    String(String),
+    Blob(Vec<u8>),
+    Extension(Box<Value>, Vec<u8>),  // meta, data
+    Array(Vec<Value>),
+    Object(Vec<(Value, Value)>),  // properties, in order
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Value {
+        // exact encoding as a base-2 rational (or integer), `null` if not finite
+        if !n.is_finite() {
+            return Value::Null;
+        }
+        let bits = n.to_bits();
+        let negative = (bits >> 63) != 0;
+        let biased = ((bits >> 52) & 0x7FF) as i64;
+        let fraction = bits & 0x000F_FFFF_FFFF_FFFF;
+        let (mut coefficient, mut exponent) = if biased == 0 {
+            (fraction, -1074)  // subnormal
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        if coefficient == 0 {
+            return Value::Integer(0);
+        }
+        let zeros = coefficient.trailing_zeros() as i64;
+        if exponent < 0 {
+            let shift = zeros.min(-exponent);
+            coefficient >>= shift;
+            exponent += shift;
+        }
+        if (0..53).contains(&exponent) && (coefficient < (1 << (53 - exponent))) {
+            let n = (coefficient << exponent) as i64;  // a "safe" integer
+            return Value::Integer(if negative { -n } else { n });
+        }
+        if exponent > 0 {
+            coefficient >>= zeros;
+            exponent += zeros;
+        }
+        Value::Rational(Number::new(negative, &coefficient.to_le_bytes()), 2, exponent)
+    }
+}
+
+fn scale(mut n: f64, base: f64, exponent: i64) -> f64 {
+    for _ in 0..exponent.unsigned_abs().min(2048) {
+        if exponent < 0 {
+            n /= base;
+        } else {
+            n *= base;
+        }
+        if (n == 0.0) || !n.is_finite() {
+            break;
+        }
+    }
+    n
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        // look up an object property by string key
+        match self {
+            Value::Object(props) => props.iter()
+                .find(|(k, _)| matches!(k, Value::String(s) if s == key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    pub fn as_f64(&self) -> Option<f64> {
+        // numeric value, possibly approximate
+        match self {
+            Value::Integer(n) => Some(*n as f64),
+            Value::BigInteger(n) => Some(n.to_f64()),
+            Value::Decimal(coefficient, exponent) => Some(scale(coefficient.to_f64(), 10.0, *exponent)),
+            Value::Rational(coefficient, base, exponent) => Some(scale(coefficient.to_f64(), *base as f64, *exponent)),
+            _ => None,
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out, &mut None);
+        out
+    }
+    pub fn encode_memoized(&self) -> Vec<u8> {
+        // repeated strings (such as property names) are sent only once
+        let mut out = Vec::new();
+        self.encode_into(&mut out, &mut Some(Vec::new()));
+        out
+    }
+    fn encode_into(&self, out: &mut Vec<u8>, memo: &mut Option<Vec<String>>) {
+        match self {
+            Value::Null => out.push(OED_NULL),
+            Value::Bool(false) => out.push(OED_FALSE),
+            Value::Bool(true) => out.push(OED_TRUE),
+            Value::Integer(n) => encode_integer(out, *n),
+            Value::BigInteger(n) => {
+                out.push(if n.negative { OED_NEG_INT } else { OED_POS_INT });
+                encode_natural(out, n);
+            },
+            Value::Decimal(coefficient, exponent) => {
+                out.push(if coefficient.negative { OED_NEG_DEC } else { OED_POS_DEC });
+                encode_integer(out, *exponent);
+                encode_natural(out, coefficient);
+            },
+            Value::Rational(coefficient, base, exponent) => {
+                out.push(if coefficient.negative { OED_NEG_RAT } else { OED_POS_RAT });
+                encode_number(out, &Number::new(false, &base.to_le_bytes()));
+                encode_integer(out, *exponent);
+                encode_natural(out, coefficient);
+            },
+            Value::String(s) => {
+                let length = s.chars().count();
+                if length > 0 {
+                    if let Some(memo) = memo {
+                        if let Some(index) = memo.iter().position(|m| m == s) {
+                            out.push(OED_MEMO_REF);
+                            out.push(index as u8);
+                            return;
+                        }
+                        if memo.len() < MEMO_MAX {
+                            memo.push(s.clone());
+                            out.push(OED_MEMO);
+                            encode_integer(out, length as i64);
+                            encode_integer(out, s.len() as i64);
+                            out.extend_from_slice(s.as_bytes());
+                            return;
+                        }
+                    }
+                }
+                out.push(OED_STRING);
+                encode_integer(out, length as i64);
+                if length > 0 {
+                    encode_integer(out, s.len() as i64);
+                    out.extend_from_slice(s.as_bytes());
+                }
+            },
+            Value::Blob(data) => {
+                out.push(OED_BLOB);
+                encode_integer(out, data.len() as i64);
+                out.extend_from_slice(data);
+            },
+            Value::Extension(meta, data) => {
+                out.push(OED_EXTENSION);
+                meta.encode_into(out, memo);
+                encode_integer(out, data.len() as i64);
+                out.extend_from_slice(data);
+            },
+            Value::Array(elements) => {
+                out.push(OED_ARRAY);
+                encode_integer(out, elements.len() as i64);
+                if !elements.is_empty() {
+                    let mut body = Vec::new();
+                    for element in elements {
+                        element.encode_into(&mut body, memo);
+                    }
+                    encode_integer(out, body.len() as i64);
+                    out.extend_from_slice(&body);
+                }
+            },
+            Value::Object(props) => {
+                out.push(OED_OBJECT);
+                encode_integer(out, props.len() as i64);
+                if !props.is_empty() {
+                    let mut body = Vec::new();
+                    for (key, value) in props {
+                        key.encode_into(&mut body, memo);
+                        value.encode_into(&mut body, memo);
+                    }
+                    encode_integer(out, body.len() as i64);
+                    out.extend_from_slice(&body);
+                }
+            },
+        }
+    }
+    pub fn decode(octets: &[u8]) -> Result<Value, Error> {
+        let (value, len) = Value::decode_prefix(octets)?;
+        if len != octets.len() {
+            return Err(E_FAIL);  // trailing octets
+        }
+        Ok(value)
+    }
+    pub fn decode_prefix(octets: &[u8]) -> Result<(Value, usize), Error> {
+        // decode the value at the start of `octets`, also returning its length
+        let mut decoder = Decoder { octets, pos: 0, memo: Vec::new(), depth: 0 };
+        let value = decoder.value()?;
+        Ok((value, decoder.pos))
+    }
+}
+
+fn encode_integer(out: &mut Vec<u8>, n: i64) {
+    if (-112..=127).contains(&n) {
+        out.push(n as u8);  // small integers encode as themselves
+    } else {
+        encode_number(out, &Number::from_i64(n));
+    }
+}
+fn encode_number(out: &mut Vec<u8>, n: &Number) {
+    match n.to_i64() {
+        Some(small) if (-112..=127).contains(&small) => out.push(small as u8),
+        _ => {
+            out.push(if n.negative { OED_NEG_INT } else { OED_POS_INT });
+            encode_natural(out, n);
+        },
+    }
+}
+fn encode_natural(out: &mut Vec<u8>, n: &Number) {
+    // the `size` and `natural` fields that terminate every number
+    encode_integer(out, n.size() as i64);
+    out.extend_from_slice(&n.natural);
+}
+
+struct Decoder<'a> {
+    octets: &'a [u8],
+    pos: usize,
+    memo: Vec<String>,
+    depth: usize,  // number of enclosing values
+}
+
+impl<'a> Decoder<'a> {
+    fn consume(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.octets.len() - self.pos < n {
+            return Err(E_BOUNDS);  // unexpected end of input
+        }
+        let data = &self.octets[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(data)
+    }
+    fn natural(&mut self, negative: bool) -> Result<Number, Error> {
+        let size = self.magnitude()?;
+        let natural = self.consume(size.div_ceil(8))?;
+        let mut n = Number::new(negative, natural);
+        if (size % 8 != 0) && (n.natural.len() == size.div_ceil(8)) {
+            let last = n.natural.len() - 1;
+            n.natural[last] &= (1 << (size % 8)) - 1;  // ignore bits beyond `size`
+            n = Number::new(negative, &n.natural);
+        }
+        Ok(n)
+    }
+    fn number(&mut self) -> Result<Number, Error> {
+        let prefix = self.consume(1)?[0];
+        if prefix < OED_FALSE {
+            return Ok(Number::from_i64(prefix as i64));
+        }
+        if prefix > OED_NULL {
+            return Ok(Number::from_i64(prefix as i64 - 256));
+        }
+        if (prefix != OED_POS_INT) && (prefix != OED_NEG_INT) {
+            return Err(E_FAIL);  // integer required
+        }
+        self.natural(prefix == OED_NEG_INT)
+    }
+    fn integer(&mut self) -> Result<i64, Error> {
+        self.number()?.to_i64().ok_or(E_BOUNDS)
+    }
+    fn magnitude(&mut self) -> Result<usize, Error> {
+        let n = self.integer()?;
+        if n < 0 {
+            return Err(E_FAIL);
+        }
+        usize::try_from(n).map_err(|_| E_BOUNDS)
+    }
+    fn string(&mut self) -> Result<String, Error> {
+        let length = self.magnitude()?;
+        if length == 0 {
+            return Ok(String::new());
+        }
+        let size = self.magnitude()?;
+        let data = self.consume(size)?;
+        let s = ::core::str::from_utf8(data).map_err(|_| E_FAIL)?;
+        if s.chars().count() != length {
+            return Err(E_FAIL);  // string length mismatch
+        }
+        Ok(String::from(s))
+    }
+    fn value(&mut self) -> Result<Value, Error> {
+        if self.depth >= DEPTH_MAX {
+            return Err(E_BOUNDS);  // nested too deeply
+        }
+        self.depth += 1;
+        let value = self.element();
+        self.depth -= 1;
+        value
+    }
+    fn element(&mut self) -> Result<Value, Error> {
+        let prefix = match self.octets.get(self.pos) {
+            Some(prefix) => *prefix,
+            None => return Err(E_BOUNDS),
+        };
+        if (prefix & 0xF0 == 0x80) && (prefix != OED_POS_INT) && (prefix != OED_NEG_INT) {
+            self.pos += 1;  // consume prefix
+        }
+        match prefix {
+            OED_NULL => Ok(Value::Null),
+            OED_FALSE | OED_TRUE => Ok(Value::Bool(prefix == OED_TRUE)),
+            OED_POS_DEC | OED_NEG_DEC => {
+                let exponent = self.integer()?;
+                let coefficient = self.natural(prefix == OED_NEG_DEC)?;
+                Ok(Value::Decimal(coefficient, exponent))
+            },
+            OED_POS_RAT | OED_NEG_RAT => {
+                let base = self.integer()?;
+                let base = u64::try_from(base).map_err(|_| E_FAIL)?;
+                let exponent = self.integer()?;
+                let coefficient = self.natural(prefix == OED_NEG_RAT)?;
+                Ok(Value::Rational(coefficient, base, exponent))
+            },
+            OED_STRING => Ok(Value::String(self.string()?)),
+            OED_MEMO => {
+                let s = self.string()?;
+                if self.memo.len() >= MEMO_MAX {
+                    return Err(E_BOUNDS);  // memo table full
+                }
+                self.memo.push(s.clone());
+                Ok(Value::String(s))
+            },
+            OED_MEMO_REF => {
+                let index = self.consume(1)?[0] as usize;
+                match self.memo.get(index) {
+                    Some(s) => Ok(Value::String(s.clone())),
+                    None => Err(E_BOUNDS),
+                }
+            },
+            OED_BLOB => {
+                let size = self.magnitude()?;
+                Ok(Value::Blob(self.consume(size)?.to_vec()))
+            },
+            OED_EXTENSION => {
+                let meta = self.value()?;
+                let size = self.magnitude()?;
+                Ok(Value::Extension(Box::new(meta), self.consume(size)?.to_vec()))
+            },
+            OED_ARRAY => {
+                let length = self.magnitude()?;
+                let mut elements = Vec::new();
+                if length > 0 {
+                    let size = self.magnitude()?;
+                    let start = self.pos;
+                    while elements.len() < length {
+                        elements.push(self.value()?);
+                    }
+                    if self.pos - start != size {
+                        return Err(E_FAIL);  // array size mismatch
+                    }
+                }
+                Ok(Value::Array(elements))
+            },
+            OED_OBJECT => {
+                let length = self.magnitude()?;
+                let mut props = Vec::new();
+                if length > 0 {
+                    let size = self.magnitude()?;
+                    let start = self.pos;
+                    while props.len() < length {
+                        let key = self.value()?;
+                        let value = self.value()?;
+                        props.push((key, value));
+                    }
+                    if self.pos - start != size {
+                        return Err(E_FAIL);  // object size mismatch
+                    }
+                }
+                Ok(Value::Object(props))
+            },
+            _ => {
+                let n = self.number()?;
+                Ok(match n.to_i64() {
+                    Some(small) => Value::Integer(small),
+                    None => Value::BigInteger(n),
+                })
+            },
+        }
+    }
+}
+
+/*
+ *  uFork   | OED
+ *  --------|---------------
+ *  #?      | null
+ *  #t      | true
+ *  #f      | false
+ *  #nil    | []
+ *  a,b     | [a, b]
+ *  fixnum  | integer
+ *  dict    | {key: value, ...}
+ *
+ *  Other values (such as capabilities) are converted by the `pack` and
+ *  `unpack` functions, which fail with `E_NO_TYPE` if they have no answer.
+ */
+pub fn from_heap(core: &mut Core, value: Any, pack: &mut dyn FnMut(&mut Core, Any) -> Result<Value, Error>) -> Result<Value, Error> {
+    if value == UNDEF {
+        Ok(Value::Null)
+    } else if value == TRUE {
+        Ok(Value::Bool(true))
+    } else if value == FALSE {
+        Ok(Value::Bool(false))
+    } else if value == NIL {
+        Ok(Value::Array(Vec::new()))
+    } else if let Some(n) = value.fix_num() {
+        Ok(Value::Integer(n as i64))
+    } else if core.typeq(PAIR_T, value) {
+        let head = from_heap(core, core.car(value), pack)?;
+        let tail = from_heap(core, core.cdr(value), pack)?;
+        Ok(Value::Array(alloc::vec![head, tail]))
+    } else if core.typeq(DICT_T, value) {
+        let mut props = Vec::new();
+        let mut dict = value;
+        while core.typeq(DICT_T, dict) {
+            let entry = *core.mem(dict);
+            props.push((from_heap(core, entry.x(), pack)?, from_heap(core, entry.y(), pack)?));
+            dict = entry.z();
+        }
+        Ok(Value::Object(props))
+    } else {
+        pack(core, value)
+    }
+}
+
+pub fn to_heap(core: &mut Core, value: &Value, unpack: &mut dyn FnMut(&mut Core, &Value) -> Result<Any, Error>) -> Result<Any, Error> {
+    match value {
+        Value::Null => Ok(UNDEF),
+        Value::Bool(true) => Ok(TRUE),
+        Value::Bool(false) => Ok(FALSE),
+        Value::Integer(n) if (-(1 << 30)..(1 << 30)).contains(n) => Ok(Any::fix(*n as isize)),
+        Value::Array(elements) if elements.is_empty() => Ok(NIL),
+        Value::Array(elements) if elements.len() == 2 => {
+            let head = to_heap(core, &elements[0], unpack)?;
+            let tail = to_heap(core, &elements[1], unpack)?;
+            core.reserve(&Quad::pair_t(head, tail))
+        },
+        Value::Object(props) if !props.is_empty() => {
+            let mut dict = NIL;
+            for (key, value) in props.iter().rev() {
+                let key = to_heap(core, key, unpack)?;
+                let value = to_heap(core, value, unpack)?;
+                dict = core.reserve(&Quad::dict_t(key, value, dict))?;
+            }
+            Ok(dict)
+        },
+        _ => unpack(core, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn oed_integer_encoding() {
+        assert_eq!(vec![0x2A], Value::Integer(42).encode());
+        assert_eq!(vec![0xFF], Value::Integer(-1).encode());
+        assert_eq!(vec![0x90], Value::Integer(-112).encode());
+        assert_eq!(vec![0x82, 0x08, 0x80], Value::Integer(128).encode());
+        assert_eq!(vec![0x83, 0x07, 0x71], Value::Integer(-113).encode());
+        assert_eq!(vec![0x82, 0x0E, 0x39, 0x30], Value::Integer(12345).encode());
+        for n in [0, 127, 128, -112, -113, 0x1234_5678, i64::MAX, i64::MIN] {
+            assert_eq!(Value::Integer(n), Value::decode(&Value::Integer(n).encode()).unwrap());
+        }
+    }
+
+    #[test]
+    fn oed_big_integers() {
+        let big = Value::BigInteger(Number::new(false, &[0, 0, 0, 0, 0, 0, 0, 0, 1]));  // 2^64
+        let octets = big.encode();
+        assert_eq!(vec![0x82, 0x41, 0, 0, 0, 0, 0, 0, 0, 0, 1], octets);
+        assert_eq!(big, Value::decode(&octets).unwrap());
+        assert_eq!(Some(18446744073709551616.0), big.as_f64());
+        // padded naturals decode to their canonical form
+        assert_eq!(Value::Integer(5), Value::decode(&[0x82, 0x10, 0x05, 0x00]).unwrap());
+        assert_eq!(Value::Integer(-1), Value::decode(&[0x83, 0x01, 0xFF]).unwrap());
+    }
+
+    #[test]
+    fn oed_decimals_and_rationals() {
+        let decimal = Value::Decimal(Number::from_i64(15), -1);  // 1.5
+        assert_eq!(vec![0x84, 0xFF, 0x04, 0x0F], decimal.encode());
+        assert_eq!(decimal, Value::decode(&decimal.encode()).unwrap());
+        assert_eq!(Some(1.5), decimal.as_f64());
+        let rational = Value::from(0.5);  // as encoded by `lib/oed.js`
+        assert_eq!(vec![0x86, 0x02, 0xFF, 0x01, 0x01], rational.encode());
+        assert_eq!(rational, Value::decode(&rational.encode()).unwrap());
+        assert_eq!(Some(0.5), rational.as_f64());
+        for n in [-0.1, 3.75, 1e300, -5e-324, 1024.0] {
+            assert_eq!(Some(n), Value::from(n).as_f64());
+        }
+        assert_eq!(Value::Integer(-1024), Value::from(-1024.0));
+        assert_eq!(Value::Null, Value::from(f64::NAN));
+    }
+
+    #[test]
+    fn oed_structure_round_trip() {
+        let value = Value::Object(vec![
+            (Value::String(String::from("target")), Value::Blob(vec![1, 2, 3])),
+            (Value::String(String::from("message")), Value::Array(vec![
+                Value::Null,
+                Value::Bool(true),
+                Value::Array(vec![]),
+                Value::String(String::from("λx")),
+                Value::Extension(Box::new(Value::Object(vec![])), vec![0xFF; 16]),
+            ])),
+        ]);
+        let octets = value.encode();
+        assert_eq!(OED_OBJECT, octets[0]);
+        assert_eq!(value, Value::decode(&octets).unwrap());
+        assert_eq!(Some(&Value::Blob(vec![1, 2, 3])), value.get("target"));
+        assert_eq!(None, value.get("missing"));
+    }
+
+    #[test]
+    fn oed_memoized_strings() {
+        let name = Value::String(String::from("name"));
+        let value = Value::Array(vec![
+            Value::Object(vec![(name.clone(), Value::Integer(1))]),
+            Value::Object(vec![(name.clone(), Value::Integer(2))]),
+        ]);
+        let octets = value.encode_memoized();
+        assert!(octets.len() < value.encode().len());
+        assert_eq!(Some(&OED_MEMO_REF), octets.iter().find(|o| **o == OED_MEMO_REF));
+        assert_eq!(value, Value::decode(&octets).unwrap());
+        assert_eq!(E_BOUNDS, Value::decode(&[OED_MEMO_REF, 0x00]).unwrap_err());
+    }
+
+    #[test]
+    fn oed_rejects_malformed_input() {
+        assert_eq!(E_BOUNDS, Value::decode(&[]).unwrap_err());
+        assert_eq!(E_BOUNDS, Value::decode(&[OED_BLOB, 0x05, 0x00]).unwrap_err());
+        assert_eq!(E_FAIL, Value::decode(&[OED_ARRAY, 0x01, 0x02, 0x00]).unwrap_err());
+        assert_eq!(E_FAIL, Value::decode(&[OED_NULL, OED_NULL]).unwrap_err());
+        assert_eq!(E_FAIL, Value::decode(&[OED_STRING, 0x02, 0x01, 0x41]).unwrap_err());
+        assert_eq!((Value::Null, 1), Value::decode_prefix(&[OED_NULL, OED_NULL]).unwrap());
+    }
+
+    #[test]
+    fn oed_rejects_deep_nesting() {
+        // `[[[...]]]`, each level an array of one element with a bogus size
+        let mut octets = Vec::new();
+        for _ in 0..100_000 {
+            octets.extend_from_slice(&[OED_ARRAY, 0x01, 0x00]);
+        }
+        octets.push(OED_NULL);
+        assert_eq!(E_BOUNDS, Value::decode(&octets).unwrap_err());
+        // nesting within the limit is fine
+        let mut value = Value::Null;
+        for _ in 1..DEPTH_MAX {
+            value = Value::Array(vec![value]);
+        }
+        assert_eq!(value, Value::decode(&value.encode()).unwrap());
+        let value = Value::Array(vec![value]);
+        assert_eq!(E_BOUNDS, Value::decode(&value.encode()).unwrap_err());
+    }
+
+    #[test]
+    fn oed_heap_round_trip() {
+        let mut core = Core::default();
+        core.init();
+        let mut no_pack = |_: &mut Core, _: Any| Err(E_NO_TYPE);
+        let mut no_unpack = |_: &mut Core, _: &Value| Err(E_NO_TYPE);
+        let value = Value::Array(vec![
+            Value::Integer(-42),
+            Value::Array(vec![
+                Value::Object(vec![
+                    (Value::Integer(1), Value::Bool(true)),
+                    (Value::Integer(2), Value::Null),
+                ]),
+                Value::Array(vec![]),
+            ]),
+        ]);
+        let list = to_heap(&mut core, &value, &mut no_unpack).unwrap();
+        assert_eq!(Any::fix(-42), core.nth(list, PLUS_1));
+        let dict = core.nth(list, PLUS_2);
+        assert_eq!(TRUE, core.dict_get(dict, PLUS_1));
+        assert_eq!(NIL, core.nth(list, MINUS_2));
+        assert_eq!(value, from_heap(&mut core, list, &mut no_pack).unwrap());
+
+        // opaque values are converted by `pack` and `unpack`
+        assert_eq!(E_NO_TYPE, from_heap(&mut core, DEBUG_DEV, &mut no_pack).unwrap_err());
+        assert_eq!(E_NO_TYPE, to_heap(&mut core, &Value::Integer(1 << 40), &mut no_unpack).unwrap_err());
+        let mut pack = |_: &mut Core, cap: Any| Ok(Value::Extension(Box::new(Value::Null), cap.raw().to_le_bytes().to_vec()));
+        let ext = from_heap(&mut core, DEBUG_DEV, &mut pack).unwrap();
+        let mut unpack = |_: &mut Core, ext: &Value| match ext {
+            Value::Extension(_, data) => Ok(Any::new(Raw::from_le_bytes(data.as_slice().try_into().unwrap()))),
+            _ => Err(E_NO_TYPE),
+        };
+        assert_eq!(DEBUG_DEV, to_heap(&mut core, &ext, &mut unpack).unwrap());
+    }
+
+}
@@ -325,6 +325,10 @@ impl Quad {
         assert!(k.is_ptr());
         Self::vm_cmp(CMP_NE, k)
     }
+    pub fn vm_cmp_equal(k: Any) -> Quad {
+        assert!(k.is_ptr());
+        Self::vm_cmp(CMP_EQUAL, k)
+    }
 
     // construct VM_ACTOR instructions
     pub fn vm_actor_send(k: Any) -> Quad {
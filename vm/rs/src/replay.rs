@@ -0,0 +1,892 @@
+// Deterministic record/replay of the nondeterministic inputs to a Core.
+//
+// A `Session` is a layer between the host and a Core. While recording,
+// it logs each input the Core can not predict:
+//   * the result of each `handle_event` of a device wrapped by
+//     `Session::device`, and each event a wrapped device's `poll` enqueues
+//   * each value read from outside, through `Session::read_input`
+//     (such as a random number, or a console character),
+//     and each reading of a clock wrapped by `Session::clock`
+//   * each event (or stub release) the host injects, through the session
+//     or a `CoreHandle`, at the run-loop step where it is delivered
+//   * the steps taken by each call to `Session::run`
+//
+// Messages injected by the host (or a `poll`) are logged by their contents,
+// so the pairs of a message are built again when it is replayed.
+// Messages posted through a `CoreHandle` are logged as they are.
+//
+// While replaying, the inbox is ignored and wrapped devices are not polled.
+// `Session::run` runs the Core for the same steps as each recorded run,
+// injecting the logged events at the same steps, and `read_input` returns
+// the logged values in the same order. Devices are called as usual,
+// so their code is run again with the same inputs, and each result is
+// checked against the log. A hash of the heap is logged after each run
+// (and at the end), so the first point where the replay diverges from the
+// recording is found, and the run is interrupted there.
+//
+// A replay must start from the same heap as its recording,
+// which usually means booting the same program in the same way.
+// Other changes the host (or a `poll`) makes, such as tasks posted
+// through a `CoreHandle`, are not logged, and are found as a divergence.
+// Host callbacks (such as a `RootSponsorPolicy::Callback`) are run again,
+// as are the outside effects of devices (such as console output).
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+
+use ::core::cell::RefCell;
+use ::core::cmp::Ordering;
+use ::core::task::Waker;
+
+use crate::*;
+use crate::core_handle::Inbound;
+
+// run-loop steps are counted from the start of recording (or replay)
+pub type Step = u64;
+
+const MESSAGE_MAX: usize = 256;  // pairs logged for a message (the rest are logged as they are)
+
+// a message, in postfix order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Part {
+    Value(Any),
+    Pair,  // of the two values before it
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Input {
+    Event { step: Step, sponsor: Any, target: Any, message: Vec<Part> },
+    Release { step: Step, stub: Any },
+    Heap { step: Step, hash: u64 },
+    Ran { steps: Step, limited: bool },  // a call to `run` ended (early, if `limited`)
+    Device { step: Step, device: usize, event: Any, result: Result<Any, Error> },
+    Clock(u64),
+    Value(u64),
+}
+
+// a log of inputs, from `Session::stop_recording`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub(crate) start: u64,  // heap hash when recording started
+    pub(crate) inputs: Vec<Input>,
+}
+
+// why a replay does not match its recording
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Heap,  // the heap differs from the recording
+    Device,  // a different device was called
+    Event,  // a device was called with a different event
+    Result,  // a device returned a different result
+    Missed,  // a recorded input was not used at its step
+    Exhausted,  // an input was needed after the last one recorded
+    Unused,  // inputs remain when the replay is stopped
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub input: usize,  // index of the recorded input
+    pub step: Step,
+    pub mismatch: Mismatch,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Recording,
+    Replaying,
+    Stopped,
+}
+
+struct State {
+    mode: Mode,
+    recording: Recording,
+    next: usize,  // index of the next input (when replaying)
+    step: Step,  // the current run-loop step
+    base: Step,  // steps taken before the current call to `run`
+    calls: Step,  // safe points reached in the current call to `run`
+    devices: usize,  // devices wrapped so far
+    divergence: Option<Divergence>,
+    interrupt: Option<InterruptHandle>,
+}
+
+impl State {
+    fn log(&mut self, input: Input) {
+        self.recording.inputs.push(input);
+    }
+    fn peek(&self) -> Option<&Input> {
+        self.recording.inputs.get(self.next)
+    }
+    fn diverge(&mut self, mismatch: Mismatch) {
+        // only the first divergence is kept, and the run stops there
+        if self.divergence.is_none() {
+            self.divergence = Some(Divergence { input: self.next, step: self.step, mismatch });
+            if let Some(interrupt) = &self.interrupt {
+                interrupt.interrupt();
+            }
+        }
+    }
+    fn missing(&mut self) {
+        // an input was needed, but the next one is something else
+        let mismatch = if self.peek().is_some() { Mismatch::Missed } else { Mismatch::Exhausted };
+        self.diverge(mismatch);
+    }
+}
+
+#[derive(Clone)]
+pub struct Session {
+    state: Rc<RefCell<State>>,
+}
+
+impl Session {
+    pub fn record() -> Session {
+        Session::new(Mode::Recording, Recording::default())
+    }
+    pub fn replay(recording: Recording) -> Session {
+        Session::new(Mode::Replaying, recording)
+    }
+    fn new(mode: Mode, recording: Recording) -> Session {
+        let state = State {
+            mode,
+            recording,
+            next: 0,
+            step: 0,
+            base: 0,
+            calls: 0,
+            devices: 0,
+            divergence: None,
+            interrupt: None,
+        };
+        Session { state: Rc::new(RefCell::new(state)) }
+    }
+    pub fn start(&self, core: &mut Core) -> Result<(), Divergence> {
+        // record (or replay) from the heap as it is now.
+        // a replay must start from the heap its recording started from.
+        let hash = core.heap_hash();
+        {
+            let mut state = self.state.borrow_mut();
+            match state.mode {
+                Mode::Recording => state.recording.start = hash,
+                Mode::Replaying if state.recording.start != hash => state.diverge(Mismatch::Heap),
+                _ => {},
+            }
+            state.interrupt = Some(core.interrupt_handle());
+        }
+        let session = self.clone();
+        core.set_step_fn(move |core| session.step(core));
+        self.divergence().map_or(Ok(()), Err)
+    }
+    pub fn device(&self, device: Box<dyn Device>) -> Box<dyn Device> {
+        // wrap a device (before it is installed), to record its results
+        let mut state = self.state.borrow_mut();
+        let id = state.devices;
+        state.devices += 1;
+        Box::new(RecordedDevice { session: self.clone(), id, device })
+    }
+    pub fn clock<F: Fn() -> u64 + 'static>(&self, clock: F) -> impl Fn() -> u64 + 'static {
+        // wrap a clock (see `Core::set_clock_fn`), to record its readings
+        let session = self.clone();
+        move || session.input(true, &clock)
+    }
+    pub fn read_input(&self, read: impl FnOnce() -> u64) -> u64 {
+        // read a value from outside the Core (a recorded input),
+        // such as a random number, or a character from the console
+        self.input(false, read)
+    }
+    pub fn send(&self, core: &mut Core, sponsor: Any, target: Any, message: Any) {
+        // inject an event from the host (errors are reported to the audit hook).
+        // while replaying, the recorded events are injected instead.
+        let message = encode(core, message);
+        self.host_event(core, sponsor, target, message);
+    }
+    pub fn release_stub(&self, core: &mut Core, stub: Any) {
+        // release a stub held by the host
+        let (mode, step) = self.mode();
+        if mode == Mode::Recording {
+            self.state.borrow_mut().log(Input::Release { step, stub });
+        }
+        if mode != Mode::Replaying {
+            core.deliver_inbound(Inbound::Release(stub));
+        }
+    }
+    pub fn run(&self, core: &mut Core, limit: i32) -> RunReport {
+        // run the Core, as `Core::run` does.
+        // while replaying, the rest of the recorded runs are made again.
+        match self.mode().0 {
+            Mode::Recording => {
+                let report = core.run(limit);
+                let limited = matches!(report.outcome, RunOutcome::StepLimit | RunOutcome::Interrupted);
+                let hash = core.heap_hash();
+                let mut state = self.state.borrow_mut();
+                state.end_run(report.steps);
+                let step = state.step;
+                state.log(Input::Ran { steps: report.steps as Step, limited });
+                state.log(Input::Heap { step, hash });
+                report
+            },
+            Mode::Replaying => {
+                let mut report = RunReport { outcome: RunOutcome::Idle, steps: 0, instructions: 0, events: 0 };
+                while let Some((steps, limited)) = self.next_run() {
+                    if limited && (steps == 0) {
+                        if let Some(interrupt) = &self.state.borrow().interrupt {
+                            interrupt.interrupt();  // stop before the first step
+                        }
+                    }
+                    report = core.run(if limited { steps } else { steps + 1 } as i32);
+                    let hash = core.heap_hash();
+                    let mut state = self.state.borrow_mut();
+                    state.end_run(report.steps);
+                    match state.peek() {
+                        Some(&Input::Ran { steps: ran, .. }) if ran == report.steps as Step => state.next += 1,
+                        _ => state.diverge(Mismatch::Missed),
+                    }
+                    state.check_heap(hash);
+                }
+                report
+            },
+            Mode::Stopped => core.run(limit),
+        }
+    }
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.state.borrow().divergence
+    }
+    pub fn stop_recording(&self, core: &mut Core) -> Option<Recording> {
+        // the log, ending with the heap as the recording leaves it
+        if self.mode().0 != Mode::Recording {
+            return None;
+        }
+        let hash = core.heap_hash();
+        core.clear_step_fn();
+        let mut state = self.state.borrow_mut();
+        let step = state.step;
+        state.log(Input::Heap { step, hash });
+        state.mode = Mode::Stopped;
+        Some(::core::mem::take(&mut state.recording))
+    }
+    pub fn stop_replay(&self, core: &mut Core) -> Result<(), Divergence> {
+        // report the first divergence (or inputs left unused)
+        if self.mode().0 != Mode::Replaying {
+            return Ok(());
+        }
+        self.replay_host(core);  // injected after the last run
+        let hash = core.heap_hash();
+        core.clear_step_fn();
+        let mut state = self.state.borrow_mut();
+        state.check_heap(hash);
+        if state.peek().is_some() {
+            state.diverge(Mismatch::Unused);
+        }
+        state.mode = Mode::Stopped;
+        state.divergence.map_or(Ok(()), Err)
+    }
+    fn mode(&self) -> (Mode, Step) {
+        let state = self.state.borrow();
+        (state.mode, state.step)
+    }
+    fn step(&self, core: &mut Core) {
+        // deliver host inputs, at a safe point between run-loop steps
+        let (mode, step) = {
+            let mut state = self.state.borrow_mut();
+            state.step = state.base + state.calls;
+            state.calls += 1;
+            (state.mode, state.step)
+        };
+        match mode {
+            Mode::Recording => {
+                for item in core.take_inbound() {
+                    let input = match &item {
+                        &Inbound::Event { sponsor, target, message } => {
+                            Some(Input::Event { step, sponsor, target, message: alloc::vec![Part::Value(message)] })
+                        },
+                        &Inbound::Release(stub) => Some(Input::Release { step, stub }),
+                        Inbound::Task(_) => None,  // not logged
+                    };
+                    if let Some(input) = input {
+                        self.state.borrow_mut().log(input);
+                    }
+                    core.deliver_inbound(item);
+                }
+            },
+            Mode::Replaying => {
+                core.take_inbound();  // posted work is replayed as inputs
+                self.replay_host(core);
+            },
+            Mode::Stopped => core.drain_inbox(),
+        }
+    }
+    fn host_event(&self, core: &mut Core, sponsor: Any, target: Any, message: Vec<Part>) {
+        let (mode, step) = self.mode();
+        if mode == Mode::Replaying {
+            return;  // replayed from the recording
+        }
+        if mode == Mode::Recording {
+            self.state.borrow_mut().log(Input::Event { step, sponsor, target, message: message.clone() });
+        }
+        match decode(core, &message) {
+            Ok(message) => core.deliver_inbound(Inbound::Event { sponsor, target, message }),
+            Err(error) => core.call_audit_fn(error, target),
+        }
+    }
+    fn replay_host(&self, core: &mut Core) {
+        // inject the events (and stub releases) recorded for this step
+        loop {
+            let input = {
+                let mut state = self.state.borrow_mut();
+                let step = match state.peek() {
+                    Some(&Input::Event { step, .. }) | Some(&Input::Release { step, .. }) => step,
+                    _ => return,  // used elsewhere
+                };
+                if state.divergence.is_some() || (step > state.step) {
+                    return;  // not yet
+                }
+                if step < state.step {
+                    state.diverge(Mismatch::Missed);
+                    return;
+                }
+                state.next += 1;
+                state.recording.inputs[state.next - 1].clone()
+            };
+            match input {
+                Input::Event { sponsor, target, message, .. } => match decode(core, &message) {
+                    Ok(message) => core.deliver_inbound(Inbound::Event { sponsor, target, message }),
+                    Err(_) => self.state.borrow_mut().diverge(Mismatch::Heap),
+                },
+                Input::Release { stub, .. } => core.deliver_inbound(Inbound::Release(stub)),
+                _ => unreachable!(),
+            }
+        }
+    }
+    fn next_run(&self) -> Option<(Step, bool)> {
+        // the steps taken by the next recorded run (if the replay goes on)
+        let state = self.state.borrow();
+        if state.divergence.is_some() {
+            return None;
+        }
+        state.recording.inputs[state.next..].iter().find_map(|input| match *input {
+            Input::Ran { steps, limited } => Some((steps, limited)),
+            _ => None,
+        })
+    }
+    fn input(&self, clock: bool, read: impl FnOnce() -> u64) -> u64 {
+        let mut state = self.state.borrow_mut();
+        match state.mode {
+            Mode::Recording => {
+                drop(state);
+                let value = read();  // (which may use the session)
+                let input = if clock { Input::Clock(value) } else { Input::Value(value) };
+                self.state.borrow_mut().log(input);
+                value
+            },
+            Mode::Replaying => {
+                let recorded = match state.peek() {
+                    Some(&Input::Clock(value)) if clock => Some(value),
+                    Some(&Input::Value(value)) if !clock => Some(value),
+                    _ => None,
+                };
+                match recorded {
+                    Some(value) => {
+                        state.next += 1;
+                        value
+                    },
+                    None => {
+                        state.missing();
+                        drop(state);
+                        read()
+                    },
+                }
+            },
+            Mode::Stopped => {
+                drop(state);
+                read()
+            },
+        }
+    }
+    fn device_result(&self, id: usize, ep: Any, result: Result<Any, Error>) {
+        let mut state = self.state.borrow_mut();
+        let step = state.step;
+        match state.mode {
+            Mode::Recording => state.log(Input::Device { step, device: id, event: ep, result }),
+            Mode::Replaying => {
+                let mismatch = match state.peek() {
+                    Some(&Input::Device { step: at, device, event, result: recorded }) => {
+                        if at != step {
+                            Some(Mismatch::Missed)
+                        } else if device != id {
+                            Some(Mismatch::Device)
+                        } else if event != ep {
+                            Some(Mismatch::Event)
+                        } else if recorded != result {
+                            Some(Mismatch::Result)
+                        } else {
+                            None
+                        }
+                    },
+                    _ => {
+                        state.missing();
+                        return;
+                    },
+                };
+                match mismatch {
+                    Some(mismatch) => state.diverge(mismatch),
+                    None => state.next += 1,
+                }
+            },
+            Mode::Stopped => {},
+        }
+    }
+}
+
+impl State {
+    fn end_run(&mut self, steps: usize) {
+        self.base += steps as Step;
+        self.calls = 0;
+        self.step = self.base;
+    }
+    fn check_heap(&mut self, hash: u64) {
+        match self.peek() {
+            Some(&Input::Heap { hash: recorded, .. }) if recorded == hash => self.next += 1,
+            Some(&Input::Heap { .. }) => self.diverge(Mismatch::Heap),
+            _ => self.missing(),
+        }
+    }
+}
+
+// a device, with its results (and the events its `poll` enqueues) recorded
+struct RecordedDevice {
+    session: Session,
+    id: usize,
+    device: Box<dyn Device>,
+}
+
+impl Device for RecordedDevice {
+    fn init(&mut self) {
+        self.device.init()
+    }
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let result = self.device.handle_event(core, ep);
+        self.session.device_result(self.id, ep, result);
+        result
+    }
+    fn drop_proxy(&mut self, core: &mut Core, cap: Any) {
+        self.device.drop_proxy(core, cap)
+    }
+    fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+        let (mode, step) = self.session.mode();
+        match mode {
+            Mode::Recording => {
+                let last = core.e_last();
+                let result = self.device.poll(core);
+                let mut ep = if last.is_ram() { core.ram(last).z() } else { core.e_first() };
+                while ep.is_ram() {
+                    let (sponsor, target) = (core.event_sponsor(ep), core.event_target(ep));
+                    let message = encode(core, core.event_message(ep));
+                    self.session.state.borrow_mut().log(Input::Event { step, sponsor, target, message });
+                    ep = core.ram(ep).z();
+                }
+                result
+            },
+            Mode::Replaying => Ok(()),  // its events are replayed as inputs
+            Mode::Stopped => self.device.poll(core),
+        }
+    }
+    fn pending(&self) -> bool {
+        (self.session.mode().0 != Mode::Replaying) && self.device.pending()
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        self.device.register_waker(waker)
+    }
+    fn compare_proxies(&self, a: Any, b: Any) -> Option<Ordering> {
+        self.device.compare_proxies(a, b)
+    }
+}
+
+fn encode(core: &Core, message: Any) -> Vec<Part> {
+    // the pairs of a message (up to `MESSAGE_MAX`), and the values they hold
+    let mut parts = Vec::new();
+    let mut pending = alloc::vec![(message, false)];
+    let mut pairs = 0;
+    while let Some((value, visited)) = pending.pop() {
+        if visited {
+            parts.push(Part::Pair);
+        } else if core.typeq(PAIR_T, value) && (pairs < MESSAGE_MAX) {
+            pairs += 1;
+            pending.push((value, true));
+            pending.push((core.cdr(value), false));
+            pending.push((core.car(value), false));
+        } else {
+            parts.push(Part::Value(value));
+        }
+    }
+    parts
+}
+
+fn decode(core: &mut Core, parts: &[Part]) -> Result<Any, Error> {
+    // build the pairs of a message again (in the order `encode` visits them)
+    let mut values = Vec::new();
+    for part in parts {
+        let value = match part {
+            Part::Value(value) => *value,
+            Part::Pair => {
+                let (cdr, car) = (values.pop().ok_or(E_BOUNDS)?, values.pop().ok_or(E_BOUNDS)?);
+                core.reserve(&Quad::pair_t(car, cdr))?
+            },
+        };
+        values.push(value);
+    }
+    match values[..] {
+        [message] => Ok(message),
+        _ => Err(E_BOUNDS),
+    }
+}
+
+/*
+
+Recordings are encoded as bytes (all numbers little-endian):
+
+    "uFrr" start:u64 count:u32 input*
+
+    input    = 1 step:u64 sponsor:u32 target:u32 count:u32 part*  ; Event
+             | 2 step:u64 stub:u32                                ; Release
+             | 3 step:u64 hash:u64                                ; Heap
+             | 4 steps:u64 limited:u8                             ; Ran
+             | 5 step:u64 device:u32 event:u32 result             ; Device
+             | 6 now:u64                                          ; Clock
+             | 7 value:u64                                        ; Value
+    part     = 0 value:u32 | 1                                    ; Value, Pair
+    result   = 0 value:u32 | 1 error:i32
+
+*/
+const MAGIC: &[u8; 4] = b"uFrr";
+
+impl Recording {
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.start.to_le_bytes());
+        out.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        for input in &self.inputs {
+            match input {
+                Input::Event { step, sponsor, target, message } => {
+                    out.push(1);
+                    out.extend_from_slice(&step.to_le_bytes());
+                    out.extend_from_slice(&sponsor.raw().to_le_bytes());
+                    out.extend_from_slice(&target.raw().to_le_bytes());
+                    out.extend_from_slice(&(message.len() as u32).to_le_bytes());
+                    for part in message {
+                        match part {
+                            Part::Value(value) => {
+                                out.push(0);
+                                out.extend_from_slice(&value.raw().to_le_bytes());
+                            },
+                            Part::Pair => out.push(1),
+                        }
+                    }
+                },
+                Input::Release { step, stub } => {
+                    out.push(2);
+                    out.extend_from_slice(&step.to_le_bytes());
+                    out.extend_from_slice(&stub.raw().to_le_bytes());
+                },
+                Input::Heap { step, hash } => {
+                    out.push(3);
+                    out.extend_from_slice(&step.to_le_bytes());
+                    out.extend_from_slice(&hash.to_le_bytes());
+                },
+                Input::Ran { steps, limited } => {
+                    out.push(4);
+                    out.extend_from_slice(&steps.to_le_bytes());
+                    out.push(*limited as u8);
+                },
+                Input::Device { step, device, event, result } => {
+                    out.push(5);
+                    out.extend_from_slice(&step.to_le_bytes());
+                    out.extend_from_slice(&(*device as u32).to_le_bytes());
+                    out.extend_from_slice(&event.raw().to_le_bytes());
+                    match result {
+                        Ok(value) => {
+                            out.push(0);
+                            out.extend_from_slice(&value.raw().to_le_bytes());
+                        },
+                        Err(error) => {
+                            out.push(1);
+                            out.extend_from_slice(&error.to_le_bytes());
+                        },
+                    }
+                },
+                Input::Clock(now) => {
+                    out.push(6);
+                    out.extend_from_slice(&now.to_le_bytes());
+                },
+                Input::Value(value) => {
+                    out.push(7);
+                    out.extend_from_slice(&value.to_le_bytes());
+                },
+            }
+        }
+        out
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Recording, Error> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(E_BOUNDS);
+        }
+        let start = reader.u64()?;
+        let count = reader.u32()?;
+        let mut inputs = Vec::new();
+        for _ in 0..count {
+            let input = match reader.u8()? {
+                1 => Input::Event {
+                    step: reader.u64()?,
+                    sponsor: reader.any()?,
+                    target: reader.any()?,
+                    message: {
+                        let count = reader.u32()?;
+                        let mut message = Vec::new();
+                        for _ in 0..count {
+                            message.push(match reader.u8()? {
+                                0 => Part::Value(reader.any()?),
+                                1 => Part::Pair,
+                                _ => return Err(E_BOUNDS),
+                            });
+                        }
+                        message
+                    },
+                },
+                2 => Input::Release {
+                    step: reader.u64()?,
+                    stub: reader.any()?,
+                },
+                3 => Input::Heap {
+                    step: reader.u64()?,
+                    hash: reader.u64()?,
+                },
+                4 => Input::Ran {
+                    steps: reader.u64()?,
+                    limited: match reader.u8()? {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(E_BOUNDS),
+                    },
+                },
+                5 => Input::Device {
+                    step: reader.u64()?,
+                    device: reader.u32()? as usize,
+                    event: reader.any()?,
+                    result: match reader.u8()? {
+                        0 => Ok(reader.any()?),
+                        1 => Err(reader.u32()? as Error),
+                        _ => return Err(E_BOUNDS),
+                    },
+                },
+                6 => Input::Clock(reader.u64()?),
+                7 => Input::Value(reader.u64()?),
+                _ => return Err(E_BOUNDS),
+            };
+            inputs.push(input);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(E_BOUNDS);  // trailing bytes
+        }
+        Ok(Recording { start, inputs })
+    }
+    #[cfg(any(test, not(feature = "no_std")))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+    #[cfg(any(test, not(feature = "no_std")))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Recording> {
+        let bytes = std::fs::read(path)?;
+        Recording::from_bytes(&bytes).map_err(|_| std::io::ErrorKind::InvalidData.into())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(E_BOUNDS);  // truncated
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn any(&mut self) -> Result<Any, Error> {
+        Ok(Any::new(self.u32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // replies a pseudo-random number to the customer (its message)
+    struct Noise {
+        state: u64,
+        session: Option<Session>,  // read through `read_input`
+    }
+
+    impl Device for Noise {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let state = &mut self.state;
+            let mut next = || {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                *state & 0xFFFF
+            };
+            let random = match &self.session {
+                Some(session) => session.read_input(next),
+                None => next(),
+            };
+            let cust = core.event_message(ep);
+            core.reserve_event(SPONSOR, cust, Any::fix(random as isize))
+        }
+    }
+
+    // prepends each message to a list, held in the heap
+    struct Keep {
+        cell: Any,
+    }
+
+    impl Device for Keep {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let list = core.reserve(&Quad::pair_t(core.event_message(ep), core.car(self.cell)))?;
+            core.set_x(self.cell, list);
+            Ok(UNDEF)
+        }
+    }
+
+    struct Booted {
+        core: Box<Core>,
+        noise: Any,
+        keep: Any,
+        cell: Any,
+        stub: Any,
+    }
+
+    fn boot(session: &Session, seed: u64, logged: bool) -> Booted {
+        let mut core = Box::new(Core::default());
+        core.init();
+        let noise = Noise { state: seed, session: logged.then(|| session.clone()) };
+        let noise = core.register_device(session.device(Box::new(noise))).unwrap();
+        let cell = core.reserve(&Quad::pair_t(NIL, NIL)).unwrap();
+        let keep = core.register_device(session.device(Box::new(Keep { cell }))).unwrap();
+        let stub = core.reserve_stub(keep, cell).unwrap();
+        let budget = TimeBudget { deadline: Some(1_000_000), per_event: Some(1_000) };
+        core.set_sponsor_time_budget(SPONSOR, budget).unwrap();
+        Booted { core, noise, keep, cell, stub }
+    }
+
+    fn record(logged: bool) -> (Recording, Any, u64) {
+        let session = Session::record();
+        let Booted { mut core, noise, keep, cell, stub } = boot(&session, 1, logged);
+        let ticks = Rc::new(::core::cell::Cell::new(0));
+        let clock = ticks.clone();
+        core.set_clock_fn(session.clock(move || {
+            clock.set(clock.get() + 7);
+            clock.get()
+        }));
+        session.start(&mut core).unwrap();
+        let handle = core.handle();
+        std::thread::spawn(move || {
+            for _ in 0..3 {
+                handle.post_event(SPONSOR, noise, keep);
+            }
+        }).join().unwrap();
+        assert_eq!(RunOutcome::Idle, session.run(&mut core, 0).outcome);
+        session.send(&mut core, SPONSOR, noise, keep);  // from the host, while idle
+        assert_eq!(RunOutcome::Idle, session.run(&mut core, 0).outcome);
+        let list = core.car(cell);
+        session.release_stub(&mut core, stub);
+        let recording = session.stop_recording(&mut core).unwrap();
+        assert!(ticks.get() > 0);
+        (recording, list, core.heap_hash())
+    }
+
+    fn replay(recording: &Recording, seed: u64, logged: bool) -> (Session, Booted) {
+        let session = Session::replay(recording.clone());
+        let mut booted = boot(&session, seed, logged);
+        booted.core.set_clock_fn(session.clock(|| 0));  // not read, when replaying
+        (session, booted)
+    }
+
+    #[test]
+    fn record_then_replay() {
+        let (recording, list, hash) = record(true);
+        assert_eq!(Ok(recording.clone()), Recording::from_bytes(&recording.to_bytes()));
+        assert_eq!(Err(E_BOUNDS), Recording::from_bytes(&recording.to_bytes()[1..]));
+        let count = |f: fn(&Input) -> bool| recording.inputs.iter().filter(|input| f(input)).count();
+        assert_eq!(3 + 1, count(|input| matches!(input, Input::Event { .. })));  // posted 3, sent 1
+        assert_eq!(1, count(|input| matches!(input, Input::Release { .. })));
+        assert_eq!(2, count(|input| matches!(input, Input::Ran { .. })));
+
+        // another seed (and clock), with the same results
+        let (session, Booted { mut core, cell, .. }) = replay(&recording, 2, true);
+        assert_eq!(Ok(()), session.start(&mut core));
+        assert_eq!(RunOutcome::Idle, session.run(&mut core, 0).outcome);
+        assert_eq!(Ok(()), session.stop_replay(&mut core));
+        assert_eq!(list, core.car(cell));
+        assert_eq!(hash, core.heap_hash());
+        let mut n = 0;
+        let mut list = core.car(cell);
+        while list.is_ram() {
+            assert!(core.car(list).is_fix());
+            list = core.cdr(list);
+            n += 1;
+        }
+        assert_eq!(4, n);
+
+        // a different starting heap
+        let (session, Booted { mut core, .. }) = replay(&recording, 1, true);
+        core.reserve(&Quad::pair_t(NIL, NIL)).unwrap();
+        let found = session.start(&mut core);
+        assert_eq!(Err(Divergence { input: 0, step: 0, mismatch: Mismatch::Heap }), found);
+
+        // a different run
+        let (session, Booted { mut core, noise, keep, .. }) = replay(&recording, 1, true);
+        assert_eq!(Ok(()), session.start(&mut core));
+        let evt = core.reserve_event(SPONSOR, noise, keep).unwrap();
+        core.event_enqueue(evt);  // not through the session
+        assert_eq!(RunOutcome::Interrupted, session.run(&mut core, 0).outcome);
+        assert_eq!(Some(Mismatch::Result), session.divergence().map(|found| found.mismatch));
+        assert!(session.stop_replay(&mut core).is_err());
+
+        // a replay stopped early
+        let (session, Booted { mut core, .. }) = replay(&recording, 1, true);
+        assert_eq!(Ok(()), session.start(&mut core));
+        let found = session.stop_replay(&mut core).unwrap_err();
+        assert_eq!(Mismatch::Missed, found.mismatch);
+    }
+
+    #[test]
+    fn unlogged_inputs_diverge() {
+        // the noise is not read through `read_input`, so is not replayed
+        let (recording, _, _) = record(false);
+        assert!(!recording.inputs.iter().any(|input| matches!(input, Input::Value(_))));
+        let (session, Booted { mut core, .. }) = replay(&recording, 2, false);
+        assert_eq!(Ok(()), session.start(&mut core));
+        session.run(&mut core, 0);
+        let found = session.stop_replay(&mut core).unwrap_err();
+        assert_eq!(Mismatch::Heap, found.mismatch);
+    }
+
+}
@@ -0,0 +1,25 @@
+// The ReplyDevice receives replies to messages sent by `Core::call`.
+// Each customer is a proxy whose handle is the fixnum id of the reply.
+
+use crate::*;
+
+pub(crate) struct ReplyDevice;
+
+impl Device for ReplyDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let event = core.mem(ep);
+        let customer = event.x();
+        let msg = event.y();
+        let proxy = core.ram(core.cap_to_ptr(customer));
+        if proxy.t() != PROXY_T {
+            return Ok(UNDEF);  // ignore messages sent to the device itself
+        }
+        core.reply_received(proxy.y().get_fix()?, msg)?;
+        Ok(UNDEF)
+    }
+    fn drop_proxy(&mut self, core: &mut Core, cap: Any) {
+        if let Some(id) = core.ram(core.cap_to_ptr(cap)).y().fix_num() {
+            core.reply_dropped(id);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Event scheduling policies (see `Core::set_scheduling_policy`).
+//
+// Under `Fifo`, events are dispatched straight from the event queue.
+// Other policies move events into ready queues as they arrive,
+// and choose among the queues by a key: the virtual time of each sponsor
+// (for fair scheduling), the rank of a priority, or a random number.
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+
+use crate::*;
+
+// how `dispatch_event` chooses the next event from the event queue
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    #[default]
+    Fifo,  // oldest event first
+    SponsorRoundRobin,  // each sponsor in turn (oldest event first)
+    WeightedFair,  // like `SponsorRoundRobin`, in proportion to sponsor weights
+    Priority,  // highest priority event first (oldest first, within a priority)
+    Chaos { seed: u64 },  // random events and continuations, repeatable for each seed
+}
+
+pub(crate) const FAIR_SHARE: u64 = 1 << 16;  // virtual time for one event, at weight 1
+
+const CHAOS_MUL: u64 = 0x2545_F491_4F6C_DD1D;
+
+// events taken from the event queue by a policy other than `Fifo`
+#[derive(Clone, Default)]
+struct ReadyQueues {
+    next_seq: u64,  // arrival order of the next event
+    queues: BTreeMap<u64, VecDeque<(u64, Any)>>,  // queue id -> (seq, event), oldest first
+    order: BTreeSet<(u64, u64, u64)>,  // (key, seq of first event, queue id) of each non-empty queue
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Scheduler {
+    policy: SchedulingPolicy,
+    weights: BTreeMap<usize, u64>,  // sponsor offset -> weight (default 1)
+    vtime: BTreeMap<usize, u64>,  // sponsor offset -> virtual time of its next event
+    virtual_time: u64,  // virtual time of the last event dispatched
+    ready: ReadyQueues,
+    priority: BTreeMap<usize, i32>,  // event offset -> priority (default 0)
+    chaos_state: u64,  // random number generator for the `Chaos` policy
+}
+
+impl Scheduler {
+    pub(crate) const fn new() -> Self {
+        Scheduler {
+            policy: SchedulingPolicy::Fifo,
+            weights: BTreeMap::new(),
+            vtime: BTreeMap::new(),
+            virtual_time: 0,
+            ready: ReadyQueues { next_seq: 0, queues: BTreeMap::new(), order: BTreeSet::new() },
+            priority: BTreeMap::new(),
+            chaos_state: 0,
+        }
+    }
+    pub(crate) fn policy(&self) -> SchedulingPolicy {
+        self.policy
+    }
+    pub(crate) fn set_policy(&mut self, policy: SchedulingPolicy) {
+        // ready events should be returned to the event queue first (see `take_ready`)
+        if let SchedulingPolicy::Chaos { seed } = policy {
+            self.chaos_state = seed ^ CHAOS_MUL;  // restart the sequence
+            if self.chaos_state == 0 {
+                self.chaos_state = CHAOS_MUL;
+            }
+        }
+        self.policy = policy;
+    }
+    pub(crate) fn replace_policy(&mut self, policy: SchedulingPolicy) -> SchedulingPolicy {
+        // switch policies for a while (the `Chaos` sequence continues)
+        ::core::mem::replace(&mut self.policy, policy)
+    }
+    pub(crate) fn has_ready(&self) -> bool {
+        !self.ready.order.is_empty()
+    }
+    pub(crate) fn ready_events(&self) -> Vec<Any> {
+        // oldest first
+        let mut ready: Vec<(u64, Any)> = self.ready.queues.values().flatten().copied().collect();
+        ready.sort_unstable_by_key(|&(seq, _)| seq);
+        ready.into_iter().map(|(_, ep)| ep).collect()
+    }
+    pub(crate) fn take_ready(&mut self) -> Vec<Any> {
+        let ready = self.ready_events();
+        self.ready = ReadyQueues::default();
+        ready
+    }
+    pub(crate) fn make_ready(&mut self, ep: Any, sponsor: Any) {
+        // add an event to the back of its ready queue.
+        // events with lower keys are dispatched first (oldest first, for equal keys).
+        let (queue_id, key) = match self.policy {
+            SchedulingPolicy::Priority => {
+                let rank = (i32::MAX as i64 - self.priority(ep) as i64) as u64;
+                (rank, rank)
+            },
+            SchedulingPolicy::Chaos { .. } => (0, 0),
+            _ => {
+                let vtime = self.vtime.get(&sponsor.ofs()).copied().unwrap_or(0);
+                (sponsor.ofs() as u64, vtime.max(self.virtual_time))  // idle sponsors start at the current virtual time
+            },
+        };
+        let seq = self.ready.next_seq;
+        self.ready.next_seq += 1;
+        let queue = self.ready.queues.entry(queue_id).or_default();
+        if queue.is_empty() {
+            self.ready.order.insert((key, seq, queue_id));
+        }
+        queue.push_back((seq, ep));
+    }
+    pub(crate) fn next_ready<F: Fn(Any) -> Any>(&mut self, sponsor_of: F) -> Option<Any> {
+        // remove the next event, as chosen by the policy
+        let (key, _, queue_id) = self.ready.order.pop_first()?;
+        let (_, ep) = match self.policy {
+            SchedulingPolicy::Chaos { .. } => {
+                let random = self.random();
+                let queue = self.ready.queues.get_mut(&queue_id).unwrap();
+                let index = (random % queue.len() as u64) as usize;
+                queue.swap_remove_back(index).unwrap()
+            },
+            _ => self.ready.queues.get_mut(&queue_id).unwrap().pop_front().unwrap(),
+        };
+        let mut key = key;
+        if let SchedulingPolicy::SponsorRoundRobin | SchedulingPolicy::WeightedFair = self.policy {
+            let sponsor = sponsor_of(ep);
+            let weight = match self.policy {
+                SchedulingPolicy::WeightedFair => self.weight(sponsor),
+                _ => 1,
+            };
+            self.virtual_time = key;
+            key += FAIR_SHARE / weight;
+            self.vtime.insert(sponsor.ofs(), key);
+        }
+        let queue = self.ready.queues.get_mut(&queue_id).unwrap();
+        match queue.front() {
+            Some(&(seq, _)) => {
+                self.ready.order.insert((key, seq, queue_id));
+            },
+            None => {
+                self.ready.queues.remove(&queue_id);
+            },
+        }
+        Some(ep)
+    }
+    pub(crate) fn idle(&mut self) {
+        // fair scheduling starts afresh
+        self.vtime.clear();
+        self.virtual_time = 0;
+    }
+    pub(crate) fn random(&mut self) -> u64 {
+        // xorshift64*
+        let mut x = self.chaos_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.chaos_state = x;
+        x.wrapping_mul(CHAOS_MUL)
+    }
+    pub(crate) fn weight(&self, sponsor: Any) -> u64 {
+        self.weights.get(&sponsor.ofs()).copied().unwrap_or(1)
+    }
+    pub(crate) fn set_weight(&mut self, sponsor: Any, weight: u64) {
+        if weight == 1 {
+            self.weights.remove(&sponsor.ofs());
+        } else {
+            self.weights.insert(sponsor.ofs(), weight);
+        }
+    }
+    pub(crate) fn priority(&self, ep: Any) -> i32 {
+        self.priority.get(&ep.ofs()).copied().unwrap_or(0)
+    }
+    pub(crate) fn set_priority(&mut self, ep: Any, priority: i32) {
+        if priority == 0 {
+            self.priority.remove(&ep.ofs());
+        } else {
+            self.priority.insert(ep.ofs(), priority);
+        }
+    }
+    #[cfg(test)]
+    pub(crate) fn has_priorities(&self) -> bool {
+        !self.priority.is_empty()
+    }
+    pub(crate) fn inherit(&mut self, cause: Any, ep: Any) {
+        if let Some(&priority) = self.priority.get(&cause.ofs()) {
+            self.priority.insert(ep.ofs(), priority);
+        }
+    }
+    pub(crate) fn forget(&mut self, ofs: usize, sponsor: bool) {
+        if sponsor {
+            self.weights.remove(&ofs);
+            self.vtime.remove(&ofs);
+        }
+        if !self.priority.is_empty() {
+            self.priority.remove(&ofs);
+        }
+    }
+}
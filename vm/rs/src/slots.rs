@@ -0,0 +1,95 @@
+// Numbered slots for device state, named by the (fixnum) tags of proxies.
+//
+// A slot is reused only after its entry has been removed,
+// and every proxy counted as naming it has been dropped,
+// so a stale proxy never reaches a newer entry.
+// Vacant slots at the end are released, so the table shrinks as it empties.
+
+use alloc::vec::Vec;
+
+pub(crate) struct Slots<T> {
+    slots: Vec<(Option<T>, usize)>,  // (entry, proxies naming the slot)
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Slots { slots: Vec::new() }
+    }
+}
+
+impl<T> Slots<T> {
+    pub(crate) fn vacant(&self) -> usize {
+        // the slot number `insert` will use next
+        self.slots.iter()
+            .position(|(entry, proxies)| entry.is_none() && (*proxies == 0))
+            .unwrap_or(self.slots.len())
+    }
+    pub(crate) fn insert(&mut self, entry: T, proxies: usize) -> usize {
+        let nr = self.vacant();
+        if nr == self.slots.len() {
+            self.slots.push((None, 0));
+        }
+        self.slots[nr] = (Some(entry), proxies);
+        nr
+    }
+    pub(crate) fn get(&self, nr: usize) -> Option<&T> {
+        self.slots.get(nr).and_then(|(entry, _)| entry.as_ref())
+    }
+    #[cfg(any(test, not(feature = "no_std")))]
+    pub(crate) fn get_mut(&mut self, nr: usize) -> Option<&mut T> {
+        self.slots.get_mut(nr).and_then(|(entry, _)| entry.as_mut())
+    }
+    pub(crate) fn take(&mut self, nr: usize) -> Option<T> {
+        let entry = self.slots.get_mut(nr).and_then(|(entry, _)| entry.take());
+        self.trim();
+        entry
+    }
+    pub(crate) fn drop_proxy(&mut self, nr: usize) {
+        if let Some((_, proxies)) = self.slots.get_mut(nr) {
+            *proxies = proxies.saturating_sub(1);
+        }
+        self.trim();
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.slots.iter().enumerate().filter_map(|(nr, (entry, _))| Some((nr, entry.as_ref()?)))
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> usize {
+        // slots in use, or named by a proxy
+        self.slots.len()
+    }
+    fn trim(&mut self) {
+        while matches!(self.slots.last(), Some((None, 0))) {
+            self.slots.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn slots_reused_after_proxies_drop() {
+        let mut slots = Slots::default();
+        assert_eq!(0, slots.insert('a', 1));
+        assert_eq!(1, slots.insert('b', 0));
+        assert_eq!(Some('a'), slots.take(0));
+        assert_eq!(2, slots.insert('c', 0));  // slot 0 is still named by a proxy
+        slots.drop_proxy(0);
+        assert_eq!(0, slots.insert('d', 0));
+        assert_eq!(vec![(0, &'d'), (1, &'b'), (2, &'c')], slots.iter().collect::<Vec<_>>());
+        slots.take(2);
+        slots.take(1);
+        assert_eq!(1, slots.capacity());  // vacant slots at the end are released
+        slots.take(0);
+        assert!(slots.is_empty());
+        assert_eq!(0, slots.capacity());
+    }
+
+}
@@ -0,0 +1,614 @@
+// The TcpDevice provides TCP networking using `std::net`.
+// The interface is described in `tcp_dev.md`.
+//
+// Sockets block, so each accept, read, write, or connect runs on a helper
+// thread. Requests are recorded when they arrive, and completed by `poll`,
+// which the host calls between `run_loop` slices. Each result posted by a
+// helper thread wakes the waker registered by an async host.
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ::core::cell::RefCell;
+use ::core::task::Waker;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+
+use crate::*;
+use crate::blob_dev::BlobDevice;
+use crate::completions::{Acceptor, Completions};
+use crate::slots::Slots;
+
+const READ_CHUNK_MAX: usize = 1<<10;  // largest blob produced by a read request
+
+fn listener_tag(listener_nr: usize) -> Any {
+    Any::fix(-(listener_nr as isize) - 1)
+}
+fn conn_tag(conn_nr: usize) -> Any {
+    Any::fix(conn_nr as isize)
+}
+
+// replies are held as a stub, protecting a `sponsor,customer` pair
+
+struct Listener {
+    acceptor: Acceptor,
+    on_open: Any,  // stub holding `sponsor,on_open`
+}
+
+struct Connection {
+    stream: TcpStream,
+    referenced: bool,  // connection proxy not yet dropped
+    failed: bool,
+    unread: Vec<u8>,  // received, but not yet delivered as a blob
+    reading: Option<Any>,  // stub holding `sponsor,callback`
+    writing: Option<Any>,  // stub holding `sponsor,callback`
+}
+
+// posted by helper threads
+enum Completion {
+    Connected(Any, io::Result<TcpStream>),  // callback stub, new stream
+    Accepted(usize, TcpStream),  // listener_nr, new stream
+    Stopped(usize),  // listener_nr, no longer accepting
+    Read(usize, io::Result<Vec<u8>>),  // conn_nr, octets (none at end of stream)
+    Written(usize, io::Result<()>),  // conn_nr
+}
+
+pub struct TcpDevice {
+    config: Vec<String>,  // addresses indexed by petname
+    blob_dev: Rc<RefCell<BlobDevice>>,
+    dev_cap: Any,
+    listeners: Slots<Listener>,  // listener_nr -> listener
+    connections: Slots<Connection>,  // conn_nr -> connection
+    connecting: usize,  // connect requests not yet completed
+    completions: Completions<Completion>,
+}
+
+impl TcpDevice {
+    pub fn new(config: Vec<String>, blob_dev: Rc<RefCell<BlobDevice>>) -> TcpDevice {
+        TcpDevice {
+            config,
+            blob_dev,
+            dev_cap: UNDEF,
+            listeners: Slots::default(),
+            connections: Slots::default(),
+            connecting: 0,
+            completions: Completions::default(),
+        }
+    }
+
+    fn address(&self, petname: Any) -> Result<String, Error> {
+        let n = petname.get_fix()?;
+        if n < 0 {
+            return Err(E_BOUNDS);
+        }
+        match self.config.get(n as usize) {
+            Some(address) => Ok(address.clone()),
+            None => Err(E_BOUNDS),
+        }
+    }
+
+    fn register(&mut self, core: &mut Core, stream: TcpStream) -> Result<Any, Error> {
+        // track a newly-opened connection, returning its capability
+        let conn_nr = self.connections.vacant();
+        let proxy = core.reserve_proxy(self.dev_cap, conn_tag(conn_nr))?;
+        self.connections.insert(Connection {
+            stream,
+            referenced: true,
+            failed: false,
+            unread: Vec::new(),
+            reading: None,
+            writing: None,
+        }, 1);
+        Ok(proxy)
+    }
+
+    fn check_forgotten(&mut self, conn_nr: usize) {
+        // dispose of the connection once nothing refers to it
+        if let Some(conn) = self.connections.get(conn_nr) {
+            if !conn.referenced && conn.reading.is_none() && conn.writing.is_none() {
+                self.connections.take(conn_nr);
+            }
+        }
+    }
+
+    fn listen(&mut self, core: &mut Core, sponsor: Any, address: String, on_open: Any) -> Result<Any, Error> {
+        let listener_nr = self.listeners.vacant();
+        let acceptor = TcpListener::bind(address).and_then(|socket| {
+            let accepted = move |stream| Completion::Accepted(listener_nr, stream);
+            Acceptor::spawn(socket, &self.completions, accepted, Completion::Stopped(listener_nr))
+        });
+        let acceptor = match acceptor {
+            Ok(acceptor) => acceptor,
+            Err(_) => return fail_result(core),
+        };
+        let on_open = hold(core, self.dev_cap, sponsor, on_open)?;
+        let stop = core.reserve_proxy(self.dev_cap, listener_tag(listener_nr))?;
+        self.listeners.insert(Listener { acceptor, on_open }, 2);  // named by `stop` and the helper thread
+        ok_result(core, stop)
+    }
+
+    fn stop_listening(&mut self, core: &mut Core, listener_nr: usize) {
+        if let Some(listener) = self.listeners.take(listener_nr) {
+            listener.acceptor.stop();
+            core.release_stub(listener.on_open);
+        }
+    }
+
+    fn connect(&mut self, core: &mut Core, sponsor: Any, address: String, callback: Any) -> Result<(), Error> {
+        let callback = hold(core, self.dev_cap, sponsor, callback)?;
+        let completions = self.completions.clone();
+        thread::spawn(move || {
+            completions.post(Completion::Connected(callback, TcpStream::connect(address)));
+        });
+        self.connecting += 1;
+        Ok(())
+    }
+
+    fn conn_request(&mut self, core: &mut Core, sponsor: Any, conn_nr: usize, callback: Any, request: Any) -> Result<Option<Any>, Error> {
+        // returns a result for immediate delivery, or `None` if pending
+        let dev_cap = self.dev_cap;
+        let data = if request.is_cap() {
+            match self.blob_dev.borrow().blob_data(core, request) {
+                Ok(data) => Some(data.to_vec()),
+                Err(_) => return Ok(Some(fail_result(core)?)),  // blob required
+            }
+        } else {
+            None
+        };
+        let conn = match self.connections.get_mut(conn_nr) {
+            Some(conn) if !conn.failed => conn,
+            _ => return Ok(Some(fail_result(core)?)),  // connection failed
+        };
+        let busy = match request {
+            UNDEF => conn.reading.is_some(),  // read request
+            _ if request.is_cap() || (request == NIL) => conn.writing.is_some(),  // write request
+            _ => return Err(E_BOUNDS),
+        };
+        if (request == UNDEF) && !busy && !conn.unread.is_empty() {
+            // deliver octets left over from a previous read
+            let result = self.blob_dev.borrow_mut().alloc_blob(core, &conn.unread)
+                .and_then(|blob| ok_result(core, blob));
+            if result.is_ok() {
+                conn.unread.clear();
+            }
+            return Ok(Some(result.or_else(|error| error_result(core, error))?));
+        }
+        let mut stream = match conn.stream.try_clone() {
+            Ok(stream) if !busy => stream,
+            _ => return Ok(Some(fail_result(core)?)),  // busy, or out of sockets
+        };
+        let completions = self.completions.clone();
+        if request == UNDEF {
+            conn.reading = Some(hold(core, dev_cap, sponsor, callback)?);
+            thread::spawn(move || {
+                let mut buf = vec![0_u8; READ_CHUNK_MAX];
+                let result = loop {
+                    match stream.read(&mut buf) {
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        result => break result,
+                    }
+                };
+                let result = result.map(|n| {
+                    buf.truncate(n);
+                    buf
+                });
+                completions.post(Completion::Read(conn_nr, result));
+            });
+        } else {
+            conn.writing = Some(hold(core, dev_cap, sponsor, callback)?);
+            let data = data.unwrap_or_default();
+            let close = request == NIL;
+            thread::spawn(move || {
+                let mut result = stream.write_all(&data);
+                if close && result.is_ok() {
+                    result = stream.shutdown(Shutdown::Write);
+                }
+                completions.post(Completion::Written(conn_nr, result));
+            });
+        }
+        Ok(None)
+    }
+
+    fn complete(&mut self, core: &mut Core, completion: Completion) -> Result<(), Error> {
+        match completion {
+            Completion::Connected(callback, result) => {
+                self.connecting -= 1;
+                let result = match result {
+                    Ok(stream) => {
+                        let conn = self.register(core, stream)?;
+                        ok_result(core, conn)?
+                    },
+                    Err(_) => fail_result(core)?,
+                };
+                reply(core, callback, result)
+            },
+            Completion::Accepted(listener_nr, stream) => {
+                let on_open = match self.listeners.get(listener_nr) {
+                    Some(listener) => core.ram(listener.on_open).y(),  // sponsor,on_open
+                    None => return Ok(()),  // stopped listening
+                };
+                let conn = self.register(core, stream)?;
+                send(core, core.car(on_open), core.cdr(on_open), conn)
+            },
+            Completion::Stopped(listener_nr) => {
+                self.listeners.drop_proxy(listener_nr);
+                Ok(())
+            },
+            Completion::Read(conn_nr, result) => {
+                let conn = match self.connections.get_mut(conn_nr) {
+                    Some(conn) => conn,
+                    None => return Ok(()),
+                };
+                let callback = match conn.reading.take() {
+                    Some(callback) => callback,
+                    None => return Ok(()),
+                };
+                let result = match result {
+                    Ok(data) if data.is_empty() => ok_result(core, NIL)?,  // end of stream
+                    Ok(data) => {
+                        let result = self.blob_dev.borrow_mut().alloc_blob(core, &data)
+                            .and_then(|blob| ok_result(core, blob));
+                        match result {
+                            Ok(result) => result,
+                            Err(error) => {
+                                conn.unread = data;  // keep them for the next read
+                                error_result(core, error)?
+                            },
+                        }
+                    },
+                    Err(_) => {
+                        conn.failed = true;
+                        fail_result(core)?
+                    },
+                };
+                reply(core, callback, result)?;
+                self.check_forgotten(conn_nr);
+                Ok(())
+            },
+            Completion::Written(conn_nr, result) => {
+                let conn = match self.connections.get_mut(conn_nr) {
+                    Some(conn) => conn,
+                    None => return Ok(()),
+                };
+                let callback = match conn.writing.take() {
+                    Some(callback) => callback,
+                    None => return Ok(()),
+                };
+                let result = match result {
+                    Ok(()) => ok_result(core, UNDEF)?,
+                    Err(_) => {
+                        conn.failed = true;
+                        fail_result(core)?
+                    },
+                };
+                reply(core, callback, result)?;
+                self.check_forgotten(conn_nr);
+                Ok(())
+            },
+        }
+    }
+}
+
+fn ok_result(core: &mut Core, output: Any) -> Result<Any, Error> {
+    core.reserve(&Quad::pair_t(TRUE, output))
+}
+fn fail_result(core: &mut Core) -> Result<Any, Error> {
+    error_result(core, E_FAIL)
+}
+fn error_result(core: &mut Core, error: Error) -> Result<Any, Error> {
+    core.reserve(&Quad::pair_t(FALSE, Any::fix(error as isize)))
+}
+fn hold(core: &mut Core, dev_cap: Any, sponsor: Any, customer: Any) -> Result<Any, Error> {
+    // protect `sponsor,customer` until a reply is sent
+    let pair = core.reserve(&Quad::pair_t(sponsor, customer))?;
+    core.reserve_stub(dev_cap, pair)
+}
+fn send(core: &mut Core, sponsor: Any, target: Any, msg: Any) -> Result<(), Error> {
+    let evt = core.reserve_event(sponsor, target, msg)?;
+    core.event_enqueue(evt);
+    Ok(())
+}
+fn reply(core: &mut Core, callback: Any, result: Any) -> Result<(), Error> {
+    // deliver `result` to the callback held by a stub, releasing the stub
+    let held = core.ram(callback).y();  // sponsor,callback
+    core.release_stub(callback);
+    send(core, core.car(held), core.cdr(held), result)
+}
+
+impl Device for TcpDevice {
+    fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+        let event = core.mem(ep);
+        let sponsor = event.t();
+        let target = event.x();
+        let msg = event.y();  // to_cancel,callback,request
+        let myself = core.ram(core.cap_to_ptr(target));
+        if myself.t() == PROXY_T {
+            self.dev_cap = myself.x();
+            let tag = myself.y().get_fix()?;
+            if tag < 0 {  // stop listening
+                self.stop_listening(core, (-1 - tag) as usize);
+                return Ok(UNDEF);
+            }
+            let callback = core.nth(msg, PLUS_2);
+            if !callback.is_cap() {
+                return Err(E_NOT_CAP);
+            }
+            let request = core.nth(msg, MINUS_2);
+            return match self.conn_request(core, sponsor, tag as usize, callback, request)? {
+                Some(result) => core.reserve_event(sponsor, callback, result),
+                None => Ok(UNDEF),
+            };
+        }
+        self.dev_cap = target;
+        let callback = core.nth(msg, PLUS_2);
+        if !callback.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let request = core.nth(msg, MINUS_2);
+        if request.is_fix() {  // connect request
+            let address = self.address(request)?;
+            self.connect(core, sponsor, address, callback)?;
+            return Ok(UNDEF);
+        }
+        // listen request
+        let petname = core.nth(request, PLUS_1);
+        let address = self.address(petname)?;
+        let on_open = core.nth(request, MINUS_1);
+        if !on_open.is_cap() {
+            return Err(E_NOT_CAP);
+        }
+        let result = self.listen(core, sponsor, address, on_open)?;
+        core.reserve_event(sponsor, callback, result)
+    }
+    fn drop_proxy(&mut self, core: &mut Core, proxy: Any) {
+        let ptr = core.cap_to_ptr(proxy);
+        if let Some(tag) = core.ram(ptr).y().fix_num() {
+            if tag < 0 {  // no one can stop the listener now
+                let listener_nr = (-1 - tag) as usize;
+                self.stop_listening(core, listener_nr);
+                self.listeners.drop_proxy(listener_nr);
+            } else {
+                let conn_nr = tag as usize;
+                if let Some(conn) = self.connections.get_mut(conn_nr) {
+                    conn.referenced = false;
+                }
+                self.check_forgotten(conn_nr);
+                self.connections.drop_proxy(conn_nr);
+            }
+        }
+    }
+    fn poll(&mut self, core: &mut Core) -> Result<(), Error> {
+        while let Some(completion) = self.completions.take() {
+            self.complete(core, completion)?;
+        }
+        Ok(())
+    }
+    fn pending(&self) -> bool {
+        (self.connecting > 0)
+            || !self.listeners.is_empty()
+            || self.connections.iter()
+                .any(|(_, conn)| conn.reading.is_some() || conn.writing.is_some())
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        self.completions.register(waker);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::format;
+    use alloc::vec;
+    use std::time::Duration;
+
+    use super::*;
+
+    // remembers each `#t,output` or `#f,error` result (or bare capability)
+    struct Recorder {
+        log: Rc<RefCell<Vec<(Any, Any)>>>,
+    }
+    impl Device for Recorder {
+        fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+            let msg = core.event_message(ep);
+            let entry = if msg.is_cap() { (UNDEF, msg) } else { (core.car(msg), core.cdr(msg)) };
+            if entry.1.is_cap() {
+                core.reserve_stub(DEBUG_DEV, entry.1)?;  // keep capability alive
+            }
+            self.log.borrow_mut().push(entry);
+            Ok(UNDEF)
+        }
+    }
+
+    fn request(core: &mut Core, recorder: Any, target: Any, input: Any) {
+        let msg = core.reserve(&Quad::pair_t(recorder, input)).unwrap();
+        let msg = core.reserve(&Quad::pair_t(UNDEF, msg)).unwrap();
+        let evt = core.reserve_event(SPONSOR, target, msg).unwrap();
+        core.event_enqueue(evt);
+    }
+
+    fn run_until(core: &mut Core, log: &Rc<RefCell<Vec<(Any, Any)>>>, count: usize) {
+        for _ in 0..5000 {
+            assert_eq!(ZERO, core.run_loop(0));
+            core.poll_devices().unwrap();
+            if log.borrow().len() >= count {
+                assert_eq!(ZERO, core.run_loop(0));
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("timed out waiting for {} results", count);
+    }
+
+    #[test]
+    fn tcp_dev_loopback() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = vec![format!("127.0.0.1:{}", port)];
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Box::new(Core::default());
+        core.init();
+        core.install_device(BLOB_DEV, Box::new(blob_dev.clone()));
+        let tcp_dev = core.register_device(Box::new(TcpDevice::new(config, blob_dev.clone()))).unwrap();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+
+        // listen on petname 0
+        let listen = core.reserve(&Quad::pair_t(ZERO, recorder)).unwrap();
+        request(&mut core, recorder, tcp_dev, listen);
+        run_until(&mut core, &log, 1);
+        let (ok, stop) = log.borrow()[0];
+        assert_eq!(TRUE, ok);
+        assert!(stop.is_cap());
+
+        // connect to petname 0
+        request(&mut core, recorder, tcp_dev, ZERO);
+        run_until(&mut core, &log, 3);
+        let opened: Vec<(Any, Any)> = log.borrow()[1..3].to_vec();
+        let client = opened.iter().find(|(ok, _)| *ok == TRUE).unwrap().1;
+        let server = opened.iter().find(|(ok, _)| *ok == UNDEF).unwrap().1;
+        assert!(client.is_cap() && server.is_cap());
+
+        // write from client, read at server
+        let blob = blob_dev.borrow_mut().alloc_blob(&mut core, b"hi").unwrap();
+        core.reserve_stub(DEBUG_DEV, blob).unwrap();
+        request(&mut core, recorder, client, blob);
+        request(&mut core, recorder, server, UNDEF);
+        run_until(&mut core, &log, 5);
+        let results: Vec<(Any, Any)> = log.borrow()[3..5].to_vec();
+        assert!(results.contains(&(TRUE, UNDEF)));  // write complete
+        let (ok, received) = *results.iter().find(|(_, v)| *v != UNDEF).unwrap();
+        assert_eq!(TRUE, ok);
+        assert_eq!(b"hi", blob_dev.borrow().blob_data(&core, received).unwrap());
+
+        // close from client, read EOF at server
+        request(&mut core, recorder, client, NIL);
+        request(&mut core, recorder, server, UNDEF);
+        run_until(&mut core, &log, 7);
+        let results: Vec<(Any, Any)> = log.borrow()[5..7].to_vec();
+        assert!(results.contains(&(TRUE, UNDEF)));  // close complete
+        assert!(results.contains(&(TRUE, NIL)));  // end of stream
+
+        // stop listening
+        let evt = core.reserve_event(SPONSOR, stop, UNDEF).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+    }
+
+    #[test]
+    fn tcp_dev_keeps_unread_octets() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = vec![format!("127.0.0.1:{}", port)];
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Box::new(Core::default());
+        core.init();
+        core.install_device(BLOB_DEV, Box::new(blob_dev.clone()));
+        let tcp_dev = core.register_device(Box::new(TcpDevice::new(config, blob_dev.clone()))).unwrap();
+        let recorder = core.register_device(Box::new(Recorder { log: log.clone() })).unwrap();
+        let listen = core.reserve(&Quad::pair_t(ZERO, recorder)).unwrap();
+        request(&mut core, recorder, tcp_dev, listen);
+        request(&mut core, recorder, tcp_dev, ZERO);
+        run_until(&mut core, &log, 3);
+        let opened: Vec<(Any, Any)> = log.borrow()[1..3].to_vec();
+        let client = opened.iter().find(|(ok, _)| *ok == TRUE).unwrap().1;
+        let server = opened.iter().find(|(ok, _)| *ok == UNDEF).unwrap().1;
+        let blob = blob_dev.borrow_mut().alloc_blob(&mut core, b"hi").unwrap();
+        core.reserve_stub(DEBUG_DEV, blob).unwrap();
+        request(&mut core, recorder, client, blob);
+        run_until(&mut core, &log, 4);
+
+        // with blob memory full, the read fails, but the octets are kept
+        let mut fillers = Vec::new();
+        for size in [4096, 256, 16, 1] {
+            while let Ok(filler) = blob_dev.borrow_mut().alloc_blob(&mut core, &vec![0; size]) {
+                fillers.push(core.reserve_stub(DEBUG_DEV, filler).unwrap());
+            }
+        }
+        request(&mut core, recorder, server, UNDEF);
+        run_until(&mut core, &log, 5);
+        assert_eq!((FALSE, Any::fix(E_NO_MEM as isize)), log.borrow()[4]);
+
+        // once there is room, the next read delivers them
+        for filler in fillers {
+            core.release_stub(filler);
+        }
+        core.gc_collect_all();
+        request(&mut core, recorder, server, UNDEF);
+        run_until(&mut core, &log, 6);
+        let (ok, received) = log.borrow()[5];
+        assert_eq!(TRUE, ok);
+        assert_eq!(b"hi", blob_dev.borrow().blob_data(&core, received).unwrap());
+    }
+
+    #[test]
+    fn tcp_dev_drops_listener() {
+        // remembers the sponsor of each result, but not the capabilities
+        struct Forgetful {
+            log: Rc<RefCell<Vec<Any>>>,
+        }
+        impl Device for Forgetful {
+            fn handle_event(&mut self, core: &mut Core, ep: Any) -> Result<Any, Error> {
+                self.log.borrow_mut().push(core.event_sponsor(ep));
+                Ok(UNDEF)
+            }
+        }
+        let config = vec![String::from("127.0.0.1:0")];
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let tcp_dev = Rc::new(RefCell::new(TcpDevice::new(config, blob_dev.clone())));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut core = Box::new(Core::default());
+        core.init();
+        core.install_device(BLOB_DEV, Box::new(blob_dev));
+        let tcp_cap = core.register_device(Box::new(tcp_dev.clone())).unwrap();
+        let forgetful = core.register_device(Box::new(Forgetful { log: log.clone() })).unwrap();
+        let quota = core.reserve(&Quad::quota(PLUS_16K, PLUS_16K, PLUS_16K)).unwrap();
+        core.set_z(quota, SPONSOR);  // parent
+        let sponsor = core.reserve(&Quad::sponsor_t(quota, ZERO)).unwrap();
+        core.reserve_stub(DEBUG_DEV, sponsor).unwrap();  // keep `sponsor` alive
+        core.start_sponsor(sponsor, forgetful).unwrap();
+        assert_eq!(ZERO, core.run_loop(0));
+        log.borrow_mut().clear();
+
+        // listen on petname 0, on behalf of `sponsor`
+        let listen = core.reserve(&Quad::pair_t(ZERO, forgetful)).unwrap();
+        let msg = core.reserve(&Quad::pair_t(forgetful, listen)).unwrap();
+        let msg = core.reserve(&Quad::pair_t(UNDEF, msg)).unwrap();
+        let evt = core.reserve_event(sponsor, tcp_cap, msg).unwrap();
+        core.event_enqueue(evt);
+        assert_eq!(ZERO, core.run_loop(0));
+        assert_eq!(vec![sponsor], *log.borrow());  // the requester pays for the reply
+        assert_eq!(1, tcp_dev.borrow().listeners.capacity());
+
+        // the `stop` capability was forgotten, so the listener is closed
+        core.gc_collect_all();
+        assert!(!tcp_dev.borrow().pending());
+        assert!(tcp_dev.borrow().listeners.is_empty());
+
+        // its number is released once the helper thread is done
+        for _ in 0..5000 {
+            core.poll_devices().unwrap();
+            if tcp_dev.borrow().listeners.capacity() == 0 {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("timed out waiting for the listener to stop");
+    }
+
+    #[test]
+    fn tcp_dev_rejects_unknown_petname() {
+        let blob_dev = Rc::new(RefCell::new(BlobDevice::new()));
+        let mut dev = TcpDevice::new(vec![], blob_dev);
+        let mut core = Core::default();
+        core.init();
+        let msg = core.reserve(&Quad::pair_t(DEBUG_DEV, PLUS_1)).unwrap();
+        let msg = core.reserve(&Quad::pair_t(UNDEF, msg)).unwrap();
+        let evt = core.reserve_event(SPONSOR, DEBUG_DEV, msg).unwrap();
+        assert_eq!(E_BOUNDS, dev.handle_event(&mut core, evt).unwrap_err());
+    }
+
+}
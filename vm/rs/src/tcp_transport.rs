@@ -0,0 +1,326 @@
+// A TCP-based AWP transport using `std::net`.
+//
+// Addresses and bind_info are strings of the form "host:port".
+// Each frame is preceded by its length, as a 32-bit big-endian integer.
+//
+// The identity equals the name, and is sent as the first frame of each
+// connection. Names are asserted, not proven, so this transport should
+// only be used between trusting parties.
+//
+// Sockets block, so each connection is read and written by helper threads,
+// whose results wake the waker registered by an async host.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ::core::task::Waker;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use crate::*;
+use crate::awp_dev::{ConnId, ListenId, Transport, TransportEvent};
+use crate::completions::{Acceptor, Completions};
+use crate::oed::Value;
+use crate::slots::Slots;
+
+const READ_CHUNK_MAX: usize = 1<<12;
+
+struct Listener {
+    acceptor: Acceptor,
+    identity: Value,
+}
+
+struct Conn {
+    stream: Option<TcpStream>,  // `None` while connecting
+    writer: Option<Sender<Vec<u8>>>,  // queues output for the writer thread
+    listener: Option<ListenId>,  // accepted by a listener, or `None` if outgoing
+    expected: Option<Value>,  // name required of the remote party
+    name: Option<Value>,  // name asserted by the remote party
+    inbuf: Vec<u8>,
+    outbuf: Vec<u8>,  // output sent while connecting
+}
+
+// posted by helper threads
+enum Completion {
+    Connected(ConnId, io::Result<TcpStream>),
+    Accepted(ListenId, TcpStream),
+    Stopped(ListenId),  // no longer accepting
+    Read(ConnId, io::Result<Vec<u8>>),  // octets (none at end of stream)
+}
+
+#[derive(Default)]
+pub struct TcpTransport {
+    listeners: Slots<Listener>,
+    conns: Vec<Option<Conn>>,
+    events: VecDeque<TransportEvent>,
+    completions: Completions<Completion>,
+}
+
+fn address_string(address: &Value) -> Result<String, Error> {
+    match address {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(E_FAIL),
+    }
+}
+
+fn frame_into(out: &mut Vec<u8>, frame: &[u8]) {
+    out.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+    out.extend_from_slice(frame);
+}
+
+impl TcpTransport {
+    pub fn new() -> TcpTransport {
+        TcpTransport::default()
+    }
+
+    fn open(&mut self, conn: Conn) -> ConnId {
+        let id = self.conns.len();
+        self.conns.push(Some(conn));
+        id
+    }
+
+    fn fail(&mut self, id: ConnId) {
+        if let Some(conn) = self.conns[id].take() {
+            if let Some(stream) = conn.stream {
+                let _ = stream.shutdown(Shutdown::Both);  // stops the helper threads
+            }
+            self.events.push_back(TransportEvent::Closed(id));
+        }
+    }
+
+    fn start(&mut self, id: ConnId, stream: TcpStream) {
+        // read and write `stream` on helper threads
+        let (reader, mut writer) = match (stream.try_clone(), stream.try_clone()) {
+            (Ok(reader), Ok(writer)) => (reader, writer),
+            _ => return self.fail(id),
+        };
+        let conn = match &mut self.conns[id] {
+            Some(conn) => conn,
+            None => return,
+        };
+        let completions = self.completions.clone();
+        spawn_reader(id, reader, completions.clone());
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            // runs until the connection is closed
+            for output in rx {
+                if let Err(e) = writer.write_all(&output) {
+                    completions.post(Completion::Read(id, Err(e)));
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        let _ = tx.send(::core::mem::take(&mut conn.outbuf));
+        conn.stream = Some(stream);
+        conn.writer = Some(tx);
+    }
+
+    fn receive(&mut self, id: ConnId, data: &[u8]) {
+        let conn = match &mut self.conns[id] {
+            Some(conn) => conn,
+            None => return,
+        };
+        conn.inbuf.extend_from_slice(data);
+        while conn.inbuf.len() >= 4 {
+            let len = u32::from_be_bytes([conn.inbuf[0], conn.inbuf[1], conn.inbuf[2], conn.inbuf[3]]) as usize;
+            if conn.inbuf.len() < 4 + len {
+                break;
+            }
+            let frame: Vec<u8> = conn.inbuf.drain(..4 + len).skip(4).collect();
+            if conn.name.is_some() {
+                self.events.push_back(TransportEvent::Received(id, frame));
+                continue;
+            }
+            // the first frame names the remote party
+            let name = match Value::decode(&frame) {
+                Ok(name) => name,
+                Err(_) => return self.fail(id),
+            };
+            if conn.expected.as_ref().is_some_and(|expected| *expected != name) {
+                return self.fail(id);  // authentication failed
+            }
+            conn.name = Some(name);
+            self.events.push_back(match conn.listener {
+                Some(listener) => TransportEvent::Opened(listener, id),
+                None => TransportEvent::Connected(id),
+            });
+        }
+    }
+
+    fn complete(&mut self, completion: Completion) {
+        match completion {
+            Completion::Connected(id, Ok(stream)) => self.start(id, stream),
+            Completion::Connected(id, Err(_)) => self.fail(id),
+            Completion::Accepted(nr, stream) => {
+                let identity = match self.listeners.get(nr) {
+                    Some(listener) => listener.identity.encode(),
+                    None => return,  // stopped listening
+                };
+                let mut outbuf = Vec::new();
+                frame_into(&mut outbuf, &identity);
+                let id = self.open(Conn {
+                    stream: None,
+                    writer: None,
+                    listener: Some(nr),
+                    expected: None,
+                    name: None,
+                    inbuf: Vec::new(),
+                    outbuf,
+                });
+                self.start(id, stream);
+            },
+            Completion::Stopped(nr) => self.listeners.drop_proxy(nr),
+            Completion::Read(id, Ok(data)) if !data.is_empty() => self.receive(id, &data),
+            Completion::Read(id, _) => {
+                if self.conns.get(id).is_some_and(Option::is_some) {
+                    self.fail(id);  // end of stream, or failure
+                }
+            },
+        }
+    }
+}
+
+fn spawn_reader(id: ConnId, mut stream: TcpStream, completions: Completions<Completion>) {
+    // runs until end of stream, or failure
+    thread::spawn(move || loop {
+        let mut buf = vec![0_u8; READ_CHUNK_MAX];
+        let result = match stream.read(&mut buf) {
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            result => result,
+        };
+        let done = !matches!(result, Ok(n) if n > 0);
+        completions.post(Completion::Read(id, result.map(|n| {
+            buf.truncate(n);
+            buf
+        })));
+        if done {
+            break;
+        }
+    });
+}
+
+impl Transport for TcpTransport {
+    fn listen(&mut self, identity: &Value, bind_info: &Value) -> Result<ListenId, Error> {
+        let socket = TcpListener::bind(address_string(bind_info)?).map_err(|_| E_FAIL)?;
+        let nr = self.listeners.vacant();
+        let accepted = move |stream| Completion::Accepted(nr, stream);
+        let acceptor = Acceptor::spawn(socket, &self.completions, accepted, Completion::Stopped(nr))
+            .map_err(|_| E_FAIL)?;
+        self.listeners.insert(Listener { acceptor, identity: identity.clone() }, 1);  // named by the helper thread
+        Ok(nr)
+    }
+    fn stop(&mut self, listener: ListenId) {
+        if let Some(listener) = self.listeners.take(listener) {
+            listener.acceptor.stop();
+        }
+    }
+    fn connect(&mut self, identity: &Value, name: &Value, address: &Value) -> Result<ConnId, Error> {
+        // `std::net` can only connect synchronously, so wait on a helper thread
+        let address = address_string(address)?;
+        let mut outbuf = Vec::new();
+        frame_into(&mut outbuf, &identity.encode());
+        let id = self.open(Conn {
+            stream: None,
+            writer: None,
+            listener: None,
+            expected: Some(name.clone()),
+            name: None,
+            inbuf: Vec::new(),
+            outbuf,
+        });
+        let completions = self.completions.clone();
+        thread::spawn(move || {
+            completions.post(Completion::Connected(id, TcpStream::connect(address)));
+        });
+        Ok(id)
+    }
+    fn name(&self, conn: ConnId) -> Option<Value> {
+        match self.conns.get(conn) {
+            Some(Some(conn)) => conn.name.clone(),
+            _ => None,
+        }
+    }
+    fn send(&mut self, conn: ConnId, frame: &[u8]) {
+        if let Some(Some(conn)) = self.conns.get_mut(conn) {
+            let mut output = Vec::new();
+            frame_into(&mut output, frame);
+            match &conn.writer {
+                Some(writer) => {
+                    let _ = writer.send(output);
+                },
+                None => conn.outbuf.extend(output),  // still connecting
+            }
+        }
+    }
+    fn close(&mut self, conn: ConnId) {
+        // the writer thread sends what remains, then shuts down the stream
+        if let Some(conn) = self.conns.get_mut(conn) {
+            *conn = None;
+        }
+    }
+    fn poll(&mut self) -> Option<TransportEvent> {
+        while self.events.is_empty() {
+            let completion = self.completions.take()?;
+            self.complete(completion);
+        }
+        self.events.pop_front()
+    }
+    fn register_waker(&mut self, waker: &Waker) -> bool {
+        if self.events.is_empty() {
+            self.completions.register(waker);
+        } else {
+            waker.wake_by_ref();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::format;
+
+    use super::*;
+    use crate::awp_dev::tests::exchange_messages;
+
+    fn free_address() -> Value {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        Value::String(format!("127.0.0.1:{}", port))
+    }
+
+    #[test]
+    fn tcp_transport_rejects_wrong_name() {
+        let address = free_address();
+        let mut bob = TcpTransport::new();
+        let mut eve = TcpTransport::new();
+        let bob_name = Value::String(String::from("bob"));
+        let eve_name = Value::String(String::from("eve"));
+        eve.listen(&eve_name, &address).unwrap();
+        let conn = bob.connect(&bob_name, &bob_name, &address).unwrap();
+        for _ in 0..5000 {
+            let _ = eve.poll();
+            if let Some(event) = bob.poll() {
+                assert_eq!(TransportEvent::Closed(conn), event);
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn awp_dev_tcp_transport() {
+        exchange_messages(
+            Box::new(TcpTransport::new()),
+            Box::new(TcpTransport::new()),
+            free_address(),
+        );
+    }
+
+}
@@ -0,0 +1,309 @@
+// Structured traces of actor transactions (see `Core::set_trace_fn`).
+//
+// Each transaction is decoded into a `TxnRecord` when it concludes,
+// which may be written as JSON Lines, or in the Chrome `trace_event` format
+// (viewable in `chrome://tracing` or Perfetto). Times are measured in
+// cycles, counted across all transactions since tracing started.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use ::core::fmt::Write;
+
+use crate::*;
+
+pub type TraceFn = Box<dyn FnMut(&TxnRecord)>;  // called as each transaction concludes
+
+// rendered values are abbreviated beyond these bounds
+const RENDER_DEPTH: usize = 4;
+const RENDER_ITEMS: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TxnOutcome {
+    Commit,
+    Abort { reason: String },  // by `end abort`
+    Failed { error: Error },  // by an error (the event may be retried)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sent {
+    pub target: Any,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxnRecord {
+    pub id: u64,  // transactions are numbered as they begin
+    pub event: Any,
+    pub provenance: Option<Provenance>,  // with causality tracking on
+    pub sponsor: Any,
+    pub target: Any,
+    pub device: bool,  // handled by a device, rather than an actor
+    pub message: String,
+    pub sent: Vec<Sent>,  // in the order sent
+    pub created: Vec<Any>,
+    pub became: Option<(Any, String)>,  // new code and data
+    pub cycles: u64,
+    pub start: u64,
+    pub end: u64,
+    pub outcome: TxnOutcome,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    JsonLines,
+    Chrome,
+}
+
+// a transaction in progress
+struct TxnTrace {
+    id: u64,
+    start: u64,
+    cycles: u64,
+    created: Vec<Any>,
+}
+
+// the tracing state of a Core
+pub(crate) struct Tracer {
+    trace_fn: Option<TraceFn>,
+    txns: BTreeMap<usize, TxnTrace>,  // event offset -> transaction in progress
+    clock: u64,  // cycles counted since tracing started
+    next: u64,  // id of the next transaction traced
+}
+
+impl Tracer {
+    pub(crate) const fn new() -> Self {
+        Tracer { trace_fn: None, txns: BTreeMap::new(), clock: 0, next: 0 }
+    }
+    pub(crate) fn start(&mut self, trace_fn: TraceFn) {
+        self.trace_fn = Some(trace_fn);
+        self.txns.clear();
+        self.clock = 0;
+        self.next = 0;
+    }
+    pub(crate) fn stop(&mut self) {
+        self.trace_fn = None;
+        self.txns.clear();
+    }
+    pub(crate) fn begin(&mut self, ep: Any) {
+        if self.trace_fn.is_some() {
+            let txn = TxnTrace { id: self.next, start: self.clock, cycles: 0, created: Vec::new() };
+            self.txns.insert(ep.ofs(), txn);
+            self.next += 1;
+        }
+    }
+    pub(crate) fn is_traced(&self, ep: Any) -> bool {
+        self.txns.contains_key(&ep.ofs())
+    }
+    pub(crate) fn cycles(&mut self, ep: Any, cost: u64) {
+        if self.trace_fn.is_some() {
+            self.clock += cost;
+            if let Some(txn) = self.txns.get_mut(&ep.ofs()) {
+                txn.cycles += cost;
+            }
+        }
+    }
+    pub(crate) fn created(&mut self, ep: Any, actor: Any) {
+        if let Some(txn) = self.txns.get_mut(&ep.ofs()) {
+            txn.created.push(actor);
+        }
+    }
+    pub(crate) fn end(&mut self, mut record: TxnRecord) {
+        // complete `record` (filled in by the Core) with the transaction's times,
+        // and pass it to the trace function
+        let Some(txn) = self.txns.remove(&record.event.ofs()) else {
+            return;  // not traced
+        };
+        record.id = txn.id;
+        record.created = txn.created;
+        record.cycles = txn.cycles;
+        record.start = txn.start;
+        record.end = self.clock;
+        if let Some(trace) = &mut self.trace_fn {
+            (trace)(&record);
+        }
+    }
+}
+
+pub fn writer<W: FnMut(&str)>(format: TraceFormat, mut write: W) -> impl FnMut(&TxnRecord) {
+    // a trace function, writing each record (as a line of text) to `write`.
+    // the Chrome format is a JSON array, left open since the trace may be cut short.
+    if format == TraceFormat::Chrome {
+        write("[\n");
+    }
+    move |record| {
+        let mut line = match format {
+            TraceFormat::JsonLines => record.to_json(),
+            TraceFormat::Chrome => record.to_trace_event() + ",",
+        };
+        line.push('\n');
+        write(&line);
+    }
+}
+
+impl TxnRecord {
+    pub fn to_json(&self) -> String {
+        // a single-line JSON object
+        let mut out = format!("{{\"id\":{},\"event\":", self.id);
+        json_string(&mut out, &print(self.event));
+        if let Some(provenance) = self.provenance {
+            let _ = write!(out, ",\"event_id\":{}", provenance.id);
+            if let Some(cause) = provenance.cause {
+                let _ = write!(out, ",\"cause\":{}", cause);
+            }
+        }
+        out.push_str(",\"sponsor\":");
+        json_string(&mut out, &print(self.sponsor));
+        out.push_str(",\"target\":");
+        json_string(&mut out, &print(self.target));
+        if self.device {
+            out.push_str(",\"device\":true");
+        }
+        out.push_str(",\"message\":");
+        json_string(&mut out, &self.message);
+        out.push_str(",\"sent\":[");
+        for (n, sent) in self.sent.iter().enumerate() {
+            if n > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"target\":");
+            json_string(&mut out, &print(sent.target));
+            out.push_str(",\"message\":");
+            json_string(&mut out, &sent.message);
+            out.push('}');
+        }
+        out.push_str("],\"created\":[");
+        for (n, &actor) in self.created.iter().enumerate() {
+            if n > 0 {
+                out.push(',');
+            }
+            json_string(&mut out, &print(actor));
+        }
+        out.push(']');
+        if let Some((code, data)) = &self.became {
+            out.push_str(",\"become\":{\"code\":");
+            json_string(&mut out, &print(*code));
+            out.push_str(",\"data\":");
+            json_string(&mut out, data);
+            out.push('}');
+        }
+        let _ = write!(out, ",\"cycles\":{},\"start\":{},\"end\":{}", self.cycles, self.start, self.end);
+        match &self.outcome {
+            TxnOutcome::Commit => out.push_str(",\"outcome\":\"commit\""),
+            TxnOutcome::Abort { reason } => {
+                out.push_str(",\"outcome\":\"abort\",\"reason\":");
+                json_string(&mut out, reason);
+            },
+            TxnOutcome::Failed { error } => {
+                let _ = write!(out, ",\"outcome\":\"failed\",\"error\":{}", error);
+            },
+        }
+        out.push('}');
+        out
+    }
+    pub fn to_trace_event(&self) -> String {
+        // a "complete" event, on a track for each target
+        let mut out = String::from("{\"name\":");
+        json_string(&mut out, &format!("{} {}", print(self.target), self.message));
+        let category = if self.device { "device" } else { "actor" };
+        let _ = write!(out, ",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":{},\"args\":{}}}",
+            category, self.start, self.end - self.start, self.target.ofs(), self.to_json());
+        out
+    }
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub fn print(value: Any) -> String {
+    // a scalar value, as printed by `ufork.js`
+    const ROM_LABELS: [&str; 16] = [
+        "#?", "#nil", "#f", "#t", "EMPTY_DQ", "#type_t", "#fixnum_t", "#sponsor_t",
+        "#actor_t", "PROXY_T", "STUB_T", "#instr_t", "#pair_t", "#dict_t", "FWD_REF_T", "FREE_T",
+    ];
+    if let Some(n) = value.fix_num() {
+        return if n < 0 { format!("{}", n) } else { format!("+{}", n) };
+    }
+    if let Some(label) = ROM_LABELS.get(value.raw() as usize) {
+        return String::from(*label);
+    }
+    let prefix = if value.is_cap() { '@' } else { '^' };
+    format!("{}{:08x}", prefix, value.raw())
+}
+
+pub fn render(core: &Core, value: Any) -> String {
+    // a value, with lists shown as `(a b . c)` and dictionaries as `{k: v}`
+    let mut out = String::new();
+    render_into(core, &mut out, value, RENDER_DEPTH);
+    out
+}
+
+fn render_into(core: &Core, out: &mut String, value: Any, depth: usize) {
+    if !navigable(core, value) {
+        out.push_str(&print(value));
+        return;
+    }
+    let quad = *core.mem(value);
+    let (open, close) = match quad.t() {
+        PAIR_T => ('(', ')'),
+        DICT_T => ('{', '}'),
+        _ => {
+            out.push_str(&print(value));
+            return;
+        },
+    };
+    if depth == 0 {
+        out.push(open);
+        out.push_str("...");
+        out.push(close);
+        return;
+    }
+    out.push(open);
+    let mut p = value;
+    let mut n = 0;
+    while navigable(core, p) && (core.mem(p).t() == quad.t()) {
+        if n == RENDER_ITEMS {
+            out.push_str(" ...");
+            p = NIL;
+            break;
+        }
+        if n > 0 {
+            out.push_str(if quad.t() == DICT_T { ", " } else { " " });
+        }
+        let item = *core.mem(p);
+        render_into(core, out, item.x(), depth - 1);
+        if quad.t() == DICT_T {
+            out.push_str(": ");
+            render_into(core, out, item.y(), depth - 1);
+            p = item.z();
+        } else {
+            p = item.y();
+        }
+        n += 1;
+    }
+    if (quad.t() == PAIR_T) && (p != NIL) {
+        out.push_str(" . ");
+        render_into(core, out, p, depth - 1);
+    }
+    out.push(close);
+}
+
+fn navigable(core: &Core, value: Any) -> bool {
+    value.is_ptr() && !value.is_cap() && (value.is_rom() || core.in_heap(value))
+}
@@ -50,6 +50,7 @@ impl Device for TimerDevice {
                 let message = core.nth(msg, MINUS_2);
                 let delayed = Quad::new_event(sponsor, target, message);
                 let ptr = core.reserve(&delayed)?;
+                core.set_event_cause(ptr, ep)?;
                 let stub = core.reserve_stub(dev, ptr)?;
                 unsafe {
                     host_start_timer(delay.raw(), stub.raw());
@@ -68,6 +69,7 @@ impl Device for TimerDevice {
                 let result = core.nth(msg, MINUS_3);
                 let delayed = Quad::new_event(sponsor, callback, result);
                 let ptr = core.reserve(&delayed)?;
+                core.set_event_cause(ptr, ep)?;
                 let stub = core.reserve_stub(dev, ptr)?;
                 unsafe {
                     host_start_timer(delay.raw(), stub.raw());